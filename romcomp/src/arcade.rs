@@ -0,0 +1,162 @@
+use crossbeam_channel::Receiver;
+use log::debug;
+use std::{fs::File, io, path::Path};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+/// the archive comment of the romsets recompressed by romcomp, so they don't get recompressed again
+const COMMENT: &str = "romcomp arcade";
+
+/// the deflate level of the recompressed entries, levels above 9 use zopfli with (level - 9) iterations
+const ZOPFLI_LEVEL: i64 = 24;
+
+/// whether the romset was recompressed by romcomp before
+pub fn is_recompressed(path: &Path) -> bool {
+    File::open(path)
+        .ok()
+        .and_then(|f| ZipArchive::new(f).ok())
+        .is_some_and(|zip| zip.comment() == COMMENT.as_bytes())
+}
+
+/// the name and CRC-32 of every entry of a zip file, in the order they are stored
+fn entries(path: &Path) -> Result<Vec<(String, u32)>, String> {
+    let mut zip =
+        ZipArchive::new(File::open(path).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+
+    (0..zip.len())
+        .map(|i| {
+            zip.by_index_raw(i)
+                .map(|e| (e.name().to_string(), e.crc32()))
+                .map_err(|e| e.to_string())
+        })
+        .collect()
+}
+
+/// recompresses every entry of a romset zip with zopfli. the names, CRCs, timestamps and order of the entries
+/// are kept, since MAME identifies the roms of a set by them. returns whether the conversion got interrupted
+pub fn recompress_set(
+    input: &Path,
+    output: &Path,
+    interrupt: &Receiver<()>,
+) -> Result<bool, String> {
+    let mut source = ZipArchive::new(File::open(input).map_err(|e| e.to_string())?)
+        .map_err(|e| e.to_string())?;
+    let mut zip = ZipWriter::new(File::create(output).map_err(|e| e.to_string())?);
+
+    for i in 0..source.len() {
+        if !interrupt.is_empty() {
+            return Ok(true);
+        }
+
+        let mut entry = source.by_index(i).map_err(|e| e.to_string())?;
+        let mut options = SimpleFileOptions::default();
+
+        if let Some(time) = entry.last_modified() {
+            options = options.last_modified_time(time);
+        }
+
+        if let Some(mode) = entry.unix_mode() {
+            options = options.unix_permissions(mode);
+        }
+
+        if entry.is_dir() {
+            zip.add_directory(entry.name(), options)
+                .map_err(|e| e.to_string())?;
+            continue;
+        }
+
+        debug!("Recompressing {} of {}", entry.name(), input.display());
+
+        zip.start_file(
+            entry.name(),
+            options
+                .compression_method(CompressionMethod::Deflated)
+                .compression_level(Some(ZOPFLI_LEVEL))
+                .large_file(entry.size() > u32::MAX as u64),
+        )
+        .map_err(|e| e.to_string())?;
+
+        // the zip crate checks the CRC of the original entry once it was read completely
+        io::copy(&mut entry, &mut zip).map_err(|e| format!("{}: {}", entry.name(), e))?;
+    }
+
+    zip.set_comment(COMMENT);
+    zip.finish().map_err(|e| e.to_string())?;
+
+    if entries(input)? != entries(output)? {
+        return Err("the entries of the recompressed set don't match the original".to_string());
+    }
+
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam_channel::unbounded;
+    use std::io::{Read, Write};
+
+    /// a romset as MAME expects it, the roms are stored uncompressed
+    fn romset(path: &Path) -> Vec<(String, Vec<u8>)> {
+        let roms = vec![
+            ("sp-s2.sp1".to_string(), b"BIOS".repeat(64)),
+            (
+                "roms/p1.p1".to_string(),
+                (0..512).map(|i| i as u8).collect(),
+            ),
+            ("empty.bin".to_string(), vec![]),
+        ];
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+        zip.add_directory("roms/", options).unwrap();
+        for (name, contents) in roms.iter() {
+            zip.start_file(name, options).unwrap();
+            zip.write_all(contents).unwrap();
+        }
+        zip.finish().unwrap();
+
+        roms
+    }
+
+    #[test]
+    fn recompressed_sets_keep_their_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("neogeo.zip");
+        let output = dir.path().join("neogeo.romcomp.zip");
+        let roms = romset(&input);
+        let (_sender, interrupt) = unbounded();
+
+        assert!(!is_recompressed(&input));
+        assert_eq!(recompress_set(&input, &output, &interrupt), Ok(false));
+        assert!(is_recompressed(&output));
+
+        // the same names and CRCs in the same order
+        assert_eq!(entries(&input).unwrap(), entries(&output).unwrap());
+
+        let mut zip = ZipArchive::new(File::open(&output).unwrap()).unwrap();
+        for (name, contents) in roms {
+            let mut entry = zip.by_name(&name).unwrap();
+            let mut read = vec![];
+            entry.read_to_end(&mut read).unwrap();
+
+            assert_eq!(read, contents, "{}", name);
+            assert_eq!(entry.compression(), CompressionMethod::Deflated);
+        }
+    }
+
+    #[test]
+    fn corrupt_sets_are_not_recompressed() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("neogeo.zip");
+        romset(&input);
+
+        // damage the stored data of the first rom, its CRC doesn't match anymore
+        let mut zip = std::fs::read(&input).unwrap();
+        let offset = zip.windows(4).position(|w| w == b"BIOS").unwrap();
+        zip[offset] = b'X';
+        std::fs::write(&input, zip).unwrap();
+        let (_sender, interrupt) = unbounded();
+
+        assert!(recompress_set(&input, &dir.path().join("out.zip"), &interrupt).is_err());
+    }
+}
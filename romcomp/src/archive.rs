@@ -0,0 +1,201 @@
+use crate::{
+    rom_format::RomFormat,
+    search::{converted_twin, guess_file, has_magic},
+};
+use crossbeam_channel::Receiver;
+use sevenz_rust::{Password, SevenZReader};
+use std::{
+    fs::{create_dir_all, File},
+    io::{self, Read, Write},
+    path::{Component, Path, PathBuf},
+    time::SystemTime,
+};
+use zip::ZipArchive;
+
+const ZIP_MAGIC: &[u8; 4] = b"PK\x03\x04";
+const SEVEN_ZIP_MAGIC: &[u8; 6] = b"7z\xBC\xAF\x27\x1C";
+
+/// whether a file is a zip or 7z archive which might hold a rom, see --extract-archives
+pub fn is_archive(path: &Path) -> bool {
+    match path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase()
+        .as_str()
+    {
+        "zip" => has_magic(path, 0, ZIP_MAGIC),
+        "7z" => has_magic(path, 0, SEVEN_ZIP_MAGIC),
+        _ => false,
+    }
+}
+
+/// extracts every file of a zip or 7z archive below the directory, keeping the directories inside the archive.
+/// the extracted files get the modification time of the archive, so --update compares the archive with the output.
+/// returns whether the extraction got interrupted
+pub fn extract_archive(
+    archive: &Path,
+    dir: &Path,
+    interrupt: &Receiver<()>,
+) -> Result<bool, String> {
+    let modified = archive
+        .metadata()
+        .and_then(|m| m.modified())
+        .map_err(|e| e.to_string())?;
+
+    if has_magic(archive, 0, ZIP_MAGIC) {
+        extract_zip(archive, dir, modified, interrupt)
+    } else {
+        extract_7z(archive, dir, modified, interrupt)
+    }
+}
+
+fn extract_zip(
+    archive: &Path,
+    dir: &Path,
+    modified: SystemTime,
+    interrupt: &Receiver<()>,
+) -> Result<bool, String> {
+    let mut zip = ZipArchive::new(File::open(archive).map_err(|e| e.to_string())?)
+        .map_err(|e| e.to_string())?;
+
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i).map_err(|e| e.to_string())?;
+
+        if entry.is_dir() {
+            continue;
+        }
+
+        let name = entry
+            .enclosed_name()
+            .ok_or_else(|| format!("{} isn't a valid file name", entry.name()))?;
+
+        // the zip crate checks the CRC of the entry once it was read completely
+        if write_entry(&mut entry, &dir.join(name), modified, interrupt)
+            .map_err(|e| e.to_string())?
+        {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+fn extract_7z(
+    archive: &Path,
+    dir: &Path,
+    modified: SystemTime,
+    interrupt: &Receiver<()>,
+) -> Result<bool, String> {
+    let mut reader = SevenZReader::open(archive, Password::empty()).map_err(|e| e.to_string())?;
+    let mut interrupted = false;
+    let mut failure = None;
+
+    reader
+        .for_each_entries(|entry, data| {
+            // stopping only ends the current block of a solid archive, the following ones are passed by
+            if interrupted || failure.is_some() {
+                return Ok(false);
+            }
+
+            if entry.is_directory() {
+                return Ok(true);
+            }
+
+            // unlike the zip crate, sevenz-rust hands out the names as they are stored
+            let Some(name) = enclosed_name(entry.name()) else {
+                failure = Some(format!("{} isn't a valid file name", entry.name()));
+                return Ok(false);
+            };
+
+            match write_entry(data, &dir.join(name), modified, interrupt) {
+                Ok(i) => interrupted = i,
+                Err(e) => failure = Some(e.to_string()),
+            }
+
+            Ok(!interrupted && failure.is_none())
+        })
+        .map_err(|e| e.to_string())?;
+
+    match failure {
+        Some(e) => Err(e),
+        None => Ok(interrupted),
+    }
+}
+
+/// the name of an entry as a relative path which can't leave the directory it gets extracted into
+fn enclosed_name(name: &str) -> Option<PathBuf> {
+    let path = Path::new(name);
+
+    path.components()
+        .all(|c| matches!(c, Component::Normal(_)))
+        .then(|| path.to_path_buf())
+}
+
+/// copies an entry into its file, returns whether the copy got interrupted
+fn write_entry(
+    reader: &mut dyn Read,
+    target: &Path,
+    modified: SystemTime,
+    interrupt: &Receiver<()>,
+) -> io::Result<bool> {
+    create_dir_all(target.parent().unwrap())?;
+
+    let mut writer = File::create(target)?;
+    let mut buffer = vec![0; 1024 * 1024];
+
+    loop {
+        if !interrupt.is_empty() {
+            return Ok(true);
+        }
+
+        match reader.read(&mut buffer)? {
+            0 => break,
+            n => writer.write_all(&buffer[..n])?,
+        }
+    }
+
+    writer.set_modified(modified)?;
+
+    Ok(false)
+}
+
+/// the rom inside an extracted archive, the cue sheet or gdi file for discs made of several files.
+/// archives holding several roms or none at all aren't converted
+pub fn archived_rom(dir: &Path) -> Result<PathBuf, String> {
+    let mut files = vec![];
+    list_files(dir, &mut files).map_err(|e| e.to_string())?;
+    files.sort();
+
+    let roms = files
+        .iter()
+        .filter(|f| {
+            // a zip inside the archive would only be a romset, and game.cue.txt describes the same disc as game.cue
+            guess_file(f).is_some_and(|g| !g.contains(RomFormat::Arcade))
+                && converted_twin(f).is_none()
+        })
+        .collect::<Vec<_>>();
+
+    match roms.as_slice() {
+        [rom] => Ok(rom.to_path_buf()),
+        [] => Err("The archive doesn't hold a rom".to_string()),
+        roms => Err(format!(
+            "The archive holds {} roms, only archives with a single rom get converted",
+            roms.len()
+        )),
+    }
+}
+
+fn list_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in dir.read_dir()? {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            list_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,133 @@
+use crate::rom_format::{CompressionOptions, CompressionTool};
+use crossbeam_channel::Receiver;
+use cue::{cd::CD, track::TrackMode};
+use lazy_regex::regex_captures;
+use log::debug;
+use std::{
+    collections::HashSet,
+    fs::{read_to_string, File},
+    io,
+    path::{Path, PathBuf},
+};
+use tempfile::Builder;
+use zip::{CompressionMethod, ZipWriter};
+
+/// returns the files referenced by a cue sheet, together with whether they only contain audio tracks
+fn cue_files(cue: &Path) -> Option<Vec<(String, bool)>> {
+    let cd = CD::parse_file(cue.to_path_buf()).ok()?;
+    let mut files: Vec<(String, bool)> = vec![];
+
+    for track in cd.tracks() {
+        let audio = matches!(track.get_mode(), TrackMode::Audio);
+
+        match files.iter_mut().find(|(f, _)| *f == track.get_filename()) {
+            Some((_, a)) => *a = *a && audio,
+            None => files.push((track.get_filename(), audio)),
+        }
+    }
+
+    Some(files)
+}
+
+/// checks whether a cue sheet references files which only contain audio tracks
+pub fn has_audio_files(cue: &Path) -> bool {
+    cue_files(cue).is_some_and(|files| files.iter().any(|(_, audio)| *audio))
+}
+
+/// archives a bin / cue disc image into a zip file.
+/// files containing only audio tracks get compressed with flac, data tracks are kept as they are
+/// and the cue sheet is updated to reference the flac files.
+/// returns whether the archiving got interrupted
+pub fn archive_audio_cd(
+    cue: &Path,
+    output: &Path,
+    temp_dir: &Path,
+    options: &CompressionOptions,
+    interrupt: &Receiver<()>,
+) -> Result<bool, String> {
+    let files = cue_files(cue).ok_or_else(|| format!("Couldn't parse {}", cue.display()))?;
+    let parent = cue.parent().unwrap();
+    let work_dir = Builder::new()
+        .tempdir_in(temp_dir)
+        .map_err(|e| e.to_string())?;
+
+    let mut audio_files: HashSet<String> = HashSet::new();
+    let mut entries: Vec<(String, PathBuf, CompressionMethod)> = vec![];
+
+    for (i, (name, audio)) in files.iter().enumerate() {
+        let source = parent.join(name);
+
+        if !audio {
+            entries.push((name.clone(), source, CompressionMethod::Deflated));
+            continue;
+        }
+
+        let flac_name = Path::new(name)
+            .with_extension("flac")
+            .to_str()
+            .unwrap()
+            .to_string();
+        let flac = work_dir.path().join(format!("{}.flac", i));
+
+        debug!("Compressing {} with flac", source.display());
+
+        let tool = CompressionTool::Flac;
+
+        match tool.run(
+            &options.binary(tool),
+            &tool.arguments(&source, &flac, options),
+            interrupt,
+        ) {
+            Ok(false) => (),
+            Ok(true) => return Ok(true),
+            Err(e) => return Err(format!("Couldn't compress {}: {}", source.display(), e)),
+        }
+
+        audio_files.insert(name.clone());
+        entries.push((flac_name, flac, CompressionMethod::Stored));
+    }
+
+    let sheet = read_to_string(cue)
+        .map_err(|e| e.to_string())?
+        .lines()
+        .map(
+            |line| match regex_captures!(r#"^(\s*FILE\s+)"?(.+?)"?\s+\w+\s*$"#i, line) {
+                Some((_, prefix, name)) if audio_files.contains(name) => format!(
+                    "{}\"{}\" WAVE",
+                    prefix,
+                    Path::new(name).with_extension("flac").to_str().unwrap()
+                ),
+                _ => line.to_string(),
+            },
+        )
+        .collect::<Vec<_>>()
+        .join("\r\n")
+        + "\r\n";
+
+    let mut zip = ZipWriter::new(File::create(output).map_err(|e| e.to_string())?);
+
+    zip.start_file(
+        cue.file_name().unwrap().to_str().unwrap(),
+        options.zip_entry(CompressionMethod::Deflated),
+    )
+    .map_err(|e| e.to_string())?;
+    io::Write::write_all(&mut zip, sheet.as_bytes()).map_err(|e| e.to_string())?;
+
+    for (name, path, method) in entries.iter() {
+        if !interrupt.is_empty() {
+            return Ok(true);
+        }
+
+        debug!("Adding {} to {}", path.display(), output.display());
+
+        zip.start_file(name.as_str(), options.zip_entry(*method))
+            .map_err(|e| e.to_string())?;
+
+        io::copy(&mut File::open(path).map_err(|e| e.to_string())?, &mut zip)
+            .map_err(|e| e.to_string())?;
+    }
+
+    zip.finish().map_err(|e| e.to_string())?;
+
+    Ok(false)
+}
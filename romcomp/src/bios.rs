@@ -0,0 +1,175 @@
+use cue::cd::CD;
+use flate2::Crc;
+use globset::{Error, GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::Deserialize;
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+/// a BIOS or firmware dump, identified by its size and CRC32
+#[derive(Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KnownBios {
+    pub size: u64,
+    pub crc32: u32,
+}
+
+/// PlayStation BIOS dumps, which are commonly stored as .bin files next to the games
+const KNOWN_BIOS: &[KnownBios] = &[
+    // SCPH-1000
+    KnownBios {
+        size: 524288,
+        crc32: 0x3b601fc8,
+    },
+    // SCPH-1001
+    KnownBios {
+        size: 524288,
+        crc32: 0x37157331,
+    },
+    // SCPH-5500
+    KnownBios {
+        size: 524288,
+        crc32: 0xff3eeb8c,
+    },
+    // SCPH-5501
+    KnownBios {
+        size: 524288,
+        crc32: 0x8d8cb7e4,
+    },
+    // SCPH-5502
+    KnownBios {
+        size: 524288,
+        crc32: 0xd786f0b9,
+    },
+    // SCPH-7001
+    KnownBios {
+        size: 524288,
+        crc32: 0x502224b6,
+    },
+];
+
+/// file names BIOS and firmware dumps are usually stored under. bios has to be a word of its own,
+/// games like BioShock or Symbiosis mustn't match. [BIOS] is the prefix of the No-Intro dumps
+const BIOS_PATTERNS: &[&str] = &[
+    "scph*",
+    "bios",
+    "bios.*",
+    "bios[ (_-]*",
+    "*[ (_-]bios[ )._-]*",
+    "*[ (_-]bios",
+    "[[]bios[]]*",
+    "64dd_ipl*",
+];
+
+/// recognizes BIOS and firmware files, which must never be converted or removed
+pub struct BiosDetector {
+    names: GlobSet,
+    known: Vec<KnownBios>,
+}
+
+impl BiosDetector {
+    /// builds a detector from the built-in list and the given additional names and dumps
+    pub fn new(names: &[String], known: &[KnownBios]) -> Result<BiosDetector, Error> {
+        let mut builder = GlobSetBuilder::new();
+
+        for pattern in BIOS_PATTERNS
+            .iter()
+            .copied()
+            .chain(names.iter().map(|p| p.as_str()))
+        {
+            builder.add(GlobBuilder::new(pattern).case_insensitive(true).build()?);
+        }
+
+        Ok(BiosDetector {
+            names: builder.build()?,
+            known: KNOWN_BIOS.iter().chain(known.iter()).copied().collect(),
+        })
+    }
+
+    /// returns the BIOS file among the input file and the tracks of a cue sheet, if there is one
+    pub fn detect(&self, file: &Path) -> Option<PathBuf> {
+        let name = file.file_name()?.to_str()?.to_lowercase();
+        let mut candidates = vec![file.to_path_buf()];
+
+        if name.ends_with(".cue") || name.ends_with(".cue.txt") {
+            if let Ok(cue) = CD::parse_file(file.to_path_buf()) {
+                candidates.extend(
+                    cue.tracks()
+                        .iter()
+                        .map(|t| file.parent().unwrap().join(t.get_filename())),
+                );
+            }
+        }
+
+        candidates.into_iter().find(|c| self.is_bios(c))
+    }
+
+    fn is_bios(&self, file: &Path) -> bool {
+        if file.file_name().is_some_and(|n| self.names.is_match(n)) {
+            return true;
+        }
+
+        let Ok(size) = file.metadata().map(|m| m.len()) else {
+            return false;
+        };
+
+        // only files of a known size are worth checksumming
+        if !self.known.iter().any(|k| k.size == size) {
+            return false;
+        }
+
+        crc32_file(file)
+            .is_ok_and(|crc| self.known.iter().any(|k| k.size == size && k.crc32 == crc))
+    }
+}
+
+fn crc32_file(path: &Path) -> io::Result<u32> {
+    let mut fh = File::open(path)?;
+    let mut crc = Crc::new();
+    let mut buf = vec![0_u8; 1024 * 1024];
+
+    loop {
+        let read = fh.read(&mut buf)?;
+
+        if read == 0 {
+            break;
+        }
+
+        crc.update(&buf[..read]);
+    }
+
+    Ok(crc.sum())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bios_names_are_detected() {
+        let detector = BiosDetector::new(&[], &[]).unwrap();
+
+        for name in [
+            "scph1001.bin",
+            "bios.bin",
+            "gba_bios.bin",
+            "PS2 BIOS (USA).bin",
+            "[BIOS] PlayStation (Japan) (v1.0).bin",
+            "neogeo-bios",
+            "64dd_ipl.n64",
+        ] {
+            assert!(detector.is_bios(Path::new(name)), "{}", name);
+        }
+    }
+
+    #[test]
+    fn games_containing_bios_are_not_detected() {
+        let detector = BiosDetector::new(&[], &[]).unwrap();
+
+        for name in ["BioShock (USA).iso", "Symbiosis.cue", "Biosphere.iso"] {
+            assert!(!detector.is_bios(Path::new(name)), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use crate::{
+    bios::KnownBios,
+    rom_format::{
+        parse_chd_hunk_size, parse_version, CompressionOptions, CompressionTool, RomFormat,
+        RvzCodec, SourceRomFormat,
+    },
+};
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+/// settings read from the config file
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// candidate binaries for each tool, tried in order
+    pub tools: HashMap<CompressionTool, Vec<String>>,
+    /// additional file and directory name patterns to ignore
+    pub ignore: Vec<String>,
+    /// additional BIOS and firmware files which must never be converted
+    pub bios: BiosConfig,
+    /// the oldest version of each tool which may be used, e.g. chdman = "0.262", see --strict
+    pub min_versions: HashMap<CompressionTool, String>,
+}
+
+/// BIOS and firmware files, in addition to the built-in list
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BiosConfig {
+    /// file name patterns
+    pub names: Vec<String>,
+    /// dumps identified by size and CRC32
+    pub files: Vec<KnownBios>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config> {
+        let config: Config = toml::from_str(&read_to_string(path)?)?;
+
+        for (tool, version) in config.min_versions.iter() {
+            if parse_version(version).is_none() {
+                return Err(anyhow!(
+                    "the minimum version {} of {} isn't a version number",
+                    version,
+                    tool
+                ));
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// settings for a single rom, read from a <stem>.romcomp.toml file next to it
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sidecar {
+    /// the format to convert the rom into, instead of the selected or detected one
+    format: Option<SourceRomFormat>,
+    /// hunk size in bytes passed to chdman
+    chd_hunk_size: Option<u32>,
+    /// compression level passed to dolphin-tool
+    rvz_level: Option<u8>,
+    /// compression codec passed to dolphin-tool
+    rvz_codec: Option<RvzCodec>,
+}
+
+impl Sidecar {
+    /// the sidecar file belonging to a rom, e.g. game.romcomp.toml for game.cue or game.cue.txt
+    pub fn path(file: &Path) -> PathBuf {
+        let name = file.file_name().unwrap().to_str().unwrap();
+        let name = name.strip_suffix(".txt").unwrap_or(name);
+        let stem = Path::new(name).file_stem().unwrap().to_str().unwrap();
+
+        file.with_file_name(format!("{}.romcomp.toml", stem))
+    }
+
+    pub fn load(path: &Path) -> Result<Sidecar> {
+        let sidecar: Sidecar = toml::from_str(&read_to_string(path)?)?;
+
+        if let Some(hunk_size) = sidecar.chd_hunk_size {
+            parse_chd_hunk_size(&hunk_size.to_string()).map_err(|e| anyhow!(e))?;
+        }
+
+        if sidecar.format == Some(SourceRomFormat::Auto) {
+            return Err(anyhow!("format requires a specific format"));
+        }
+
+        Ok(sidecar)
+    }
+
+    /// the console flags of the overridden format
+    pub fn format(&self) -> Option<RomFormat> {
+        self.format.as_ref().and_then(|f| f.rom_format())
+    }
+
+    /// overrides the global options with the settings of this file
+    pub fn apply(&self, options: &mut CompressionOptions) {
+        if self.chd_hunk_size.is_some() {
+            options.chd_hunk_size = self.chd_hunk_size;
+        }
+
+        if self.rvz_level.is_some() {
+            options.rvz_level = self.rvz_level;
+        }
+
+        if self.rvz_codec.is_some() {
+            options.rvz_codec = self.rvz_codec;
+        }
+    }
+}
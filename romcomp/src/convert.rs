@@ -1,71 +1,604 @@
-use crate::rom_format::RomFormat;
-use crossbeam_channel::Receiver;
+use crate::{
+    arcade::{is_recompressed, recompress_set},
+    archive::extract_archive,
+    audio::{archive_audio_cd, has_audio_files},
+    config::Sidecar,
+    cso::{decompress_cso, DAX_MAGIC},
+    gdi::gdi_files,
+    hash::{hash_input, sha1_file},
+    http_status::HttpStatus,
+    jobs::Job,
+    n64::trimmed_length,
+    permissions::{apply_permissions, Owner},
+    progress::Progress,
+    reflink::reflink_or_copy,
+    report::{write_json_report, FileStatus, ReportEntry},
+    rom_format::{CompressionOptions, CompressionTool, RomFormat},
+    s3::S3Client,
+    search::{
+        guess_file, is_dvd_image, iso_looks_truncated, read_at, root_of, sector_mode_mismatches,
+        wbfs_parts,
+    },
+    state::{InputStamp, StateFile},
+    status::StatusFile,
+    tui::Tui,
+    verify::{verify_output, verify_replacement},
+    zst::{compress_zstd_seekable, DEFAULT_FRAME_SIZE},
+};
+use clap::ValueEnum;
+use crossbeam_channel::{Receiver, Sender};
 use cue::cd::CD;
 use filesize::PathExt;
 use humansize::{format_size, DECIMAL};
 use lazy_regex::regex_replace;
+use log::{debug, error, info, log, warn, Level};
 use std::{
-    fs::{copy, remove_dir, remove_file, rename, File},
-    io::{Read, Write},
+    collections::{BTreeMap, HashMap},
+    fmt,
+    fs::{copy, create_dir_all, hard_link, remove_dir, remove_dir_all, remove_file, rename, File},
+    io::{ErrorKind, Read, Write},
+    net::SocketAddr,
     path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicUsize, Ordering},
-        Arc,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
     },
-    time::Duration,
+    thread::JoinHandle,
+    time::{Duration, Instant},
 };
-use tempfile::TempDir;
-use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+use tempfile::{Builder, TempDir};
+use zip::ZipWriter;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 enum FileSource {
     /// input to romcomp, not created by us
     Input,
     /// temporary input file, created by romcomp
-    /// used e.g. with cue.txt files, which need to be copied into .cue files
+    /// used e.g. with cue.txt files, which need to be copied into .cue files, and the files extracted from archives
     TemporaryInput,
     /// temporary output files, created by romcomp
     /// used e.g. when trimming NDS files or converting N64 roms
     TemporaryOutput,
     /// the compression target, created during the conversion
     Output,
+    /// temporary directory of a single conversion, removed with everything in it
+    /// used e.g. when converting N64 roms, so their z64 can't clash with the files next to the input
+    TemporaryDirectory,
+}
+
+/// the reason why a file wasn't converted
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum SkipReason {
+    /// the output file already exists
+    TargetExists,
+    /// the input file failed the sanity checks
+    Truncated,
+    /// the arcade romset was recompressed by romcomp before
+    Recompressed,
+    /// the inputs are smaller than --min-size or larger than --max-size
+    SizeOutOfRange,
+    /// the image is a cso or dax image already, see --recompress
+    Compressed,
+    /// the archive doesn't hold a single rom which can be converted, see --extract-archives
+    NoRom,
+    /// the output saved less space than --min-savings demands, so it was deleted again
+    InsufficientSavings,
+    /// the input was converted by an earlier run with the same --state file
+    Completed,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SkipReason::TargetExists => write!(f, "Target file already exists"),
+            SkipReason::Truncated => write!(f, "Input file looks truncated"),
+            SkipReason::Recompressed => write!(f, "Romset was recompressed before"),
+            SkipReason::SizeOutOfRange => write!(f, "Input size is outside of the given range"),
+            SkipReason::Compressed => write!(f, "Image is compressed already"),
+            SkipReason::NoRom => write!(f, "Archive doesn't hold a rom which can be converted"),
+            SkipReason::InsufficientSavings => write!(f, "Compression saved too little space"),
+            SkipReason::Completed => write!(f, "Converted by an earlier run"),
+        }
+    }
+}
+
+/// limits the amount of I/O heavy operations running at the same time
+struct IoLimiter {
+    available: usize,
+    running: AtomicUsize,
+}
+
+impl IoLimiter {
+    /// waits until an operation may start, it may run as long as the returned permit is alive
+    fn acquire(self: &Arc<Self>) -> IoPermit {
+        while self
+            .running
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |r| {
+                (r < self.available).then_some(r + 1)
+            })
+            .is_err()
+        {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+
+        IoPermit(Arc::clone(self))
+    }
+}
+
+struct IoPermit(Arc<IoLimiter>);
+
+impl Drop for IoPermit {
+    fn drop(&mut self) {
+        self.0.running.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// a file being converted by a worker thread. once dropped, the file is no longer active and the thread
+/// no longer counts as running, even if the thread panicked, which also marks the file as failed
+struct ActiveConversion {
+    file: PathBuf,
+    active_files: Arc<Mutex<Vec<PathBuf>>>,
+    failed_files: Arc<Mutex<Vec<(PathBuf, String)>>>,
+    report_entries: Arc<Mutex<Vec<ReportEntry>>>,
+    thread_count: Arc<AtomicUsize>,
+    /// the size of the inputs, which counts against --io-budget while the conversion is running
+    size: u64,
+    running_size: Arc<AtomicU64>,
+    events: Option<Sender<ConversionEvent>>,
+}
+
+impl Drop for ActiveConversion {
+    fn drop(&mut self) {
+        // a panicking thread might have poisoned the lists, which are still consistent
+        if std::thread::panicking() {
+            error!(
+                "Failed compression of {}: the conversion crashed",
+                self.file.display()
+            );
+            self.failed_files
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push((self.file.clone(), "the conversion crashed".to_string()));
+            self.report_entries
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(ReportEntry::new(
+                    self.file.clone(),
+                    None,
+                    0,
+                    0,
+                    FileStatus::Failed,
+                    Some("the conversion crashed".to_string()),
+                ));
+            notify(
+                self.events.as_ref(),
+                ConversionEvent::Failed(self.file.clone(), "the conversion crashed".to_string()),
+            );
+        }
+
+        self.active_files
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|f| *f != self.file);
+        self.running_size.fetch_sub(self.size, Ordering::Relaxed);
+        self.thread_count.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// checks that the output exists, isn't empty and has been flushed to its storage device.
+/// the output is only opened for reading, it might be read-only already, e.g. because of --output-mode
+fn output_is_complete(path: &Path) -> bool {
+    File::open(path)
+        .and_then(|fh| {
+            fh.sync_all()?;
+            fh.metadata()
+        })
+        .is_ok_and(|m| m.is_file() && m.len() > 0)
+}
+
+/// the marker written next to an output while it is being created,
+/// an output with a marker is the incomplete leftover of an aborted run
+fn partial_marker(output: &Path) -> PathBuf {
+    append_extension(output, "romcomp-partial")
+}
+
+/// the file with another extension after its name, e.g. Game.zip becomes Game.zip.romcomp-arcade.
+/// names which aren't valid UTF-8 are kept as they are
+fn append_extension(file: &Path, extension: &str) -> PathBuf {
+    let mut name = file.as_os_str().to_os_string();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+/// replaces the original by its recompressed set or image once that passed the verification.
+/// the original is gone once it got replaced, so a replacement which fails is left to be cleaned up
+/// with the other outputs. returns whether the check got interrupted
+fn replace_verified(
+    output: &Path,
+    original: &Path,
+    options: &CompressionOptions,
+    interrupt: &Receiver<()>,
+) -> Result<bool, String> {
+    debug!("Verifying {}", output.display());
+
+    match verify_replacement(output, original, options, interrupt) {
+        Ok(true) => Ok(true),
+        Ok(false) => rename(output, original)
+            .map(|_| false)
+            .map_err(|e| format!("Failed to replace the original: {}", e)),
+        Err(e) => {
+            warn!(
+                "Keeping {}: {} failed the verification",
+                original.display(),
+                output.display()
+            );
+            Err(format!("The output failed the verification: {}", e))
+        }
+    }
+}
+
+/// the input with only its last extension replaced, whatever its casing, e.g. Game v1.1 (USA).ISO becomes Game v1.1 (USA).chd.
+/// images detected by their content can have any extension, a cue sheet saved as .cue.txt loses both
+fn replace_extension(file: &Path, extension: &str) -> PathBuf {
+    let is_cue_txt = file
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("txt"))
+        && file
+            .file_stem()
+            .and_then(|s| Path::new(s).extension())
+            .is_some_and(|e| e.eq_ignore_ascii_case("cue"));
+
+    if is_cue_txt {
+        file.with_extension("").with_extension(extension)
+    } else {
+        file.with_extension(extension)
+    }
+}
+
+/// deletes the output left behind by an aborted run along with its marker, a complete output is kept.
+/// returns whether there was an incomplete output
+fn discard_incomplete_output(output: &Path, dry_run: bool) -> bool {
+    let marker = partial_marker(output);
+
+    if !marker.is_file() {
+        return false;
+    }
+
+    if dry_run {
+        info!(
+            "Would delete incomplete output file {} of a previous run",
+            output.display()
+        );
+    } else {
+        debug!(
+            "Deleting incomplete output file {} of a previous run",
+            output.display()
+        );
+
+        let _ = remove_file(output);
+        let _ = remove_file(&marker);
+    }
+
+    true
+}
+
+/// the output with its extension in each casing --ext-case produces,
+/// so outputs of earlier runs are found on case-sensitive filesystems as well
+fn extension_cases(output: &Path) -> Vec<PathBuf> {
+    let mut cases = vec![output.to_path_buf()];
+
+    if let Some(extension) = output.extension().and_then(|e| e.to_str()) {
+        for case in [extension.to_lowercase(), extension.to_uppercase()] {
+            let path = output.with_extension(case);
+
+            if !cases.contains(&path) {
+                cases.push(path);
+            }
+        }
+    }
+
+    cases
+}
+
+/// the space saved by compressing the input size into the output size, along with the percentage saved.
+/// nothing might have been processed, and some files grow, e.g. ones which were compressed already
+pub fn savings(is: u64, os: u64) -> String {
+    let percentage = |sign: &str, difference: u64| {
+        if is > 0 {
+            format!(" ({}{:.2}%)", sign, difference as f64 * 100f64 / is as f64)
+        } else {
+            String::new()
+        }
+    };
+
+    if is >= os {
+        format!(
+            "Saved {}{}",
+            format_size(is - os, DECIMAL),
+            percentage("", is - os)
+        )
+    } else {
+        format!(
+            "Grew by {}{}",
+            format_size(os - is, DECIMAL),
+            percentage("-", os - is)
+        )
+    }
+}
+
+/// how the sizes of inputs and outputs are measured, both are always measured the same way
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum SizeMode {
+    /// the space allocated on disk, smaller than the length for sparse files or on compressing filesystems
+    #[default]
+    #[value(name = "ondisk")]
+    OnDisk,
+    /// the length of the file, the same on every filesystem
+    Logical,
+}
+
+impl SizeMode {
+    /// the size of a file, of the target of a symlink like the one followed by --follow-symlinks
+    pub fn of(self, file: &Path) -> std::io::Result<u64> {
+        let metadata = file.metadata()?;
+
+        match self {
+            SizeMode::OnDisk => file.size_on_disk_fast(&metadata),
+            SizeMode::Logical => Ok(metadata.len()),
+        }
+    }
+}
+
+/// checks whether a file was modified after another one
+fn is_newer(file: &Path, other: &Path) -> bool {
+    match (
+        file.metadata().and_then(|m| m.modified()),
+        other.metadata().and_then(|m| m.modified()),
+    ) {
+        (Ok(file), Ok(other)) => file > other,
+        _ => false,
+    }
+}
+
+/// copies all extended attributes of a file to another file
+fn copy_xattrs(from: &Path, to: &Path) -> std::io::Result<()> {
+    for name in xattr::list(from)? {
+        if let Some(value) = xattr::get(from, &name)? {
+            xattr::set(to, &name, &value)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// a successfully converted file
+#[derive(Clone, Debug)]
+pub struct ConversionRecord {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub format: RomFormat,
+    pub input_size: u64,
+    pub output_size: u64,
+    /// the tool which created the output, if there was a choice
+    pub tool: Option<CompressionTool>,
+}
+
+/// what happened to a file, sent to the channel given to Converter::events as soon as it happened
+#[derive(Clone, Debug)]
+pub enum ConversionEvent {
+    /// the conversion of the file started
+    Started(PathBuf),
+    Finished(ConversionRecord),
+    Skipped(PathBuf, SkipReason),
+    /// the file couldn't be converted, with the reason
+    Failed(PathBuf, String),
+    /// the conversion was interrupted, its output got deleted
+    Aborted(PathBuf),
+}
+
+/// sends the event if anyone listens, a receiver which went away doesn't stop the conversions
+fn notify(events: Option<&Sender<ConversionEvent>>, event: ConversionEvent) {
+    if let Some(events) = events {
+        let _ = events.send(event);
+    }
+}
+
+/// what to do with an output which is identical to another output
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum DuplicateAction {
+    /// only report the duplicate
+    Report,
+    /// replace the duplicate with a hardlink to the other output
+    Hardlink,
+    /// delete the duplicate
+    Remove,
+}
+
+/// the casing of the extension of the output files
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum ExtCase {
+    /// e.g. .chd
+    #[default]
+    Lower,
+    /// e.g. .CHD
+    Upper,
+    /// uppercase if the extension of the input is uppercase, lowercase otherwise
+    Preserve,
+}
+
+fn handle_duplicate_output(file: &PathBuf, existing: &PathBuf, action: DuplicateAction) {
+    info!(
+        "Duplicate output: {} is identical to {}",
+        file.display(),
+        existing.display()
+    );
+
+    match action {
+        DuplicateAction::Report => (),
+        DuplicateAction::Hardlink => {
+            debug!(
+                "Replacing {} with a hardlink to {}",
+                file.display(),
+                existing.display()
+            );
+
+            if let Err(e) = remove_file(file).and_then(|_| hard_link(existing, file)) {
+                warn!("Error creating hardlink {}: {:?}", file.display(), e);
+            }
+        }
+        DuplicateAction::Remove => {
+            debug!("Deleting duplicate output {}", file.display());
+
+            if let Err(e) = remove_file(file) {
+                warn!("Error deleting {}: {:?}", file.display(), e);
+            }
+        }
+    }
 }
 
 pub struct Converter {
     available_threads: usize,
+    /// how large the inputs of the conversions running at the same time may be in total, see --io-budget
+    io_budget: Option<u64>,
+    running_size: Arc<AtomicU64>,
     thread_count: Arc<AtomicUsize>,
-    skipped_files: Arc<AtomicUsize>,
+    skipped_files: Arc<Mutex<Vec<(PathBuf, SkipReason)>>>,
     processed_files: Arc<AtomicUsize>,
+    failed_files: Arc<Mutex<Vec<(PathBuf, String)>>>,
+    records: Arc<Mutex<Vec<ConversionRecord>>>,
+    report_entries: Arc<Mutex<Vec<ReportEntry>>>,
     input_file_size: Arc<AtomicUsize>,
     output_file_size: Arc<AtomicUsize>,
-    verbose: bool,
     remove_after_compression: bool,
     flatten: bool,
-    root_directory: PathBuf,
+    hash: bool,
+    sanity_checks: bool,
+    update: bool,
+    overwrite: bool,
+    preserve_xattrs: bool,
+    output_mode: Option<u32>,
+    output_owner: Option<Owner>,
+    trim: bool,
+    best_of: bool,
+    retries: usize,
+    audio_flac: bool,
+    zstd_seekable: bool,
+    explain: bool,
+    dry_run: bool,
+    tui: bool,
+    progress: bool,
+    http_status: Option<SocketAddr>,
+    s3: Option<S3Client>,
+    s3_remove_local: bool,
+    io_limiter: Option<Arc<IoLimiter>>,
+    reflink: bool,
+    ext_case: ExtCase,
+    output_root: Option<PathBuf>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    min_savings: Option<f64>,
+    size_mode: SizeMode,
+    recompress: bool,
+    fail_fast: bool,
+    compression_options: CompressionOptions,
+    dupe_action: Option<DuplicateAction>,
+    output_hashes: Arc<Mutex<HashMap<[u8; 20], PathBuf>>>,
+    status_file: Option<PathBuf>,
+    report: Option<PathBuf>,
+    state: Option<Arc<StateFile>>,
+    events: Option<Sender<ConversionEvent>>,
+    status_writer: Mutex<Option<(Arc<StatusFile>, JoinHandle<()>)>>,
+    tui_handle: Mutex<Option<JoinHandle<()>>>,
+    progress_handle: Mutex<Option<JoinHandle<()>>>,
+    http_handle: Mutex<Option<JoinHandle<()>>>,
+    running: Arc<AtomicBool>,
+    active_files: Arc<Mutex<Vec<PathBuf>>>,
+    /// the locations the files were found in
+    roots: Vec<PathBuf>,
+    /// aborts the running conversions
     interrupt: Receiver<()>,
+    /// keeps further conversions from starting, but lets the running ones finish
+    stop: Receiver<()>,
     temp_dir: Arc<TempDir>,
+    /// the directories below the temporary directory the archives were extracted into, with their archives
+    archives: Mutex<Vec<(PathBuf, PathBuf)>>,
 }
 
 impl Converter {
-    pub fn new(root: &PathBuf, temp_dir: TempDir, threads: usize, interrupt: Receiver<()>) -> Self {
+    pub fn new(
+        roots: &[PathBuf],
+        temp_dir: TempDir,
+        threads: usize,
+        interrupt: Receiver<()>,
+    ) -> Self {
         Self {
             available_threads: threads,
+            io_budget: None,
+            running_size: Arc::new(AtomicU64::new(0)),
             thread_count: Arc::new(AtomicUsize::new(0)),
-            skipped_files: Arc::new(AtomicUsize::new(0)),
+            skipped_files: Arc::new(Mutex::new(vec![])),
             processed_files: Arc::new(AtomicUsize::new(0)),
+            failed_files: Arc::new(Mutex::new(vec![])),
+            records: Arc::new(Mutex::new(vec![])),
+            report_entries: Arc::new(Mutex::new(vec![])),
             input_file_size: Arc::new(AtomicUsize::new(0)),
             output_file_size: Arc::new(AtomicUsize::new(0)),
-            verbose: false,
             remove_after_compression: false,
             flatten: false,
-            root_directory: root.clone(),
+            hash: false,
+            sanity_checks: true,
+            update: false,
+            overwrite: false,
+            preserve_xattrs: false,
+            output_mode: None,
+            output_owner: None,
+            trim: false,
+            best_of: false,
+            retries: 0,
+            audio_flac: false,
+            zstd_seekable: false,
+            explain: false,
+            dry_run: false,
+            tui: false,
+            progress: false,
+            http_status: None,
+            s3: None,
+            s3_remove_local: false,
+            io_limiter: None,
+            reflink: false,
+            ext_case: ExtCase::Lower,
+            output_root: None,
+            min_size: None,
+            max_size: None,
+            min_savings: None,
+            size_mode: SizeMode::OnDisk,
+            recompress: false,
+            fail_fast: false,
+            compression_options: CompressionOptions::default(),
+            dupe_action: None,
+            output_hashes: Arc::new(Mutex::new(HashMap::new())),
+            status_file: None,
+            report: None,
+            state: None,
+            events: None,
+            status_writer: Mutex::new(None),
+            tui_handle: Mutex::new(None),
+            progress_handle: Mutex::new(None),
+            http_handle: Mutex::new(None),
+            running: Arc::new(AtomicBool::new(false)),
+            active_files: Arc::new(Mutex::new(vec![])),
+            roots: roots.to_vec(),
+            stop: interrupt.clone(),
             interrupt,
             temp_dir: Arc::new(temp_dir),
+            archives: Mutex::new(vec![]),
         }
     }
 
-    pub fn verbose(mut self, verbose: bool) -> Self {
-        self.verbose = verbose;
+    /// a signal which ends the run more gently than the interrupt, the running conversions get finished
+    pub fn stop(mut self, stop: Receiver<()>) -> Self {
+        self.stop = stop;
         self
     }
 
@@ -79,262 +612,1552 @@ impl Converter {
         self
     }
 
-    pub fn get_output_file_name(file: &PathBuf, format: RomFormat) -> Option<PathBuf> {
-        if format.contains(RomFormat::PlayStationX) || format.contains(RomFormat::PlayStation2) {
-            Some(
-                Path::new(
-                    regex_replace!(r"iso|(cue(\.txt)?)$"i, file.to_str().unwrap(), "chd").as_ref(),
-                )
-                .to_path_buf(),
-            )
+    /// record the CRC32 and SHA-1 hashes of the input roms in the report, see --hash
+    pub fn hash(mut self, hash: bool) -> Self {
+        self.hash = hash;
+        self
+    }
+
+    pub fn sanity_checks(mut self, sanity_checks: bool) -> Self {
+        self.sanity_checks = sanity_checks;
+        self
+    }
+
+    /// recompress files whose output already exists, but is older than the input
+    pub fn update(mut self, update: bool) -> Self {
+        self.update = update;
+        self
+    }
+
+    /// recompress files whose output already exists, regardless of its age
+    pub fn overwrite(mut self, overwrite: bool) -> Self {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// copy the extended attributes of the input file to the output file
+    pub fn preserve_xattrs(mut self, preserve: bool) -> Self {
+        self.preserve_xattrs = preserve;
+        self
+    }
+
+    /// change the mode of every output file, e.g. 0o644
+    pub fn output_mode(mut self, mode: Option<u32>) -> Self {
+        self.output_mode = mode;
+        self
+    }
+
+    /// change the owner of every output file
+    pub fn output_owner(mut self, owner: Option<Owner>) -> Self {
+        self.output_owner = owner;
+        self
+    }
+
+    /// leave out the padding at the end of n64 roms when zipping them
+    pub fn trim(mut self, trim: bool) -> Self {
+        self.trim = trim;
+        self
+    }
+
+    /// also convert files with the alternative tools of their format and keep the smallest output
+    pub fn best_of(mut self, best_of: bool) -> Self {
+        self.best_of = best_of;
+        self
+    }
+
+    /// how often a failing tool gets run again before the conversion fails
+    pub fn retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// archive bin / cue images containing audio tracks into a zip file with flac compressed audio tracks,
+    /// instead of converting them to chd
+    pub fn audio_flac(mut self, audio_flac: bool) -> Self {
+        self.audio_flac = audio_flac;
+        self
+    }
+
+    /// wrap single file roms into the zstd seekable format instead of converting them
+    pub fn zstd_seekable(mut self, zstd_seekable: bool) -> Self {
+        self.zstd_seekable = zstd_seekable;
+        self
+    }
+
+    /// print the detected format, the selected tool, the output and the decision for every file before acting on it
+    pub fn explain(mut self, explain: bool) -> Self {
+        self.explain = explain;
+        self
+    }
+
+    /// only report the planned output and the decision for every file, without converting or deleting anything
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// show a full-screen view of the run instead of the line-based output
+    pub fn tui(mut self, tui: bool) -> Self {
+        self.tui = tui;
+        self
+    }
+
+    /// show progress bars on stderr while running
+    pub fn progress(mut self, progress: bool) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// serve /healthz and /metrics over HTTP on the given address while running
+    pub fn http_status(mut self, address: Option<SocketAddr>) -> Self {
+        self.http_status = address;
+        self
+    }
+
+    /// upload every output to S3-compatible storage once it is verified
+    pub fn s3(mut self, client: Option<S3Client>) -> Self {
+        self.s3 = client;
+        self
+    }
+
+    /// delete the local output once it was uploaded to S3
+    pub fn s3_remove_local(mut self, remove: bool) -> Self {
+        self.s3_remove_local = remove;
+        self
+    }
+
+    /// limit how many I/O heavy operations (temporary copies, zipping) may run at the same time,
+    /// independent of the amount of conversions running in parallel
+    pub fn io_concurrency(mut self, limit: Option<usize>) -> Self {
+        self.io_limiter = limit.map(|l| {
+            Arc::new(IoLimiter {
+                available: l,
+                running: AtomicUsize::new(0),
+            })
+        });
+        self
+    }
+
+    /// limit the conversions running at the same time by the total size of their inputs,
+    /// in addition to the amount of threads
+    pub fn io_budget(mut self, budget: Option<u64>) -> Self {
+        self.io_budget = budget;
+        self
+    }
+
+    /// clone files instead of copying them when creating temporary copies, if the filesystem supports it
+    pub fn reflink(mut self, reflink: bool) -> Self {
+        self.reflink = reflink;
+        self
+    }
+
+    /// the casing of the extension of the output files
+    pub fn ext_case(mut self, ext_case: ExtCase) -> Self {
+        self.ext_case = ext_case;
+        self
+    }
+
+    /// write the outputs below the given directory instead of next to their inputs,
+    /// keeping their path relative to the location they were found in
+    pub fn output_root(mut self, output_root: Option<PathBuf>) -> Self {
+        self.output_root = output_root;
+        self
+    }
+
+    /// skip files whose inputs are smaller or larger than the given sizes in bytes
+    pub fn size_range(mut self, min: Option<u64>, max: Option<u64>) -> Self {
+        self.min_size = min;
+        self.max_size = max;
+        self
+    }
+
+    /// delete outputs which saved less than the given percentage of the input size, keeping the inputs
+    pub fn min_savings(mut self, min_savings: Option<f64>) -> Self {
+        self.min_savings = min_savings;
+        self
+    }
+
+    /// whether the sizes of inputs and outputs are measured on disk or by their length
+    pub fn size_mode(mut self, mode: SizeMode) -> Self {
+        self.size_mode = mode;
+        self
+    }
+
+    /// the size of an input or output, as measured by --size-mode
+    pub fn file_size(&self, file: &Path) -> std::io::Result<u64> {
+        self.size_mode.of(file)
+    }
+
+    /// recompress cso and dax images instead of skipping them
+    pub fn recompress(mut self, recompress: bool) -> Self {
+        self.recompress = recompress;
+        self
+    }
+
+    pub fn fail_fast(mut self, fail_fast: bool) -> Self {
+        self.fail_fast = fail_fast;
+        self
+    }
+
+    pub fn compression_options(mut self, options: CompressionOptions) -> Self {
+        self.compression_options = options;
+        self
+    }
+
+    /// hash all outputs and handle outputs identical to already finished ones as given
+    pub fn detect_duplicate_outputs(mut self, action: Option<DuplicateAction>) -> Self {
+        self.dupe_action = action;
+        self
+    }
+
+    /// periodically write the progress to the given file
+    pub fn status_file(mut self, path: Option<PathBuf>) -> Self {
+        self.status_file = path;
+        self
+    }
+
+    /// write the outcome of every file and the totals as JSON to the given file once finished
+    pub fn report(mut self, path: Option<PathBuf>) -> Self {
+        self.report = path;
+        self
+    }
+
+    /// skip the inputs converted by earlier runs with the state file, and record every finished conversion in it
+    pub fn state(mut self, state: Option<StateFile>) -> Self {
+        self.state = state.map(Arc::new);
+        self
+    }
+
+    /// send what happens to every file to the given channel, e.g. to show the progress in a frontend
+    pub fn events(mut self, events: Sender<ConversionEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// must be called once before the first conversion
+    pub fn begin(&self, total_files: usize) {
+        self.running.store(true, Ordering::Relaxed);
+
+        if let Some(path) = self.status_file.as_ref() {
+            let status = Arc::new(StatusFile {
+                path: path.clone(),
+                total_files,
+                start: Instant::now(),
+                processed_files: Arc::clone(&self.processed_files),
+                failed_files: Arc::clone(&self.failed_files),
+                skipped_files: Arc::clone(&self.skipped_files),
+                input_file_size: Arc::clone(&self.input_file_size),
+                output_file_size: Arc::clone(&self.output_file_size),
+                active_files: Arc::clone(&self.active_files),
+            });
+            let s_ptr = Arc::clone(&status);
+            let running = Arc::clone(&self.running);
+
+            let handle = std::thread::spawn(move || {
+                while running.load(Ordering::Relaxed) {
+                    if let Err(e) = s_ptr.write(false) {
+                        debug!("Error writing status file: {:?}", e);
+                    }
+
+                    std::thread::sleep(Duration::from_secs(1));
+                }
+            });
+
+            *self.status_writer.lock().unwrap() = Some((status, handle));
+        }
+
+        if self.tui {
+            let tui = Tui {
+                total_files,
+                start: Instant::now(),
+                processed_files: Arc::clone(&self.processed_files),
+                failed_files: Arc::clone(&self.failed_files),
+                skipped_files: Arc::clone(&self.skipped_files),
+                input_file_size: Arc::clone(&self.input_file_size),
+                output_file_size: Arc::clone(&self.output_file_size),
+                active_files: Arc::clone(&self.active_files),
+                records: Arc::clone(&self.records),
+            };
+            let running = Arc::clone(&self.running);
+
+            let handle = std::thread::spawn(move || {
+                if let Err(e) = tui.run(&running) {
+                    eprintln!("Error drawing the terminal interface: {:?}", e);
+                }
+            });
+
+            *self.tui_handle.lock().unwrap() = Some(handle);
+        }
+
+        if self.progress {
+            let progress = Progress {
+                total_files,
+                start: Instant::now(),
+                processed_files: Arc::clone(&self.processed_files),
+                failed_files: Arc::clone(&self.failed_files),
+                skipped_files: Arc::clone(&self.skipped_files),
+                input_file_size: Arc::clone(&self.input_file_size),
+                output_file_size: Arc::clone(&self.output_file_size),
+                active_files: Arc::clone(&self.active_files),
+                records: Arc::clone(&self.records),
+                size_mode: self.size_mode,
+            };
+            let running = Arc::clone(&self.running);
+
+            let handle = std::thread::spawn(move || {
+                if let Err(e) = progress.run(&running) {
+                    eprintln!("Error drawing the progress bars: {:?}", e);
+                }
+            });
+
+            *self.progress_handle.lock().unwrap() = Some(handle);
+        }
+
+        if let Some(address) = self.http_status {
+            let server = HttpStatus {
+                total_files,
+                processed_files: Arc::clone(&self.processed_files),
+                failed_files: Arc::clone(&self.failed_files),
+                skipped_files: Arc::clone(&self.skipped_files),
+                input_file_size: Arc::clone(&self.input_file_size),
+                output_file_size: Arc::clone(&self.output_file_size),
+                active_files: Arc::clone(&self.active_files),
+            };
+            let running = Arc::clone(&self.running);
+
+            let handle = std::thread::spawn(move || {
+                if let Err(e) = server.serve(address, &running) {
+                    warn!("Error serving the HTTP status on {}: {:?}", address, e);
+                }
+            });
+
+            *self.http_handle.lock().unwrap() = Some(handle);
+        }
+    }
+
+    /// the output of a file, with its extension cased as requested by --ext-case.
+    /// if an output root is set, the output is placed there at the same path the file has below its location
+    pub fn get_output_file_name(&self, file: &PathBuf, format: RomFormat) -> Option<PathBuf> {
+        let mut output = if format.contains(RomFormat::CSO) && self.replaces_input(file, format) {
+            // the recompressed image replaces the original once it is complete
+            append_extension(file, "romcomp-cso")
+        } else {
+            self.output_file_name(&self.located(file), format)?
+        };
+
+        // the temporary output of an arcade set or cso image is always created next to it
+        if let Some(output_root) = self
+            .output_root
+            .as_ref()
+            .filter(|_| !self.replaces_input(file, format))
+        {
+            output = output_root.join(output.strip_prefix(self.root_of(file)).unwrap());
+        }
+
+        let upper = match self.ext_case {
+            ExtCase::Lower => false,
+            ExtCase::Upper => true,
+            ExtCase::Preserve => file.extension().and_then(|e| e.to_str()).is_some_and(|e| {
+                e.chars().any(|c| c.is_ascii_uppercase())
+                    && !e.chars().any(|c| c.is_ascii_lowercase())
+            }),
+        };
+
+        // the temporary output of an arcade set or cso image never ends up in the library
+        if !upper || self.replaces_input(file, format) {
+            return Some(output);
+        }
+
+        let extension = output.extension()?.to_str()?.to_uppercase();
+
+        Some(output.with_extension(extension))
+    }
+
+    /// whether the output takes the place of the input once it is complete, like a recompressed arcade set
+    /// or a cso image recompressed next to itself in the same format. below an output root, cso images are written
+    /// as usual, just like those extracted from an archive
+    fn replaces_input(&self, file: &Path, format: RomFormat) -> bool {
+        format.contains(RomFormat::Arcade)
+            || (format.contains(RomFormat::CSO)
+                && self.output_root.is_none()
+                && self.archive_of(file).is_none()
+                && file.extension().is_some_and(|e| {
+                    e.eq_ignore_ascii_case(self.compression_options.cso_format.extension())
+                }))
+    }
+
+    fn output_file_name(&self, file: &Path, format: RomFormat) -> Option<PathBuf> {
+        if format.contains(RomFormat::ZST) {
+            Some(append_extension(file, "zst"))
+        } else if format.contains(RomFormat::FLAC) {
+            Some(replace_extension(file, "zip"))
+        } else if format.contains(RomFormat::Arcade) {
+            // the recompressed set replaces the original once it is complete
+            Some(append_extension(file, "romcomp-arcade"))
+        } else if format.contains(RomFormat::PlayStationX)
+            || format.contains(RomFormat::PlayStation2)
+            || format.contains(RomFormat::PlayStation3)
+            || format.contains(RomFormat::Dreamcast)
+        {
+            Some(replace_extension(file, "chd"))
         } else if format.contains(RomFormat::PlayStationPortable) {
-            Some(file.parent().unwrap().join(format!(
-                "{}.{}",
-                file.file_stem().unwrap().to_str().unwrap(),
-                "cso"
-            )))
-        } else if format.contains(RomFormat::NintendoWii) {
-            Some(file.parent().unwrap().join(format!(
-                "{}.{}",
-                file.file_stem().unwrap().to_str().unwrap(),
-                "rvz"
-            )))
+            Some(replace_extension(
+                file,
+                self.compression_options.cso_format.extension(),
+            ))
+        } else if format.contains(RomFormat::NintendoWii)
+            || format.contains(RomFormat::NintendoGameCube)
+        {
+            Some(replace_extension(file, "rvz"))
         } else if format.contains(RomFormat::Nintendo64) || format.contains(RomFormat::NintendoDS) {
-            Some(
-                Path::new(
-                    regex_replace!(r"(nds|n64|v64|z64)$"i, file.to_str().unwrap(), "zip").as_ref(),
-                )
-                .to_path_buf(),
-            )
+            Some(replace_extension(file, "zip"))
         } else {
             None
         }
     }
 
+    /// whether a conversion of the given size can start without exceeding --io-budget.
+    /// a conversion larger than the budget starts once no other conversion is running
+    fn fits_budget(&self, size: u64) -> bool {
+        let running = self.running_size.load(Ordering::Relaxed);
+
+        self.io_budget
+            .is_none_or(|budget| running == 0 || running + size <= budget)
+    }
+
+    /// whether --fail-fast keeps any further conversions from starting, because a conversion failed
+    pub fn stopped_by_failure(&self) -> bool {
+        self.fail_fast && !self.failed_files.lock().unwrap().is_empty()
+    }
+
     pub fn finish(&self) {
+        let mut stopping = false;
+
         while self.thread_count.load(Ordering::Relaxed) > 0 {
+            if !stopping && !self.stop.is_empty() && self.interrupt.is_empty() {
+                info!(
+                    "Finishing {} running conversions, press Ctrl-C again to abort them",
+                    self.thread_count.load(Ordering::Relaxed)
+                );
+                stopping = true;
+            }
+
             std::thread::sleep(Duration::from_millis(50));
         }
 
+        self.running.store(false, Ordering::Relaxed);
+
+        if let Some(handle) = self.tui_handle.lock().unwrap().take() {
+            let _ = handle.join();
+        }
+
+        if let Some(handle) = self.progress_handle.lock().unwrap().take() {
+            let _ = handle.join();
+        }
+
+        if let Some(handle) = self.http_handle.lock().unwrap().take() {
+            let _ = handle.join();
+        }
+
+        if let Some((status, handle)) = self.status_writer.lock().unwrap().take() {
+            let _ = handle.join();
+
+            if let Err(e) = status.write(true) {
+                warn!("Error writing status file: {:?}", e);
+            }
+        }
+
         let processed = self.processed_files.load(Ordering::Relaxed);
-        let skipped = self.skipped_files.load(Ordering::Relaxed);
+        let failed_files = self.failed_files.lock().unwrap();
+        let failed = failed_files.len();
+        let skipped_files = self.skipped_files.lock().unwrap();
+        let skipped = skipped_files.len();
         let is = self.input_file_size.load(Ordering::Relaxed);
         let os = self.output_file_size.load(Ordering::Relaxed);
 
-        println!(
-            "Compression finished:
-            \tProcessed files: {}, Skipped files: {}, Total: {}
+        if self.dry_run {
+            info!(
+                "Dry run finished:
+            \tWould process {}, would skip {}
+            \tInput file size: {}",
+                processed,
+                skipped,
+                &format_size(is, DECIMAL)
+            );
+        } else {
+            info!(
+                "Compression finished:
+            \tProcessed files: {}, Skipped files: {}, Failed files: {}, Total: {}
             \tInput file size: {}, Output file size: {}
-            \tSaved {} ({:.2}%)",
-            processed,
-            skipped,
-            processed + skipped,
-            &format_size(is, DECIMAL),
-            &format_size(os, DECIMAL),
-            &format_size(is - os, DECIMAL),
-            100f64 - (os as f64 * 100f64 / is as f64)
+            \t{}",
+                processed,
+                skipped,
+                failed,
+                processed + skipped + failed,
+                &format_size(is, DECIMAL),
+                &format_size(os, DECIMAL),
+                savings(is as u64, os as u64)
+            );
+        }
+
+        let mut reasons: BTreeMap<SkipReason, Vec<&PathBuf>> = BTreeMap::new();
+
+        for (file, reason) in skipped_files.iter() {
+            reasons.entry(*reason).or_default().push(file);
+        }
+
+        for (reason, files) in reasons.iter() {
+            info!("\tSkipped ({}): {}", reason, files.len());
+
+            for file in files.iter() {
+                debug!("\t\t{}", file.display());
+            }
+        }
+
+        // the reasons were printed when the conversions failed
+        for (file, _) in failed_files.iter() {
+            debug!("\tFailed: {}", file.display());
+        }
+
+        let mut winners: BTreeMap<String, usize> = BTreeMap::new();
+
+        for record in self.records.lock().unwrap().iter() {
+            if let Some(tool) = record.tool {
+                *winners.entry(tool.to_string()).or_default() += 1;
+            }
+        }
+
+        for (tool, count) in winners.iter() {
+            info!("\tBest of: {} won {} file(s)", tool, count);
+        }
+
+        if let Some(path) = self.report.as_ref() {
+            if let Err(e) = write_json_report(path, &self.report_entries.lock().unwrap()) {
+                warn!("Error writing report {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// the location a file was found in, or its directory if the file was given as location itself
+    fn root_of(&self, file: &Path) -> PathBuf {
+        let file = self.located(file);
+
+        root_of(&file, &self.roots)
+            .filter(|r| r.as_path() != file)
+            .cloned()
+            .unwrap_or_else(|| file.parent().unwrap().to_path_buf())
+    }
+
+    /// extracts an archive into its own directory below the temporary directory, see --extract-archives.
+    /// returns the directory, None if the extraction got interrupted
+    pub fn extract_archive(&self, archive: &Path) -> Result<Option<PathBuf>, String> {
+        let dir = self
+            .temp_dir
+            .path()
+            .join(format!("archive-{}", self.archives.lock().unwrap().len()));
+
+        debug!("Extracting {} to {}", archive.display(), dir.display());
+
+        self.archives
+            .lock()
+            .unwrap()
+            .push((dir.clone(), archive.to_path_buf()));
+
+        match extract_archive(archive, &dir, &self.stop) {
+            Ok(false) => Ok(Some(dir)),
+            Ok(true) => {
+                let _ = remove_dir_all(&dir);
+                Ok(None)
+            }
+            Err(e) => {
+                let _ = remove_dir_all(&dir);
+                Err(format!("Couldn't extract the archive: {}", e))
+            }
+        }
+    }
+
+    /// counts an archive which doesn't hold a rom to convert as skipped and deletes what was extracted from it
+    pub fn skip_archive(&self, archive: &Path, reason: &str) {
+        self.skipped_files
+            .lock()
+            .unwrap()
+            .push((archive.to_path_buf(), SkipReason::NoRom));
+        notify(
+            self.events.as_ref(),
+            ConversionEvent::Skipped(archive.to_path_buf(), SkipReason::NoRom),
+        );
+        self.report_entries.lock().unwrap().push(ReportEntry::new(
+            archive.to_path_buf(),
+            None,
+            self.size_mode.of(archive).unwrap_or(0),
+            0,
+            FileStatus::Skipped,
+            Some(reason.to_string()),
+        ));
+
+        if let Some((dir, _)) = self
+            .archives
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(_, a)| a == archive)
+        {
+            let _ = remove_dir_all(dir);
+        }
+    }
+
+    /// deletes the files extracted from the same archive as the file, once they won't be converted
+    pub fn discard_extracted(&self, file: &Path) {
+        if let Some((dir, _)) = self.archive_of(file) {
+            let _ = remove_dir_all(dir);
+        }
+    }
+
+    /// the directory a file was extracted into and the archive it was extracted from
+    fn archive_of(&self, file: &Path) -> Option<(PathBuf, PathBuf)> {
+        self.archives
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(dir, _)| file.starts_with(dir))
+            .cloned()
+    }
+
+    /// where a file extracted from an archive would be if it was next to the archive, which is where its output
+    /// gets written. other files are where they are
+    fn located(&self, file: &Path) -> PathBuf {
+        match self.archive_of(file) {
+            Some((_, archive)) => archive.with_file_name(file.file_name().unwrap()),
+            None => file.to_path_buf(),
+        }
+    }
+
+    /// the file the user knows a file by, which is the archive for files extracted from one
+    fn origin(&self, file: &Path) -> PathBuf {
+        self.archive_of(file)
+            .map(|(_, archive)| archive)
+            .unwrap_or_else(|| file.to_path_buf())
+    }
+
+    /// the directory the output of a file is written below, flatten never moves an output out of it
+    fn output_root_of(&self, file: &Path) -> PathBuf {
+        self.output_root
+            .clone()
+            .unwrap_or_else(|| self.root_of(file))
+    }
+
+    /// whether any conversion failed, must be called after finish()
+    pub fn had_failures(&self) -> bool {
+        !self.failed_files.lock().unwrap().is_empty()
+    }
+
+    /// all successfully converted files, must be called after finish()
+    pub fn records(&self) -> Vec<ConversionRecord> {
+        self.records.lock().unwrap().clone()
+    }
+
+    fn skip(&self, file: &PathBuf, format: RomFormat, reason: SkipReason) {
+        let origin = self.origin(file);
+
+        self.skipped_files
+            .lock()
+            .unwrap()
+            .push((origin.clone(), reason));
+        notify(
+            self.events.as_ref(),
+            ConversionEvent::Skipped(origin.clone(), reason),
         );
+
+        let output = if reason == SkipReason::Recompressed || reason == SkipReason::Compressed {
+            Some(origin.clone())
+        } else {
+            self.get_output_file_name(file, format).map(|o| {
+                extension_cases(&o)
+                    .into_iter()
+                    .find(|o| o.is_file())
+                    .unwrap_or(o)
+            })
+        };
+
+        self.report_entries.lock().unwrap().push(ReportEntry::new(
+            origin,
+            output.clone(),
+            Self::input_files(file, format)
+                .iter()
+                .map(|f| self.size_mode.of(f).unwrap_or(0))
+                .sum(),
+            output.and_then(|o| self.size_mode.of(&o).ok()).unwrap_or(0),
+            FileStatus::Skipped,
+            Some(reason.to_string()),
+        ));
+
+        self.discard_extracted(file);
+    }
+
+    /// skipped files are only shown in verbose mode, unless the run is a dry one
+    fn skip_level(&self) -> Level {
+        if self.dry_run {
+            Level::Info
+        } else {
+            Level::Debug
+        }
+    }
+
+    fn explain_decision(&self, file: &PathBuf, format: RomFormat, decision: &str) {
+        if !self.explain {
+            return;
+        }
+
+        info!(
+            "Explain {}: format {:?}, tool {}, output {}: {}",
+            self.origin(file).display(),
+            format,
+            format
+                .compression_tool()
+                .or(format
+                    .contains(RomFormat::FLAC)
+                    .then_some(CompressionTool::Flac))
+                .map(|t| t.to_string())
+                .or_else(|| format.contains(RomFormat::ZST).then(|| "zstd".to_string()))
+                .unwrap_or_else(|| "none".to_string()),
+            self.get_output_file_name(file, format)
+                .map(|o| o.display().to_string())
+                .unwrap_or_else(|| "none".to_string()),
+            decision
+        );
+    }
+
+    /// the format and options used for a single file. these include the flags only known once the file itself
+    /// was inspected and the overrides of its sidecar file
+    fn file_settings(&self, file: &PathBuf, format: RomFormat) -> (RomFormat, CompressionOptions) {
+        let mut format = format;
+        let mut options = self.compression_options.clone();
+        // the sidecar of a rom extracted from an archive belongs to the archive
+        let sidecar_path = Sidecar::path(&self.origin(file));
+
+        if sidecar_path.is_file() {
+            match Sidecar::load(&sidecar_path) {
+                Ok(sidecar) => {
+                    debug!("Using the settings of {}", sidecar_path.display());
+
+                    if let Some(console) = sidecar.format() {
+                        if guess_file(file).is_some_and(|g| g.contains(console)) {
+                            format = (format & RomFormat::FILE_FORMATS) | console;
+                        } else {
+                            warn!(
+                                "Ignoring the format of {}: {} can't be converted into it",
+                                sidecar_path.display(),
+                                file.display()
+                            );
+                        }
+                    }
+
+                    sidecar.apply(&mut options);
+
+                    if let Err(e) = options.rvz_settings() {
+                        warn!(
+                            "Ignoring the rvz settings of {}: {}",
+                            sidecar_path.display(),
+                            e
+                        );
+                        options.rvz_codec = self.compression_options.rvz_codec;
+                        options.rvz_level = self.compression_options.rvz_level;
+                    }
+                }
+                Err(e) => warn!("Ignoring {}: {}", sidecar_path.display(), e),
+            }
+        }
+
+        // PS2 games were released on both CDs and DVDs, which chdman converts with different commands
+        if format.contains(RomFormat::PlayStation2 | RomFormat::ISO) && is_dvd_image(file) {
+            options.chd_dvd = true;
+        }
+
+        // chdman has no Blu-ray command, but its DVD format holds any image of 2048 byte sectors
+        if format.contains(RomFormat::PlayStation3) {
+            options.chd_dvd = true;
+        }
+
+        if self.audio_flac && format.contains(RomFormat::BIN) && has_audio_files(file) {
+            format |= RomFormat::FLAC;
+        }
+
+        if self.zstd_seekable && format.intersects(RomFormat::SINGLE_FILE) {
+            format |= RomFormat::ZST;
+        }
+
+        (format, options)
+    }
+
+    /// the shell command which converts a file, or the reason why there is none.
+    /// only conversions which run a single tool on the input file itself can be expressed as a command
+    pub fn command_line(&self, file: &PathBuf, format: RomFormat) -> Result<String, String> {
+        let (format, options) = self.file_settings(file, format);
+        let output = self
+            .get_output_file_name(file, format)
+            .ok_or_else(|| "no output format available".to_string())?;
+
+        if extension_cases(&output)
+            .iter()
+            .any(|o| o.is_file() && !self.overwrite && !(self.update && is_newer(file, o)))
+        {
+            return Err(format!("skipped, {}", SkipReason::TargetExists));
+        }
+
+        if self.sanity_checks && format.contains(RomFormat::ISO) && iso_looks_truncated(file) {
+            return Err(format!("skipped, {}", SkipReason::Truncated));
+        }
+
+        if format.zip() || format.contains(RomFormat::FLAC) {
+            return Err("zipped by romcomp itself".to_string());
+        }
+
+        if format.contains(RomFormat::ZST) {
+            return Err("wrapped into seekable zstd by romcomp itself".to_string());
+        }
+
+        if format.contains(RomFormat::Arcade) {
+            return Err("recompressed by romcomp itself".to_string());
+        }
+
+        if file.to_str().unwrap().to_lowercase().ends_with(".cue.txt") {
+            return Err("needs to be renamed to .cue first".to_string());
+        }
+
+        match format.compression_tool() {
+            Some(tool) if options.internal.contains(&tool) => {
+                Err(format!("converted by romcomp's replacement for {}", tool))
+            }
+            Some(tool) => Ok(tool.command_line(file, &output, &options)),
+            None => Err("no compression tool available".to_string()),
+        }
+    }
+
+    /// all files read when converting a file, the file itself first
+    pub fn input_files(file: &Path, format: RomFormat) -> Vec<PathBuf> {
+        let mut inputs = vec![file.to_path_buf()];
+
+        if format.contains(RomFormat::BIN) {
+            // an unreadable cue sheet fails once the conversion parses it
+            if let Ok(cd) = CD::parse_file(file.to_path_buf()) {
+                for track in cd.tracks() {
+                    let bin = file.parent().unwrap().join(track.get_filename());
+
+                    if !inputs.contains(&bin) {
+                        inputs.push(bin);
+                    }
+                }
+            }
+        } else if format.contains(RomFormat::WBFS) {
+            inputs.append(&mut wbfs_parts(file));
+        } else if format.contains(RomFormat::GDI) {
+            inputs = gdi_files(file);
+        }
+
+        inputs
+    }
+
+    /// where --flatten would most likely move an output to, assuming the inputs got deleted by --remove.
+    /// the outputs of other files in the same directory aren't known before they are written
+    fn flattened_output(&self, file: &Path, output: &Path, inputs: &[PathBuf]) -> PathBuf {
+        let root = self.output_root_of(file);
+        // everything besides the output, the removed inputs and the directory the output is moved out of
+        let remaining = |dir: &Path, below: &Path| {
+            dir.read_dir().map_or(0, |rd| {
+                rd.flatten()
+                    .map(|e| e.path())
+                    .filter(|p| p != output && p != below && !inputs.contains(p))
+                    .count()
+            })
+        };
+        let mut below = output;
+        let mut dir = output.parent().unwrap();
+
+        while dir != root && dir.starts_with(&root) && remaining(dir, below) == 0 {
+            below = dir;
+            dir = dir.parent().unwrap();
+        }
+
+        // an existing file of the same name is never replaced, the output stays where it is then
+        let target = dir.join(output.file_name().unwrap());
+
+        if target != output && target.exists() {
+            output.to_path_buf()
+        } else {
+            target
+        }
+    }
+
+    /// the conversion of a file as a job, which can be executed independently of the other jobs
+    pub fn job(&self, file: &PathBuf, format: RomFormat) -> Job {
+        let (settings_format, _) = self.file_settings(file, format);
+        let inputs = Converter::input_files(file, settings_format);
+
+        let (command, note) = match self.command_line(file, format) {
+            Ok(command) => (Some(command), None),
+            Err(reason) => (None, Some(reason)),
+        };
+
+        Job {
+            input: file.clone(),
+            inputs,
+            // the flags added by file_settings are detected again when the job runs
+            format: format.iter_names().map(|(n, _)| n.to_string()).collect(),
+            command,
+            note,
+            output: self.get_output_file_name(file, settings_format),
+        }
     }
 
     pub fn convert(&self, file: &PathBuf, format: RomFormat) {
-        if Converter::get_output_file_name(file, format)
-            .map(|f| f.is_file())
-            .unwrap_or(false)
+        let (format, options) = self.file_settings(file, format);
+        let origin = self.origin(file);
+        // taken before the conversion, the input might get removed afterwards
+        let stamp = self
+            .state
+            .as_ref()
+            .and_then(|_| InputStamp::of(&origin).ok());
+
+        if let (Some(state), Some(stamp)) = (self.state.as_ref(), stamp.as_ref()) {
+            if state.is_completed(stamp) {
+                self.explain_decision(file, format, &format!("Skip, {}", SkipReason::Completed));
+                self.skip(file, format, SkipReason::Completed);
+                log!(
+                    self.skip_level(),
+                    "Skipping {}: {}",
+                    origin.display(),
+                    SkipReason::Completed
+                );
+                return;
+            }
+        }
+
+        if self.min_size.is_some() || self.max_size.is_some() {
+            let size = Self::input_files(file, format)
+                .iter()
+                .map(|f| self.size_mode.of(f).unwrap_or(0))
+                .sum::<u64>();
+
+            if self.min_size.is_some_and(|m| size < m) || self.max_size.is_some_and(|m| size > m) {
+                self.explain_decision(
+                    file,
+                    format,
+                    &format!("Skip, {}", SkipReason::SizeOutOfRange),
+                );
+                self.skip(file, format, SkipReason::SizeOutOfRange);
+                log!(
+                    self.skip_level(),
+                    "Skipping {}: {} ({})",
+                    origin.display(),
+                    SkipReason::SizeOutOfRange,
+                    format_size(size, DECIMAL)
+                );
+                return;
+            }
+        }
+
+        if format.contains(RomFormat::CSO) && !self.recompress {
+            self.explain_decision(file, format, &format!("Skip, {}", SkipReason::Compressed));
+            self.skip(file, format, SkipReason::Compressed);
+            log!(
+                self.skip_level(),
+                "Skipping {}: {}",
+                origin.display(),
+                SkipReason::Compressed
+            );
+            return;
+        }
+
+        // the outdated or overwritten output which gets deleted before the conversion starts
+        let mut replace_output = None;
+        let output_file = self.get_output_file_name(file, format);
+
+        for output in output_file.iter().flat_map(|o| extension_cases(o)) {
+            discard_incomplete_output(&output, self.dry_run);
+        }
+
+        if let Some(output) = output_file
+            .iter()
+            .flat_map(|o| extension_cases(o))
+            .find(|o| o.is_file() && !partial_marker(o).is_file())
+        {
+            if self.overwrite {
+                debug!(
+                    "Recompressing {}: Overwriting {}",
+                    origin.display(),
+                    output.display()
+                );
+                self.explain_decision(
+                    file,
+                    format,
+                    "Recompress, --overwrite replaces the existing output",
+                );
+                replace_output = Some(output);
+            } else if self.update && is_newer(file, &output) {
+                debug!(
+                    "Recompressing {}: Input file is newer than {}",
+                    origin.display(),
+                    output.display()
+                );
+                self.explain_decision(
+                    file,
+                    format,
+                    "Recompress, the input is newer than the output",
+                );
+                replace_output = Some(output);
+            } else {
+                self.explain_decision(file, format, &format!("Skip, {}", SkipReason::TargetExists));
+                self.skip(file, format, SkipReason::TargetExists);
+                log!(
+                    self.skip_level(),
+                    "Skipping {}: {}",
+                    origin.display(),
+                    SkipReason::TargetExists
+                );
+                return;
+            }
+        }
+
+        if format.contains(RomFormat::Arcade) && is_recompressed(file) {
+            self.explain_decision(file, format, &format!("Skip, {}", SkipReason::Recompressed));
+            self.skip(file, format, SkipReason::Recompressed);
+            log!(
+                self.skip_level(),
+                "Skipping {}: {}",
+                origin.display(),
+                SkipReason::Recompressed
+            );
+            return;
+        }
+
+        if self.sanity_checks && format.contains(RomFormat::ISO) && iso_looks_truncated(file) {
+            self.explain_decision(file, format, &format!("Skip, {}", SkipReason::Truncated));
+            self.skip(file, format, SkipReason::Truncated);
+            info!("Skipping {}: {}", origin.display(), SkipReason::Truncated);
+            return;
+        }
+
+        // chdman trusts the cue sheet, a wrong mode results in a chd which verifies but contains broken sectors
+        if self.sanity_checks && format.contains(RomFormat::BIN) {
+            for (bin, declared, actual) in sector_mode_mismatches(file) {
+                warn!(
+                    "Warning: {} declares {} as MODE{}/2352, but its sectors are MODE{}",
+                    origin.display(),
+                    bin,
+                    declared,
+                    actual
+                );
+            }
+        }
+
+        // the hunk size is given in whole CD frames, DVDs keep the default of chdman
+        if options.chd_dvd
+            && options.chd_hunk_size.is_some()
+            && format.compression_tool() == Some(CompressionTool::Chdman)
         {
-            self.skipped_files.fetch_add(1, Ordering::Relaxed);
-            if self.verbose {
-                println!("Skipping {}: Target file already exists", file.display());
+            warn!(
+                "Ignoring the hunk size for {}: DVD images are converted with chdman's default hunk size",
+                origin.display()
+            );
+        }
+
+        if replace_output.is_none() {
+            self.explain_decision(file, format, "Compress");
+        }
+
+        if self.dry_run {
+            // the archive is what --remove deletes, the extracted files are only temporary
+            let inputs = if self.archive_of(file).is_some() {
+                vec![origin.clone()]
+            } else {
+                Converter::input_files(file, format)
+            };
+            let size = inputs
+                .iter()
+                .map(|i| self.size_mode.of(i).unwrap_or(0))
+                .sum::<u64>();
+            let mut output = output_file.unwrap();
+
+            if self.flatten {
+                output = self.flattened_output(file, &output, &inputs);
             }
+
+            info!(
+                "Would compress {} ({}) to {}{}",
+                origin.display(),
+                format_size(size, DECIMAL),
+                output.display(),
+                replace_output
+                    .map(|o| format!(
+                        ", replacing the {} {}",
+                        if self.overwrite {
+                            "existing"
+                        } else {
+                            "outdated"
+                        },
+                        o.display()
+                    ))
+                    .unwrap_or_default()
+            );
+
+            self.processed_files.fetch_add(1, Ordering::Relaxed);
+            self.input_file_size
+                .fetch_add(size as usize, Ordering::Relaxed);
+            self.discard_extracted(file);
             return;
         }
 
         let itrp = self.interrupt.clone();
+        let size = if self.io_budget.is_some() {
+            Self::input_files(file, format)
+                .iter()
+                .map(|f| self.size_mode.of(f).unwrap_or(0))
+                .sum::<u64>()
+        } else {
+            0
+        };
 
-        while self.thread_count.load(Ordering::Relaxed) >= self.available_threads {
+        while self.thread_count.load(Ordering::Relaxed) >= self.available_threads
+            || !self.fits_budget(size)
+        {
             std::thread::sleep(Duration::from_millis(50));
 
-            if !itrp.is_empty() {
+            if !itrp.is_empty() || !self.stop.is_empty() {
                 return;
             }
         }
 
-        let t_ptr = Arc::clone(&self.thread_count);
+        // one of the conversions the file was waiting for might have failed
+        if self.stopped_by_failure() || !self.stop.is_empty() {
+            return;
+        }
+
+        let active = ActiveConversion {
+            file: origin.clone(),
+            active_files: Arc::clone(&self.active_files),
+            failed_files: Arc::clone(&self.failed_files),
+            report_entries: Arc::clone(&self.report_entries),
+            thread_count: Arc::clone(&self.thread_count),
+            size,
+            running_size: Arc::clone(&self.running_size),
+            events: self.events.clone(),
+        };
         let p_ptr = Arc::clone(&self.processed_files);
+        let records_ptr = Arc::clone(&self.records);
+        let report_ptr = Arc::clone(&self.report_entries);
+        let f_ptr = Arc::clone(&self.failed_files);
+        let skipped_ptr = Arc::clone(&self.skipped_files);
         let is_ptr = Arc::clone(&self.input_file_size);
         let os_ptr = Arc::clone(&self.output_file_size);
         let p = file.clone();
+        let archive = self.archive_of(file);
+        let in_place = self.replaces_input(file, format);
         let rem = self.remove_after_compression;
-        let verbose = self.verbose;
         let flatten = self.flatten;
-        let root = self.root_directory.clone();
+        let hash = self.hash;
+        let preserve_xattrs = self.preserve_xattrs;
+        let output_mode = self.output_mode;
+        let output_owner = self.output_owner;
+        let trim = self.trim;
+        let best_of = self.best_of;
+        let retries = self.retries;
+        let min_savings = self.min_savings;
+        let size_mode = self.size_mode;
+        let s3 = self.s3.clone();
+        let s3_remove_local = self.s3_remove_local;
+        let io_limiter = self.io_limiter.clone();
+        let reflink = self.reflink;
+        let output_root = self.output_root_of(file);
         let temp_dir = Arc::clone(&self.temp_dir);
+        let dupe_action = self.dupe_action;
+        let hashes_ptr = Arc::clone(&self.output_hashes);
+        let state = self.state.clone();
+        let events = self.events.clone();
 
         self.thread_count.fetch_add(1, Ordering::Relaxed);
+        self.running_size.fetch_add(size, Ordering::Relaxed);
+        self.active_files.lock().unwrap().push(origin.clone());
 
-        if self.verbose {
-            println!("Beginning compression of {}...", file.display());
-        }
+        debug!("Beginning compression of {}...", origin.display());
+        notify(
+            self.events.as_ref(),
+            ConversionEvent::Started(origin.clone()),
+        );
 
         std::thread::spawn(move || {
-            let prepare_files =
-                |p: &PathBuf, f: RomFormat, verbose: bool| -> Vec<(PathBuf, FileSource)> {
-                    if f.contains(RomFormat::BIN) {
-                        let mut files = vec![(p.clone(), FileSource::Input)];
-
-                        if p.file_name()
-                            .unwrap()
-                            .to_str()
-                            .unwrap()
-                            .ends_with("cue.txt")
-                        {
-                            let new = Path::new(
-                                regex_replace!(r"\.txt$"i, p.to_str().unwrap(), "").as_ref(),
-                            )
-                            .to_path_buf();
-                            if verbose {
-                                println!("Copy {} to {} temporarily", p.display(), new.display());
-                            }
+            let _active = active;
 
-                            let _ = copy(p, &new);
+            let stage = |from: &PathBuf, to: &PathBuf| -> Result<(), String> {
+                let _permit = io_limiter.as_ref().map(|l| l.acquire());
 
-                            files.push((new, FileSource::TemporaryInput));
-                        }
+                let copied = if reflink {
+                    reflink_or_copy(from, to)
+                } else {
+                    copy(from, to).map(|_| false)
+                };
 
-                        files.append(
-                            &mut CD::parse_file(p.clone())
-                                .unwrap()
-                                .tracks()
-                                .into_iter()
-                                .map(|t| {
-                                    (
-                                        p.parent().unwrap().join(t.get_filename()),
-                                        FileSource::Input,
-                                    )
-                                })
-                                .collect::<Vec<_>>(),
-                        );
+                if copied.is_ok() {
+                    debug!(
+                        "{} {} to {} temporarily",
+                        if copied.as_ref().is_ok_and(|c| *c) {
+                            "Cloned"
+                        } else {
+                            "Copied"
+                        },
+                        from.display(),
+                        to.display()
+                    );
+                }
 
-                        files
-                    } else if format.contains(RomFormat::Nintendo64) {
-                        let mut files = vec![(p.clone(), FileSource::Input)];
-                        if !format.contains(RomFormat::Z64) {
-                            files.push((
-                                p.parent().unwrap().join(format!(
-                                    "{}.{}",
-                                    p.file_stem().unwrap().to_str().unwrap(),
-                                    "z64"
-                                )),
-                                FileSource::TemporaryOutput,
-                            ));
+                copied.map(|_| ()).map_err(|e| {
+                    let _ = remove_file(to);
+                    format!("Failed to stage temp copy {}: {}", to.display(), e)
+                })
+            };
+
+            let prepare_files = |p: &PathBuf,
+                                 f: RomFormat|
+             -> Result<Vec<(PathBuf, FileSource)>, String> {
+                if f.contains(RomFormat::BIN) {
+                    let mut files = vec![(p.clone(), FileSource::Input)];
+                    // parsed before staging, so a broken cue sheet leaves no temporary copy behind
+                    let cd = CD::parse_file(p.clone())
+                        .map_err(|e| format!("Couldn't read the cue sheet: {:?}", e))?;
+
+                    if p.file_name()
+                        .unwrap()
+                        .to_str()
+                        .unwrap()
+                        .ends_with("cue.txt")
+                    {
+                        let new =
+                            Path::new(regex_replace!(r"\.txt$"i, p.to_str().unwrap(), "").as_ref())
+                                .to_path_buf();
+
+                        stage(p, &new)?;
+
+                        files.push((new, FileSource::TemporaryInput));
+                    }
+
+                    // a file can contain multiple tracks, but must only be accounted for once
+                    for track in cd.tracks() {
+                        let bin = p.parent().unwrap().join(track.get_filename());
+
+                        if !files.iter().any(|(f, _)| *f == bin) {
+                            files.push((bin, FileSource::Input));
                         }
-                        files
-                    } else if format.contains(RomFormat::NintendoDS) {
-                        let new = temp_dir.path().join(p.file_name().unwrap()).to_path_buf();
+                    }
+
+                    Ok(files)
+                } else if f.contains(RomFormat::ZST) {
+                    Ok(vec![(p.clone(), FileSource::Input)])
+                } else if f.contains(RomFormat::GDI) {
+                    // chdman reads the track files in the order of the gdi file
+                    Ok(gdi_files(p)
+                        .into_iter()
+                        .map(|f| (f, FileSource::Input))
+                        .collect())
+                } else if f.contains(RomFormat::Nintendo64) {
+                    let mut files = vec![(p.clone(), FileSource::Input)];
+                    // 64DD disks don't have a byte order and get zipped as they are
+                    if !f.intersects(RomFormat::Z64 | RomFormat::NDD) {
+                        // a game.z64 next to the input might be a rom of its own, which the cleanup would delete
+                        let dir = Builder::new()
+                            .prefix("n64-")
+                            .tempdir_in(temp_dir.path())
+                            .map_err(|e| format!("Couldn't create a temporary directory: {}", e))?
+                            .keep();
+
+                        // rom64 takes no output argument and writes the z64 next to the rom it converts
+                        if !options.internal.contains(&CompressionTool::Rom64) {
+                            let new = dir.join(p.file_name().unwrap());
+
+                            stage(p, &new).inspect_err(|_| {
+                                let _ = remove_dir_all(&dir);
+                            })?;
 
-                        if verbose {
-                            println!("Copy {} to {} temporarily", p.display(), new.display());
+                            files.push((new, FileSource::TemporaryInput));
                         }
 
-                        let _ = copy(p, &new);
+                        files.push((
+                            append_extension(&dir.join(p.file_stem().unwrap()), "z64"),
+                            FileSource::TemporaryOutput,
+                        ));
+                        files.push((dir, FileSource::TemporaryDirectory));
+                    }
+                    Ok(files)
+                } else if f.contains(RomFormat::WBFS) {
+                    // dolphin-tool picks up the additional parts of split images by itself
+                    let mut files = vec![(p.clone(), FileSource::Input)];
+                    files.append(
+                        &mut wbfs_parts(p)
+                            .into_iter()
+                            .map(|f| (f, FileSource::Input))
+                            .collect::<Vec<_>>(),
+                    );
+                    Ok(files)
+                } else if f.contains(RomFormat::CSO)
+                    && options.internal.contains(&CompressionTool::MaxCSO)
+                {
+                    // unlike maxcso, the internal encoder only reads uncompressed images
+                    if read_at(p, 0, DAX_MAGIC.len()).is_some_and(|m| m == DAX_MAGIC) {
+                        return Err("The internal cso encoder can't read dax images".to_string());
+                    }
 
-                        vec![
+                    // images of the same name in different directories are decompressed at the same time
+                    let dir = Builder::new()
+                        .prefix("cso-")
+                        .tempdir_in(temp_dir.path())
+                        .map_err(|e| format!("Couldn't create a temporary directory: {}", e))?
+                        .keep();
+                    let new = append_extension(&dir.join(p.file_stem().unwrap()), "iso");
+
+                    debug!(
+                        "Decompressing {} to {} temporarily",
+                        p.display(),
+                        new.display()
+                    );
+
+                    match decompress_cso(p, &new, &itrp) {
+                        Ok(false) => Ok(vec![
                             (p.clone(), FileSource::Input),
-                            (new.clone(), FileSource::TemporaryInput),
-                            (new, FileSource::TemporaryOutput),
-                        ]
-                    } else {
-                        vec![(p.clone(), FileSource::Input)]
+                            (new, FileSource::TemporaryInput),
+                            (dir, FileSource::TemporaryDirectory),
+                        ]),
+                        Ok(true) => {
+                            let _ = remove_dir_all(&dir);
+                            Err("The decompression got interrupted".to_string())
+                        }
+                        Err(e) => {
+                            let _ = remove_dir_all(&dir);
+                            Err(format!("Couldn't decompress {}: {}", p.display(), e))
+                        }
                     }
-                };
+                } else if f.contains(RomFormat::NintendoDS) {
+                    let new = temp_dir.path().join(p.file_name().unwrap()).to_path_buf();
+
+                    stage(p, &new)?;
+
+                    Ok(vec![
+                        (p.clone(), FileSource::Input),
+                        (new.clone(), FileSource::TemporaryInput),
+                        (new, FileSource::TemporaryOutput),
+                    ])
+                } else {
+                    Ok(vec![(p.clone(), FileSource::Input)])
+                }
+            };
 
             let cleanup = |f: Vec<(PathBuf, FileSource)>,
                            remove_after_compression: bool,
-                           interrupted: bool,
-                           verbose: bool| {
+                           interrupted: bool| {
                 let mut processed = vec![];
 
+                // only remove the inputs once the output is known to be completely written,
+                // otherwise the inputs might be the only copy left
+                let remove_after_compression = remove_after_compression
+                    && !interrupted
+                    && match f.iter().find(|(_, s)| *s == FileSource::Output) {
+                        Some((output, _)) if output_is_complete(output) => true,
+                        Some((output, _)) => {
+                            warn!(
+                                "Keeping input files: {} couldn't be verified to be completely written",
+                                output.display()
+                            );
+                            false
+                        }
+                        None => false,
+                    };
+
                 for (file, source) in f.into_iter() {
                     if !processed.contains(&file) {
                         if source == FileSource::TemporaryInput
                             || source == FileSource::TemporaryOutput
                         {
-                            if verbose {
-                                println!("Deleting temporary file {}", file.display());
-                            }
+                            debug!("Deleting temporary file {}", file.display());
 
                             let _ = remove_file(&file);
                         } else if source == FileSource::Input
                             && remove_after_compression
                             && !interrupted
                         {
-                            if verbose {
-                                println!("Deleting input file {}", file.display());
-                            }
+                            debug!("Deleting input file {}", file.display());
 
                             let _ = remove_file(&file);
                         } else if source == FileSource::Output && interrupted {
-                            if verbose {
-                                println!("Deleting incomplete output file {}", file.display());
-                            }
+                            debug!("Deleting incomplete output file {}", file.display());
 
                             let _ = remove_file(&file);
+                        } else if source == FileSource::TemporaryDirectory {
+                            debug!("Deleting temporary directory {}", file.display());
+
+                            let _ = remove_dir_all(&file);
                         }
 
                         processed.push(file);
                     }
                 }
+
+                // along with anything else the archive held besides the rom
+                if let Some((dir, _)) = archive.as_ref() {
+                    let _ = remove_dir_all(dir);
+                }
             };
 
-            let flatten_directories = |file: &PathBuf, root: &PathBuf, verbose: bool| {
+            let flatten_directories = |file: &PathBuf, root: &PathBuf| -> PathBuf {
                 let mut dir = file.parent();
 
+                // the root itself is never left, even if the output is the only file in it
                 while dir.is_some_and(|dir| {
-                    dir.starts_with(root) && dir.read_dir().is_ok_and(|rd| rd.count() == 1)
+                    dir != root
+                        && dir.starts_with(root)
+                        && dir.read_dir().is_ok_and(|rd| rd.count() == 1)
                 }) {
                     dir = dir.unwrap().parent();
                 }
 
                 if dir.is_some() && dir != file.parent() {
-                    if verbose {
-                        println!(
-                            "Moving {} to {}",
-                            file.display(),
-                            dir.unwrap().join(file.file_name().unwrap()).display()
-                        );
-                    }
-
-                    if let Err(e) = rename(file, dir.unwrap().join(file.file_name().unwrap())) {
-                        if verbose {
-                            println!("Error moving file: {:?}", e);
+                    let target = dir.unwrap().join(file.file_name().unwrap());
+
+                    // rename would silently replace an output of the same name, e.g. one flattened
+                    // out of a sibling directory. linking fails instead, even if both happen at once
+                    let moved = match hard_link(file, &target) {
+                        Ok(()) => remove_file(file).inspect_err(|_| {
+                            let _ = remove_file(&target);
+                        }),
+                        Err(e) if e.kind() == ErrorKind::AlreadyExists => Err(e),
+                        // filesystems without hard links, e.g. FAT
+                        Err(_) if target.exists() => Err(ErrorKind::AlreadyExists.into()),
+                        Err(_) => rename(file, &target),
+                    };
+
+                    match moved {
+                        Ok(()) => {
+                            debug!("Moved {} to {}", file.display(), target.display());
+                        }
+                        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                            debug!(
+                                "Keeping {} in place: {} already exists",
+                                file.display(),
+                                target.display()
+                            );
+                            return file.clone();
+                        }
+                        Err(e) => {
+                            debug!("Error moving file: {:?}", e);
+                            return file.clone();
                         }
-                        return;
                     }
 
                     let mut current = file.parent();
                     while current != dir && current.is_some() {
-                        if verbose {
-                            println!("Removing empty directory {}", current.unwrap().display());
-                        }
+                        debug!("Removing empty directory {}", current.unwrap().display());
                         if let Err(e) = remove_dir(current.as_ref().unwrap()) {
-                            if verbose {
-                                println!("Error removing directory: {:?}", e);
-                            }
-                            return;
+                            debug!("Error removing directory: {:?}", e);
+                            return target;
                         }
                         current = current.unwrap().parent();
                     }
+
+                    target
+                } else {
+                    file.clone()
                 }
             };
 
-            let mut files = prepare_files(&p, format, verbose);
+            // every input has to exist, e.g. a missing bin file would only be noticed by the tool otherwise
+            let prepared = prepare_files(&p, format)
+                .and_then(|files| {
+                    // the roms themselves, not the archive they might have been extracted from
+                    let hashes = if hash {
+                        let _permit = io_limiter.as_ref().map(|l| l.acquire());
+
+                        files
+                            .iter()
+                            .filter(|(_, s)| *s == FileSource::Input)
+                            .map(|(f, _)| match hash_input(f, &itrp) {
+                                Ok(Some(hash)) => Ok(hash),
+                                Ok(None) => Err("The hashing got interrupted".to_string()),
+                                Err(e) => Err(format!("Couldn't hash {}: {}", f.display(), e)),
+                            })
+                            .collect::<Result<Vec<_>, String>>()
+                    } else {
+                        Ok(vec![])
+                    };
+
+                    match hashes {
+                        Ok(hashes) => Ok((files, hashes)),
+                        Err(e) => {
+                            cleanup(files, false, true);
+                            Err(e)
+                        }
+                    }
+                })
+                .map(|(files, hashes)| {
+                    let files = match archive.as_ref() {
+                        // the extracted files come after the staged ones, which the tools read instead of them
+                        Some((dir, archive)) => {
+                            let (extracted, mut files): (Vec<_>, Vec<_>) = files
+                                .into_iter()
+                                .partition(|(f, s)| *s == FileSource::Input && f.starts_with(dir));
+
+                            files.extend(
+                                extracted
+                                    .into_iter()
+                                    .map(|(f, _)| (f, FileSource::TemporaryInput)),
+                            );
+                            files.push((archive.clone(), FileSource::Input));
+                            files
+                        }
+                        None => files,
+                    };
+
+                    (files, hashes)
+                })
+                .and_then(|(files, hashes)| {
+                    let is = files
+                        .iter()
+                        .filter(|(_, s)| *s == FileSource::Input)
+                        .map(|(f, _)| {
+                            size_mode
+                                .of(f)
+                                .map_err(|e| format!("Couldn't read {}: {}", f.display(), e))
+                        })
+                        .sum::<Result<u64, String>>();
+
+                    match is {
+                        Ok(is) => Ok((files, is, hashes)),
+                        Err(e) => {
+                            cleanup(files, false, true);
+                            Err(e)
+                        }
+                    }
+                });
 
-            let mut is: u64 = 0;
+            let (mut files, is, hashes) = match prepared {
+                Ok(prepared) => prepared,
+                Err(e) => {
+                    if let Some((dir, _)) = archive.as_ref() {
+                        let _ = remove_dir_all(dir);
+                    }
 
-            for (f, s) in files.iter() {
-                if *s == FileSource::Input {
-                    is += f.size_on_disk().unwrap();
+                    error!("Failed compression of {}: {}", origin.display(), e);
+                    f_ptr.lock().unwrap().push((origin.clone(), e.clone()));
+                    notify(
+                        events.as_ref(),
+                        ConversionEvent::Failed(origin.clone(), e.clone()),
+                    );
+                    report_ptr.lock().unwrap().push(ReportEntry::new(
+                        origin.clone(),
+                        None,
+                        0,
+                        0,
+                        FileStatus::Failed,
+                        Some(e),
+                    ));
+                    return;
                 }
-            }
+            };
 
             let in_file = files
                 .iter()
@@ -351,50 +2174,257 @@ impl Converter {
                     .0
                     .clone()
             } else {
-                Converter::get_output_file_name(&in_file, format).unwrap()
+                output_file.clone().unwrap()
             };
             let mut interrupted = false;
+            // the reason the conversion failed, a failed conversion counts as interrupted as well
+            let mut failure: Option<String> = None;
 
-            files.push((
-                Converter::get_output_file_name(&p, format).unwrap(),
-                FileSource::Output,
-            ));
+            files.push((output_file.unwrap(), FileSource::Output));
 
-            let expression = format
-                .compression_tool()
-                .map(|c| c.build(&in_file, &out_file));
+            if let Some(outdated) = replace_output {
+                debug!("Deleting replaced output file {}", outdated.display());
+
+                let _ = remove_file(outdated);
+            }
+
+            let mut output = files.last().unwrap().0.clone();
+            let mut marker = partial_marker(&output);
+
+            // outputs written below an output root mirror the directories of their inputs
+            if let Err(e) = create_dir_all(output.parent().unwrap()) {
+                warn!(
+                    "Couldn't create {}: {}",
+                    output.parent().unwrap().display(),
+                    e
+                );
+            }
+
+            if let Err(e) = File::create(&marker) {
+                warn!("Couldn't create {}: {}", marker.display(), e);
+            }
+
+            // returns whether the tool got interrupted, or why it failed
+            let run_tool = |tool: CompressionTool,
+                            input: &PathBuf,
+                            output: &PathBuf|
+             -> Result<bool, String> {
+                if options.internal.contains(&tool) {
+                    return tool
+                        .run_internal(input, output, &options, &itrp)
+                        .map_err(|e| e.to_string());
+                }
+
+                let run_once = || -> Result<bool, String> {
+                    let interrupted = tool.run(
+                        &options.binary(tool),
+                        &tool.arguments(input, output, &options),
+                        &itrp,
+                    )?;
+
+                    // the output is recorded by the path it was planned at, a tool writing it anywhere else
+                    // would leave an empty record behind
+                    if !interrupted && !output.exists() {
+                        return Err(format!("{} didn't create {}", tool, output.display()));
+                    }
 
-            if let Some(e) = expression {
-                let proc = e
-                    .dir(std::env::current_dir().unwrap())
-                    .stderr_capture()
-                    .stdout_capture()
-                    .start()
-                    .unwrap();
+                    Ok(interrupted)
+                };
+
+                // failures can be transient, e.g. a file locked by another program. the delay doubles
+                // with every attempt, the error of the last attempt is the one reported
+                let mut attempt = 0;
 
                 loop {
-                    let status = proc.try_wait();
-                    if status.as_ref().is_ok_and(|e| *e == None) {
-                        std::thread::sleep(Duration::from_millis(50));
-                        if !itrp.is_empty() {
-                            interrupted = true;
-                            let _ = proc.kill();
-                            break;
+                    match run_once() {
+                        Err(e) if attempt < retries && itrp.is_empty() => {
+                            attempt += 1;
+                            let delay = Duration::from_secs(1 << (attempt - 1).min(6));
+
+                            warn!(
+                                "{} failed for {}, retrying in {}s ({}/{}): {}",
+                                tool,
+                                input.display(),
+                                delay.as_secs(),
+                                attempt,
+                                retries,
+                                e
+                            );
+
+                            // tools like bitbutcher modify their input in place, which must be kept
+                            if output != input {
+                                let _ = remove_file(output);
+                            }
+
+                            let start = Instant::now();
+
+                            while start.elapsed() < delay {
+                                if !itrp.is_empty() {
+                                    return Ok(true);
+                                }
+                                std::thread::sleep(Duration::from_millis(50));
+                            }
                         }
-                        std::thread::sleep(Duration::from_millis(50));
-                    } else if status
-                        .as_ref()
-                        .is_ok_and(|e| e.is_some_and(|e| e.status.success()))
-                    {
+                        result => return result,
+                    }
+                }
+            };
+
+            let mut tool = format.compression_tool();
+
+            if let Some(c) = tool {
+                match run_tool(c, &in_file, &out_file) {
+                    Ok(i) => interrupted = i,
+                    Err(e) => failure = Some(e),
+                }
+
+                // try the alternatives and keep whichever output is the smallest
+                for alternative in format.alternative_tools().into_iter().filter(|_| best_of) {
+                    if interrupted || failure.is_some() {
                         break;
+                    }
+
+                    let candidate = out_file.with_extension(options.extension(alternative));
+
+                    if candidate.exists() {
+                        info!(
+                            "Not trying {}: {} already exists",
+                            alternative,
+                            candidate.display()
+                        );
+                        continue;
+                    }
+
+                    let candidate_marker = partial_marker(&candidate);
+                    let _ = File::create(&candidate_marker);
+
+                    debug!("Trying {} for {}", alternative, in_file.display());
+
+                    // the output of the first tool is still there, so a failing alternative is no failure
+                    let result = run_tool(alternative, &in_file, &candidate);
+
+                    if let Err(e) = result.as_ref() {
+                        warn!(
+                            "{} couldn't convert {}: {}",
+                            alternative,
+                            in_file.display(),
+                            e
+                        );
+                    }
+
+                    if result != Ok(false) {
+                        let _ = remove_file(&candidate);
+                        let _ = remove_file(&candidate_marker);
+                        interrupted = !itrp.is_empty();
+                        continue;
+                    }
+
+                    let size = |f: &PathBuf| f.metadata().map(|m| m.len()).unwrap_or(u64::MAX);
+
+                    if size(&candidate) < size(&out_file) {
+                        debug!(
+                            "{} is smaller than {}, deleting the latter",
+                            candidate.display(),
+                            out_file.display()
+                        );
+
+                        let _ = remove_file(&out_file);
+                        let _ = remove_file(&marker);
+                        files.retain(|(_, s)| *s != FileSource::Output);
+                        files.push((candidate.clone(), FileSource::Output));
+                        out_file = candidate.clone();
+                        output = candidate;
+                        marker = candidate_marker;
+                        tool = Some(alternative);
                     } else {
-                        interrupted = true;
-                        break;
+                        let _ = remove_file(&candidate);
+                        let _ = remove_file(&candidate_marker);
+                    }
+                }
+
+                if best_of
+                    && !format.alternative_tools().is_empty()
+                    && !interrupted
+                    && failure.is_none()
+                {
+                    info!(
+                        "Best of: {} created the smallest output for {}",
+                        tool.unwrap(),
+                        in_file.display()
+                    );
+                }
+            } else if format.contains(RomFormat::FLAC) {
+                match archive_audio_cd(&in_file, &out_file, temp_dir.path(), &options, &itrp) {
+                    Ok(i) => interrupted = i,
+                    Err(e) => failure = Some(format!("Failed to archive the audio tracks: {}", e)),
+                }
+            } else if format.contains(RomFormat::ZST) {
+                let _permit = io_limiter.as_ref().map(|l| l.acquire());
+
+                match compress_zstd_seekable(
+                    &in_file,
+                    &out_file,
+                    options.zstd_frame_size.unwrap_or(DEFAULT_FRAME_SIZE),
+                    options.preset.zstd_level(),
+                    &itrp,
+                ) {
+                    Ok(i) => interrupted = i,
+                    Err(e) => failure = Some(e.to_string()),
+                }
+            } else if format.contains(RomFormat::Arcade) {
+                let _permit = io_limiter.as_ref().map(|l| l.acquire());
+
+                match recompress_set(&in_file, &out_file, &itrp) {
+                    Ok(i) => interrupted = i,
+                    Err(e) => failure = Some(format!("Failed to recompress: {}", e)),
+                }
+            }
+
+            // a recompressed set or image is only kept if it is smaller than the original.
+            // MAME finds a set by its name only, so the recompressed set takes the place of the original,
+            // just like a cso image recompressed next to itself
+            if !interrupted
+                && failure.is_none()
+                && (format.contains(RomFormat::Arcade) || format.contains(RomFormat::CSO))
+            {
+                let size = |f: &PathBuf| f.metadata().map(|m| m.len()).unwrap_or(u64::MAX);
+                // the original ends up as the output if it is kept or replaced
+                let mut output_is_input = in_place;
+
+                if size(&out_file) >= size(&p) {
+                    info!(
+                        "Keeping {}: it is already compressed at least as well",
+                        p.display()
+                    );
+                    let _ = remove_file(&out_file);
+                    output_is_input = true;
+                } else if in_place && !output_is_complete(&out_file) {
+                    failure = Some(format!(
+                        "{} couldn't be verified to be completely written",
+                        out_file.display()
+                    ));
+                } else if in_place {
+                    // the replacement is always checked first, the original is gone once it got replaced
+                    let _permit = io_limiter.as_ref().map(|l| l.acquire());
+
+                    match replace_verified(&out_file, &p, &options, &itrp) {
+                        Ok(i) => interrupted = i,
+                        Err(e) => failure = Some(e),
                     }
                 }
+
+                if output_is_input && !interrupted && failure.is_none() {
+                    let _ = remove_file(&marker);
+                    files.retain(|(f, _)| *f != p);
+                    files.retain(|(_, s)| *s != FileSource::Output);
+                    files.push((p.clone(), FileSource::Output));
+                    out_file = p.clone();
+                    output = p.clone();
+                    marker = partial_marker(&output);
+                }
             }
 
-            if !interrupted && format.zip() {
+            if !interrupted && failure.is_none() && format.zip() {
                 out_file = files
                     .iter()
                     .find(|(_, s)| *s == FileSource::Output)
@@ -410,73 +2440,888 @@ impl Converter {
                     })
                     .0;
 
-                if verbose {
-                    println!("Zipping {} to {}", temp_file.display(), out_file.display());
-                }
+                let _permit = io_limiter.as_ref().map(|l| l.acquire());
+
+                // only the trimmed part is zipped, so the file itself never gets modified
+                let length = if trim && format.contains(RomFormat::Nintendo64) {
+                    match trimmed_length(temp_file) {
+                        Ok(Some(length)) => {
+                            debug!("Trimming {} to {} bytes", temp_file.display(), length);
+                            length
+                        }
+                        Ok(None) => u64::MAX,
+                        Err(e) => {
+                            warn!("Couldn't trim {}: {}", temp_file.display(), e);
+                            u64::MAX
+                        }
+                    }
+                } else {
+                    u64::MAX
+                };
 
-                let mut ifh = File::open(&temp_file).unwrap();
-                let ofh = File::create(&out_file).unwrap();
+                debug!("Zipping {} to {}", temp_file.display(), out_file.display());
 
-                let mut zip = ZipWriter::new(ofh);
+                // returns whether zipping got interrupted
+                let zip_file = || -> Result<bool, String> {
+                    let mut ifh = File::open(temp_file)
+                        .map_err(|e| format!("Couldn't open {}: {}", temp_file.display(), e))?
+                        .take(length);
+                    let ofh = File::create(&out_file)
+                        .map_err(|e| format!("Couldn't create {}: {}", out_file.display(), e))?;
 
-                let _ = zip
-                    .start_file(
+                    let mut zip = ZipWriter::new(ofh);
+
+                    zip.start_file(
                         temp_file.file_name().unwrap().to_str().unwrap(),
-                        SimpleFileOptions::default()
-                            .compression_method(CompressionMethod::Deflated),
+                        options.rom_zip_entry(),
                     )
-                    .unwrap();
-
-                let mut buf = [0_u8; 1024 * 1024];
-
-                'reader: while let Ok(chunk) = ifh.read(&mut buf) {
-                    if chunk == 0 {
-                        break;
-                    }
+                    .map_err(|e| format!("Couldn't zip {}: {}", temp_file.display(), e))?;
 
-                    let mut offset: usize = 0;
+                    let mut buf = [0_u8; 1024 * 1024];
 
-                    while offset < chunk {
+                    loop {
                         if !itrp.is_empty() {
-                            interrupted = true;
-                            break 'reader;
+                            return Ok(true);
                         }
 
-                        let written = zip.write(&buf[offset..chunk]);
+                        let chunk = ifh
+                            .read(&mut buf)
+                            .map_err(|e| format!("Couldn't read {}: {}", temp_file.display(), e))?;
 
-                        if written.is_err() {
-                            break 'reader;
+                        if chunk == 0 {
+                            break;
                         }
 
-                        offset += written.unwrap();
+                        zip.write_all(&buf[..chunk])
+                            .map_err(|e| format!("Couldn't write {}: {}", out_file.display(), e))?;
+                    }
+
+                    zip.finish()
+                        .map_err(|e| format!("Couldn't write {}: {}", out_file.display(), e))?;
+
+                    Ok(false)
+                };
+
+                match zip_file() {
+                    Ok(i) => interrupted = i,
+                    Err(e) => failure = Some(e),
+                }
+            }
+
+            // a corrupt output must never replace its inputs. scrubbed discs can't be compared against
+            // their dumps anymore, so they are always checked by dolphin-tool
+            let scrubbed = options.rvz_scrub
+                && format.intersects(RomFormat::NintendoWii | RomFormat::NintendoGameCube);
+
+            // the original, kept or replaced after its replacement was checked, isn't checked again
+            if (rem || scrubbed) && !interrupted && failure.is_none() && out_file != p {
+                let _permit = io_limiter.as_ref().map(|l| l.acquire());
+
+                debug!("Verifying {}", out_file.display());
+
+                match verify_output(&out_file, &options, &itrp) {
+                    Ok(i) => interrupted = i,
+                    Err(e) => {
+                        warn!(
+                            "Keeping input files: {} failed the verification",
+                            out_file.display()
+                        );
+                        failure = Some(format!("The output failed the verification: {}", e));
                     }
                 }
+            }
 
-                let _ = zip.flush();
+            // a failed conversion leaves an incomplete output behind, just like an interrupted one
+            if failure.is_some() {
+                interrupted = true;
+            }
 
-                let _ = zip.finish();
+            let os = size_mode.of(&out_file).unwrap_or(0);
+
+            // recompressed sets and images already keep the original unless they got smaller
+            if min_savings.is_some_and(|min| {
+                !interrupted
+                    && out_file != p
+                    && is > 0
+                    && (is as f64 - os as f64) * 100f64 / (is as f64) < min
+            }) {
+                info!(
+                    "Skipping {}: {}, {} to {}. {}",
+                    origin.display(),
+                    SkipReason::InsufficientSavings,
+                    format_size(is, DECIMAL),
+                    format_size(os, DECIMAL),
+                    savings(is, os)
+                );
+
+                // just like an interrupted conversion, the output gets deleted and the inputs are kept
+                cleanup(files, false, true);
+                let _ = remove_file(&marker);
+
+                skipped_ptr
+                    .lock()
+                    .unwrap()
+                    .push((origin.clone(), SkipReason::InsufficientSavings));
+                notify(
+                    events.as_ref(),
+                    ConversionEvent::Skipped(origin.clone(), SkipReason::InsufficientSavings),
+                );
+                report_ptr.lock().unwrap().push(
+                    ReportEntry::new(
+                        origin.clone(),
+                        None,
+                        is,
+                        os,
+                        FileStatus::Skipped,
+                        Some(SkipReason::InsufficientSavings.to_string()),
+                    )
+                    .hashes(hashes),
+                );
+                return;
+            }
 
-                drop(ifh);
+            if preserve_xattrs && !interrupted {
+                if let Err(e) = copy_xattrs(&origin, &out_file) {
+                    warn!(
+                        "Couldn't copy extended attributes to {}: {}",
+                        out_file.display(),
+                        e
+                    );
+                }
             }
 
-            let os = out_file.size_on_disk().unwrap_or(0);
+            cleanup(files, rem, interrupted);
 
-            cleanup(files, rem, interrupted, verbose);
+            // incomplete outputs got deleted by the cleanup
+            if interrupted || output_is_complete(&output) {
+                let _ = remove_file(&marker);
+            }
 
             if flatten && !interrupted {
-                flatten_directories(&out_file, &root, verbose);
+                out_file = flatten_directories(&out_file, &output_root);
+            }
+
+            // a read-only output must neither keep the inputs nor the marker around,
+            // so the permissions are only changed once the output is in place for good
+            if (output_mode.is_some() || output_owner.is_some()) && !interrupted {
+                if let Err(e) = apply_permissions(&out_file, output_mode, output_owner) {
+                    warn!(
+                        "Couldn't change the permissions of {}: {}",
+                        out_file.display(),
+                        e
+                    );
+                }
+            }
+
+            if let Some(action) = dupe_action.filter(|_| !interrupted) {
+                match sha1_file(&out_file) {
+                    Ok(hash) => {
+                        let existing = {
+                            let mut hashes = hashes_ptr.lock().unwrap();
+                            match hashes.get(&hash) {
+                                Some(existing) => Some(existing.clone()),
+                                None => {
+                                    hashes.insert(hash, out_file.clone());
+                                    None
+                                }
+                            }
+                        };
+
+                        if let Some(existing) = existing {
+                            handle_duplicate_output(&out_file, &existing, action);
+                        }
+                    }
+                    Err(e) => warn!("Error hashing {}: {:?}", out_file.display(), e),
+                }
+            }
+
+            if let Some(client) = s3.as_ref().filter(|_| !interrupted) {
+                let key = client.key(&out_file, &output_root);
+
+                if let Err(e) = key.as_ref() {
+                    warn!("Not uploading {}: {}", out_file.display(), e);
+                } else if !output_is_complete(&out_file) {
+                    warn!(
+                        "Not uploading {}: It couldn't be verified to be completely written",
+                        out_file.display()
+                    );
+                } else if let Ok(key) = key {
+                    let _permit = io_limiter.as_ref().map(|l| l.acquire());
+
+                    debug!("Uploading {} to {}", out_file.display(), key);
+
+                    match client.upload(&out_file, &key) {
+                        Ok(()) if s3_remove_local => {
+                            debug!("Deleting uploaded output file {}", out_file.display());
+
+                            let _ = remove_file(&out_file);
+                        }
+                        Ok(()) => (),
+                        Err(e) => warn!("Error uploading {}: {}", out_file.display(), e),
+                    }
+                }
             }
 
             if !interrupted {
-                println!("Finished compression of {}", out_file.display());
+                info!(
+                    "Finished compression of {}: {} to {}. {}",
+                    out_file.display(),
+                    format_size(is, DECIMAL),
+                    format_size(os, DECIMAL),
+                    savings(is, os)
+                );
                 is_ptr.fetch_add(is.try_into().unwrap(), Ordering::Relaxed);
                 os_ptr.fetch_add(os.try_into().unwrap(), Ordering::Relaxed);
                 p_ptr.fetch_add(1, Ordering::Relaxed);
+                report_ptr.lock().unwrap().push(
+                    ReportEntry::new(
+                        origin.clone(),
+                        Some(out_file.clone()),
+                        is,
+                        os,
+                        FileStatus::Processed,
+                        None,
+                    )
+                    .hashes(hashes),
+                );
+                if let (Some(state), Some(stamp)) = (state.as_ref(), stamp) {
+                    state.complete(stamp, &out_file);
+                }
+
+                let record = ConversionRecord {
+                    input: origin.clone(),
+                    output: out_file,
+                    format,
+                    input_size: is,
+                    output_size: os,
+                    tool: tool.filter(|_| best_of && !format.alternative_tools().is_empty()),
+                };
+
+                notify(events.as_ref(), ConversionEvent::Finished(record.clone()));
+                records_ptr.lock().unwrap().push(record);
+            } else if let Some(e) = failure {
+                error!("Failed compression of {}: {}", origin.display(), e);
+                f_ptr.lock().unwrap().push((origin.clone(), e.clone()));
+                notify(
+                    events.as_ref(),
+                    ConversionEvent::Failed(origin.clone(), e.clone()),
+                );
+                report_ptr.lock().unwrap().push(
+                    ReportEntry::new(
+                        origin.clone(),
+                        Some(out_file),
+                        is,
+                        0,
+                        FileStatus::Failed,
+                        Some(e),
+                    )
+                    .hashes(hashes),
+                );
             } else {
-                println!("Aborted compression of {}", out_file.display());
+                info!("Aborted compression of {}", out_file.display());
+                notify(events.as_ref(), ConversionEvent::Aborted(origin.clone()));
+                report_ptr.lock().unwrap().push(
+                    ReportEntry::new(
+                        origin.clone(),
+                        Some(out_file),
+                        is,
+                        0,
+                        FileStatus::Aborted,
+                        Some("the conversion was interrupted".to_string()),
+                    )
+                    .hashes(hashes),
+                );
             }
-
-            t_ptr.fetch_sub(1, Ordering::Relaxed);
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::write;
+
+    #[test]
+    fn replace_extension_replaces_only_the_last_extension() {
+        for (file, output) in [
+            ("/roms/Game.CUE", "/roms/Game.chd"),
+            ("/roms/game.cue.txt", "/roms/game.chd"),
+            ("/roms/Game.Cue.TXT", "/roms/Game.chd"),
+            ("/roms/Game (USA).iso", "/roms/Game (USA).chd"),
+            (
+                "/roms/Game v1.1 (USA).Disc 1.ISO",
+                "/roms/Game v1.1 (USA).Disc 1.chd",
+            ),
+            ("/roms/Game.ISO.bak", "/roms/Game.ISO.chd"),
+            ("/roms/notes.txt", "/roms/notes.chd"),
+            ("/roms/Game", "/roms/Game.chd"),
+        ] {
+            assert_eq!(
+                replace_extension(Path::new(file), "chd"),
+                PathBuf::from(output),
+                "{}",
+                file
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn replace_extension_keeps_non_utf8_names() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let file = Path::new("/roms").join(OsStr::from_bytes(b"Game \xff.iso"));
+
+        assert_eq!(
+            replace_extension(&file, "rvz"),
+            Path::new("/roms").join(OsStr::from_bytes(b"Game \xff.rvz"))
+        );
+    }
+
+    #[test]
+    fn partial_marker_is_next_to_the_output() {
+        assert_eq!(
+            partial_marker(Path::new("/roms/Game (USA).chd")),
+            PathBuf::from("/roms/Game (USA).chd.romcomp-partial")
+        );
+    }
+
+    #[test]
+    fn failed_verification_keeps_the_original_set() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_sender, interrupt) = crossbeam_channel::unbounded();
+        let original = dir.path().join("neogeo.zip");
+        let mut zip = ZipWriter::new(File::create(&original).unwrap());
+
+        zip.start_file("sp-s2.sp1", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(&b"BIOS".repeat(64)).unwrap();
+        zip.finish().unwrap();
+
+        let contents = std::fs::read(&original).unwrap();
+        let output = append_extension(&original, "romcomp-arcade");
+
+        write(&output, b"output").unwrap();
+
+        assert!(replace_verified(
+            &output,
+            &original,
+            &CompressionOptions::default(),
+            &interrupt
+        )
+        .unwrap_err()
+        .starts_with("The output failed the verification"));
+        assert_eq!(std::fs::read(&original).unwrap(), contents);
+        assert!(output.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn zst_output_keeps_a_non_utf8_name() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join(OsStr::from_bytes(b"Pok\xe9mon.gba"));
+        let converter = converter(dir.path(), CompressionOptions::default());
+
+        assert_eq!(
+            converter.get_output_file_name(&file, RomFormat::ZST),
+            Some(dir.path().join(OsStr::from_bytes(b"Pok\xe9mon.gba.zst")))
+        );
+    }
+
+    #[test]
+    fn incomplete_output_of_an_aborted_run_is_deleted() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("game.chd");
+
+        write(&output, b"half").unwrap();
+        write(partial_marker(&output), b"").unwrap();
+
+        assert!(discard_incomplete_output(&output, false));
+        assert!(!output.exists());
+        assert!(!partial_marker(&output).exists());
+    }
+
+    #[test]
+    fn complete_output_is_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("game.chd");
+
+        write(&output, b"complete").unwrap();
+
+        assert!(!discard_incomplete_output(&output, false));
+        assert!(output.is_file());
+    }
+
+    #[test]
+    fn dry_run_keeps_incomplete_output() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("game.chd");
+
+        write(&output, b"half").unwrap();
+        write(partial_marker(&output), b"").unwrap();
+
+        assert!(discard_incomplete_output(&output, true));
+        assert!(output.is_file());
+        assert!(partial_marker(&output).is_file());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn read_only_output_is_complete() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("game.chd");
+
+        write(&output, b"complete").unwrap();
+        std::fs::set_permissions(&output, std::fs::Permissions::from_mode(0o444)).unwrap();
+
+        assert!(output_is_complete(&output));
+    }
+
+    #[test]
+    fn empty_or_missing_output_is_incomplete() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("game.chd");
+
+        assert!(!output_is_complete(&output));
+
+        write(&output, b"").unwrap();
+
+        assert!(!output_is_complete(&output));
+    }
+
+    /// a converter for the files below the root, which never gets interrupted
+    fn converter(root: &Path, options: CompressionOptions) -> Converter {
+        let (_, interrupt) = crossbeam_channel::unbounded();
+
+        Converter::new(
+            &[root.to_path_buf()],
+            tempfile::tempdir().unwrap(),
+            1,
+            interrupt,
+        )
+        .compression_options(options)
+    }
+
+    #[test]
+    fn failed_staging_fails_the_conversion() {
+        let dir = tempfile::tempdir().unwrap();
+        let cue = dir.path().join("game.cue.txt");
+        write(
+            &cue,
+            "FILE \"game.bin\" BINARY\n  TRACK 01 MODE2/2352\n    INDEX 01 00:00:00\n",
+        )
+        .unwrap();
+        write(dir.path().join("game.bin"), vec![0; 2352 * 16]).unwrap();
+        // the cue sheet gets staged as game.cue, which can't be written over a directory
+        create_dir_all(dir.path().join("game.cue")).unwrap();
+
+        let converter = converter(dir.path(), CompressionOptions::default());
+        converter.convert(&cue, guess_file(&cue).unwrap());
+        converter.finish();
+
+        let failed = converter.failed_files.lock().unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].0, cue);
+        assert!(
+            failed[0].1.starts_with("Failed to stage temp copy"),
+            "{}",
+            failed[0].1
+        );
+        assert!(dir.path().join("game.cue").is_dir());
+        assert!(!dir.path().join("game.chd").exists());
+    }
+
+    /// a cue sheet with three tracks, all of them stored in the same bin file
+    fn single_bin_cue(dir: &Path) -> PathBuf {
+        let cue = dir.join("game.cue");
+        write(
+            &cue,
+            "FILE \"game.bin\" BINARY\n\
+             \x20 TRACK 01 MODE2/2352\n\
+             \x20   INDEX 01 00:00:00\n\
+             \x20 TRACK 02 AUDIO\n\
+             \x20   INDEX 00 00:02:00\n\
+             \x20   INDEX 01 00:04:00\n\
+             \x20 TRACK 03 AUDIO\n\
+             \x20   INDEX 01 00:06:00\n",
+        )
+        .unwrap();
+        write(dir.join("game.bin"), vec![0; 2352 * 75 * 8]).unwrap();
+        cue
+    }
+
+    #[test]
+    fn bin_shared_by_tracks_is_an_input_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let cue = single_bin_cue(dir.path());
+
+        assert_eq!(
+            Converter::input_files(&cue, guess_file(&cue).unwrap()),
+            [cue.clone(), dir.path().join("game.bin")]
+        );
+    }
+
+    /// options running a shell script instead of the tool, which writes a few bytes to the path after -o
+    /// if it creates an output at all
+    #[cfg(unix)]
+    fn stub_tool(dir: &Path, tool: CompressionTool, creates_output: bool) -> CompressionOptions {
+        use std::os::unix::fs::PermissionsExt;
+
+        let binary = dir.join(format!("stub-{}", tool));
+        let script = if creates_output {
+            "#!/bin/sh\nwhile [ $# -gt 0 ]; do [ \"$1\" = -o ] && echo output > \"$2\"; shift; done\n"
+        } else {
+            "#!/bin/sh\n"
+        };
+        write(&binary, script).unwrap();
+        std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut options = CompressionOptions::default();
+        options
+            .binaries
+            .insert(tool, binary.to_str().unwrap().to_string());
+        options
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn bin_shared_by_tracks_is_counted_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let cue = single_bin_cue(dir.path());
+        let options = stub_tool(dir.path(), CompressionTool::Chdman, true);
+        let converter = converter(dir.path(), options).size_mode(SizeMode::Logical);
+        converter.convert(&cue, guess_file(&cue).unwrap());
+        converter.finish();
+
+        let size = cue.metadata().unwrap().len() + 2352 * 75 * 8;
+        assert_eq!(converter.records.lock().unwrap()[0].input_size, size);
+        assert_eq!(
+            converter.input_file_size.load(Ordering::Relaxed),
+            size as usize
+        );
+    }
+
+    #[test]
+    fn maxcso_gets_the_output_explicitly() {
+        let args = CompressionTool::MaxCSO.arguments(
+            Path::new("/roms/Game.ISO"),
+            Path::new("/roms/Game.cso"),
+            &CompressionOptions::default(),
+        );
+
+        assert!(args
+            .windows(2)
+            .any(|a| a[0] == "-o" && a[1] == "/roms/Game.cso"));
+    }
+
+    /// converts a PSP iso with a stand-in maxcso
+    #[cfg(unix)]
+    fn convert_psp(creates_output: bool) -> (tempfile::TempDir, Converter) {
+        let dir = tempfile::tempdir().unwrap();
+        let iso = dir.path().join("Game.ISO");
+        File::create(&iso).unwrap().set_len(2048 * 17).unwrap();
+
+        let options = stub_tool(dir.path(), CompressionTool::MaxCSO, creates_output);
+        let converter = converter(dir.path(), options);
+        converter.convert(&iso, RomFormat::ISO | RomFormat::PlayStationPortable);
+        converter.finish();
+
+        (dir, converter)
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn planned_output_is_recorded() {
+        let (dir, converter) = convert_psp(true);
+        let records = converter.records.lock().unwrap();
+
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].output, dir.path().join("Game.cso"));
+        assert!(records[0].output.is_file());
+        assert!(records[0].output_size > 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn missing_output_fails_the_conversion() {
+        let (dir, converter) = convert_psp(false);
+        let failed = converter.failed_files.lock().unwrap();
+
+        assert!(converter.records.lock().unwrap().is_empty());
+        assert_eq!(failed.len(), 1);
+        assert!(
+            failed[0].1.ends_with(&format!(
+                "didn't create {}",
+                dir.path().join("Game.cso").display()
+            )),
+            "{}",
+            failed[0].1
+        );
+    }
+
+    /// converts a single file with the internal n64 backend, returns the names and contents of the entries of the zip
+    fn zip_n64(name: &str, rom: &[u8]) -> (PathBuf, Vec<(String, Vec<u8>)>) {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join(name);
+        write(&input, rom).unwrap();
+
+        let mut options = CompressionOptions::default();
+        options.internal.insert(CompressionTool::Rom64);
+        let converter = converter(dir.path(), options);
+
+        converter.convert(&input, guess_file(&input).unwrap());
+        converter.finish();
+
+        let output = replace_extension(&input, "zip");
+        let mut zip = zip::ZipArchive::new(File::open(&output).unwrap()).unwrap();
+        let entries = (0..zip.len())
+            .map(|i| {
+                let mut entry = zip.by_index(i).unwrap();
+                let mut contents = vec![];
+                entry.read_to_end(&mut contents).unwrap();
+                (entry.name().to_string(), contents)
+            })
+            .collect();
+
+        // the temporary z64 mustn't end up next to the input
+        assert!(!input.with_extension("z64").exists());
+
+        (output.file_name().unwrap().into(), entries)
+    }
+
+    /// a tiny rom in big endian byte order, the first word is the magic of the header
+    fn z64_rom() -> Vec<u8> {
+        let mut rom = vec![0x80, 0x37, 0x12, 0x40];
+        rom.extend((0..1020).map(|i| i as u8));
+        rom
+    }
+
+    #[test]
+    fn v64_and_n64_roms_are_zipped_as_z64() {
+        let z64 = z64_rom();
+        let v64 = z64
+            .chunks_exact(2)
+            .flat_map(|w| [w[1], w[0]])
+            .collect::<Vec<_>>();
+        let n64 = z64
+            .chunks_exact(4)
+            .flat_map(|w| [w[3], w[2], w[1], w[0]])
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            zip_n64("Game (USA).v64", &v64),
+            (
+                "Game (USA).zip".into(),
+                vec![("Game (USA).z64".to_string(), z64.clone())]
+            )
+        );
+        assert_eq!(
+            zip_n64("Game (USA).n64", &n64),
+            (
+                "Game (USA).zip".into(),
+                vec![("Game (USA).z64".to_string(), z64)]
+            )
+        );
+    }
+
+    #[test]
+    fn ndd_disks_are_zipped_as_they_are() {
+        let disk = (0..4096).map(|i| (i * 7) as u8).collect::<Vec<_>>();
+
+        assert_eq!(
+            zip_n64("Game (Japan).ndd", &disk),
+            (
+                "Game (Japan).zip".into(),
+                vec![("Game (Japan).ndd".to_string(), disk)]
+            )
+        );
+    }
+
+    /// the chdman command line for a PS2 iso of the given size
+    fn chdman_command(size: u64) -> String {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("game.iso");
+        File::create(&input).unwrap().set_len(size).unwrap();
+
+        let options = CompressionOptions {
+            chd_hunk_size: Some(2448 * 8),
+            ..Default::default()
+        };
+
+        converter(dir.path(), options)
+            .command_line(&input, RomFormat::ISO | RomFormat::PlayStation2)
+            .unwrap()
+    }
+
+    #[test]
+    fn cd_images_are_converted_with_createcd() {
+        let command = chdman_command(2048 * 17);
+
+        assert!(command.starts_with("chdman createcd "), "{}", command);
+        assert!(command.contains(" -hs 19584"), "{}", command);
+    }
+
+    #[test]
+    fn dvd_images_are_converted_with_createdvd() {
+        // larger than any CD, the file is sparse
+        let command = chdman_command(2048 * 99 * 60 * 75 + 2048);
+
+        assert!(command.starts_with("chdman createdvd "), "{}", command);
+        assert!(!command.contains("-hs"), "{}", command);
+    }
+
+    #[test]
+    fn flatten_moves_outputs_below_the_output_root() {
+        let input_root = tempfile::tempdir().unwrap();
+        let output_root = tempfile::tempdir().unwrap();
+        let rom = input_root.path().join("Game (USA)").join("Game (USA).z64");
+        create_dir_all(rom.parent().unwrap()).unwrap();
+        write(&rom, z64_rom()).unwrap();
+
+        let converter = converter(input_root.path(), CompressionOptions::default())
+            .output_root(Some(output_root.path().to_path_buf()))
+            .flatten(true)
+            .remove_after_compression(true);
+        converter.convert(&rom, guess_file(&rom).unwrap());
+        converter.finish();
+
+        assert!(!converter.had_failures());
+        assert!(output_root.path().join("Game (USA).zip").is_file());
+        assert!(!output_root.path().join("Game (USA)").exists());
+        // the input directory is emptied by --remove, but flatten only works below the output root
+        assert!(!rom.exists());
+        assert!(input_root.path().join("Game (USA)").is_dir());
+    }
+
+    #[test]
+    fn flatten_keeps_outputs_of_the_same_name() {
+        let root = tempfile::tempdir().unwrap();
+        let roms = ["Disc A", "Disc B"].map(|dir| root.path().join(dir).join("Game.z64"));
+        let converter = converter(root.path(), CompressionOptions::default())
+            .flatten(true)
+            .remove_after_compression(true);
+
+        for (i, rom) in roms.iter().enumerate() {
+            let mut contents = z64_rom();
+            contents[4] = i as u8;
+            create_dir_all(rom.parent().unwrap()).unwrap();
+            write(rom, contents).unwrap();
+        }
+
+        for rom in roms.iter() {
+            converter.convert(rom, guess_file(rom).unwrap());
+            converter.finish();
+        }
+
+        // the first output gets flattened, the second one is kept in its directory instead of replacing it
+        let first = root.path().join("Game.zip");
+        let second = root.path().join("Disc B").join("Game.zip");
+        assert!(!converter.had_failures());
+        assert!(!root.path().join("Disc A").exists());
+
+        for (i, output) in [first, second].iter().enumerate() {
+            let mut zip = zip::ZipArchive::new(File::open(output).unwrap()).unwrap();
+            let mut contents = vec![];
+            zip.by_name("Game.z64")
+                .unwrap()
+                .read_to_end(&mut contents)
+                .unwrap();
+
+            assert_eq!(contents[4], i as u8, "{}", output.display());
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failed_verification_keeps_the_replaced_original() {
+        let dir = tempfile::tempdir().unwrap();
+        let iso = dir.path().join("Game.iso");
+        let cso = dir.path().join("Game.cso");
+        File::create(&iso).unwrap().set_len(2048 * 17).unwrap();
+        let (_sender, interrupt) = crossbeam_channel::unbounded();
+        crate::cso::compress_cso(&iso, &cso, 1, &interrupt).unwrap();
+        remove_file(&iso).unwrap();
+        let original = std::fs::read(&cso).unwrap();
+
+        // the stand-in maxcso writes a few bytes, smaller than the original but no cso image
+        let options = stub_tool(dir.path(), CompressionTool::MaxCSO, true);
+        let converter = converter(dir.path(), options)
+            .recompress(true)
+            .remove_after_compression(true);
+        converter.convert(&cso, guess_file(&cso).unwrap());
+        converter.finish();
+
+        let failed = converter.failed_files.lock().unwrap();
+        assert_eq!(failed.len(), 1);
+        assert!(
+            failed[0]
+                .1
+                .starts_with("The output failed the verification"),
+            "{}",
+            failed[0].1
+        );
+        assert_eq!(std::fs::read(&cso).unwrap(), original);
+        assert!(!dir.path().join("Game.cso.romcomp-cso").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn truncated_output_below_another_root_keeps_the_input() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        // stands in for another disk, the output is written there instead of being moved
+        let output_root = tempfile::tempdir().unwrap();
+        let iso = dir.path().join("Game.iso");
+        File::create(&iso).unwrap().set_len(2048 * 17).unwrap();
+
+        // the stand-in maxcso gets cut off before it wrote anything to the output
+        let binary = dir.path().join("stub-maxcso");
+        write(
+            &binary,
+            "#!/bin/sh\nwhile [ $# -gt 0 ]; do [ \"$1\" = -o ] && : > \"$2\"; shift; done\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let mut options = CompressionOptions::default();
+        options.binaries.insert(
+            CompressionTool::MaxCSO,
+            binary.to_str().unwrap().to_string(),
+        );
+
+        let converter = converter(dir.path(), options)
+            .output_root(Some(output_root.path().to_path_buf()))
+            .remove_after_compression(true);
+        converter.convert(&iso, RomFormat::ISO | RomFormat::PlayStationPortable);
+        converter.finish();
+
+        assert_eq!(converter.failed_files.lock().unwrap().len(), 1);
+        assert_eq!(iso.metadata().unwrap().len(), 2048 * 17);
+        assert!(!output_root.path().join("Game.cso").exists());
+    }
+
+    #[test]
+    fn internal_recompression_leaves_no_decompressed_image_behind() {
+        let dir = tempfile::tempdir().unwrap();
+        let iso = dir.path().join("Game.iso");
+        let cso = dir.path().join("Game.cso");
+        File::create(&iso).unwrap().set_len(2048 * 17).unwrap();
+        let (_sender, interrupt) = crossbeam_channel::unbounded();
+        crate::cso::compress_cso(&iso, &cso, 1, &interrupt).unwrap();
+        remove_file(&iso).unwrap();
+
+        let mut options = CompressionOptions::default();
+        options.internal.insert(CompressionTool::MaxCSO);
+        let converter = converter(dir.path(), options).recompress(true);
+        converter.convert(&cso, guess_file(&cso).unwrap());
+        converter.finish();
+
+        assert!(converter.failed_files.lock().unwrap().is_empty());
+        assert!(cso.is_file());
+        assert_eq!(converter.temp_dir.path().read_dir().unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,411 @@
+use clap::ValueEnum;
+use crossbeam_channel::Receiver;
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+use std::{
+    fs::File,
+    io::{self, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+/// the implementation used to create cso files
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum CsoBackend {
+    /// compress with maxcso
+    Maxcso,
+    /// compress with romcomp's own encoder, which doesn't need any external tools
+    Internal,
+}
+
+/// the formats maxcso can create
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum CsoFormat {
+    /// CSO v1, deflated blocks. the only format the internal encoder creates and every emulator reads
+    #[default]
+    Cso1,
+    /// CSO v2, deflated or lz4 compressed blocks
+    Cso2,
+    /// lz4 compressed blocks, faster to decompress than cso but larger
+    Zso,
+    /// the older dax format with deflated blocks of 8 KiB
+    Dax,
+}
+
+impl CsoFormat {
+    /// the extension of the files of this format
+    pub fn extension(self) -> &'static str {
+        match self {
+            CsoFormat::Cso1 | CsoFormat::Cso2 => "cso",
+            CsoFormat::Zso => "zso",
+            CsoFormat::Dax => "dax",
+        }
+    }
+
+    /// the name of the format as maxcso's --format takes it
+    pub fn maxcso_name(self) -> &'static str {
+        match self {
+            CsoFormat::Cso1 => "cso1",
+            CsoFormat::Cso2 => "cso2",
+            CsoFormat::Zso => "zso",
+            CsoFormat::Dax => "dax",
+        }
+    }
+}
+
+pub const CSO_MAGIC: &[u8; 4] = b"CISO";
+/// the magic of dax images, an older compressed format for PSP games which maxcso can read as well
+pub const DAX_MAGIC: &[u8; 4] = b"DAX\0";
+/// the magic of zso images, which share the header of cso files but hold lz4 compressed blocks
+pub const ZSO_MAGIC: &[u8; 4] = b"ZISO";
+const CSO_HEADER_SIZE: u32 = 0x18;
+const CSO_BLOCK_SIZE: u32 = 2048;
+/// index entries with this bit set point to a block which is stored uncompressed
+const CSO_PLAIN_BLOCK: u32 = 0x80000000;
+
+/// compresses an iso into a CSO v1 file, the format maxcso produces by default.
+/// every 2048 byte block gets deflated on its own and is stored uncompressed if that doesn't save any space.
+/// returns whether the compression got interrupted
+pub fn compress_cso(
+    input: &Path,
+    output: &Path,
+    level: u32,
+    interrupt: &Receiver<()>,
+) -> io::Result<bool> {
+    let mut reader = File::open(input)?;
+    let total_bytes = reader.metadata()?.len();
+    let blocks = total_bytes.div_ceil(CSO_BLOCK_SIZE as u64);
+    let index_size = (blocks + 1) * 4;
+
+    // the index stores block offsets shifted by the alignment, which must fit into 31 bits
+    let mut align = 0u8;
+    while (CSO_HEADER_SIZE as u64 + index_size + total_bytes + (blocks << align)) >> align
+        >= CSO_PLAIN_BLOCK as u64
+    {
+        align += 1;
+    }
+
+    let mut writer = BufWriter::new(File::create(output)?);
+
+    writer.write_all(CSO_MAGIC)?;
+    writer.write_all(&CSO_HEADER_SIZE.to_le_bytes())?;
+    writer.write_all(&total_bytes.to_le_bytes())?;
+    writer.write_all(&CSO_BLOCK_SIZE.to_le_bytes())?;
+    writer.write_all(&[1, align, 0, 0])?;
+    // placeholder for the index, which gets written once all block offsets are known
+    writer.write_all(&vec![0; index_size as usize])?;
+
+    let mut index: Vec<u32> = Vec::with_capacity(blocks as usize + 1);
+    let mut position = CSO_HEADER_SIZE as u64 + index_size;
+    let mut block = vec![0; CSO_BLOCK_SIZE as usize];
+
+    for _ in 0..blocks {
+        if !interrupt.is_empty() {
+            return Ok(true);
+        }
+
+        let padding = position.next_multiple_of(1 << align) - position;
+        writer.write_all(&vec![0; padding as usize])?;
+        position += padding;
+
+        // the last block of an image which isn't a multiple of the block size gets padded with zeroes
+        block.fill(0);
+        let mut read = 0;
+        while read < block.len() {
+            match reader.read(&mut block[read..])? {
+                0 => break,
+                n => read += n,
+            }
+        }
+
+        let mut encoder = DeflateEncoder::new(vec![], Compression::new(level));
+        encoder.write_all(&block)?;
+        let compressed = encoder.finish()?;
+
+        if compressed.len() < block.len() {
+            index.push((position >> align) as u32);
+            writer.write_all(&compressed)?;
+            position += compressed.len() as u64;
+        } else {
+            index.push((position >> align) as u32 | CSO_PLAIN_BLOCK);
+            writer.write_all(&block)?;
+            position += block.len() as u64;
+        }
+    }
+
+    // the final entry marks the end of the last block
+    index.push((position >> align) as u32);
+
+    writer.seek(SeekFrom::Start(CSO_HEADER_SIZE as u64))?;
+    for entry in index {
+        writer.write_all(&entry.to_le_bytes())?;
+    }
+    writer.flush()?;
+
+    Ok(false)
+}
+
+/// decompresses a CSO v1 file back into the original iso, e.g. one created by compress_cso or maxcso.
+/// returns whether the decompression got interrupted
+pub fn decompress_cso(input: &Path, output: &Path, interrupt: &Receiver<()>) -> io::Result<bool> {
+    let mut writer = BufWriter::new(File::create(output)?);
+    let interrupted = decode_cso(input, &mut writer, interrupt)?;
+    writer.flush()?;
+
+    Ok(interrupted)
+}
+
+/// checks that every block of a CSO v1 file can be decompressed. returns whether the check got interrupted
+pub fn verify_cso(input: &Path, interrupt: &Receiver<()>) -> io::Result<bool> {
+    decode_cso(input, &mut io::sink(), interrupt)
+}
+
+/// checks the header of the formats only maxcso can read, the magic has to match
+/// and the image has to hold some data. the blocks themselves aren't checked
+pub fn verify_cso_header(input: &Path, format: CsoFormat) -> io::Result<()> {
+    let invalid = |message: &str| {
+        io::Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "{} isn't a {} file: {}",
+                input.display(),
+                format.extension(),
+                message
+            ),
+        )
+    };
+
+    let mut header = [0; CSO_HEADER_SIZE as usize];
+    File::open(input)?
+        .read_exact(&mut header)
+        .map_err(|_| invalid("too short"))?;
+
+    // dax images store the size of the image as 32 bit number right after the magic
+    let (magic, total_bytes) = match format {
+        CsoFormat::Dax => (
+            DAX_MAGIC,
+            u32::from_le_bytes(header[4..8].try_into().unwrap()) as u64,
+        ),
+        CsoFormat::Zso => (
+            ZSO_MAGIC,
+            u64::from_le_bytes(header[8..16].try_into().unwrap()),
+        ),
+        CsoFormat::Cso2 => (
+            CSO_MAGIC,
+            u64::from_le_bytes(header[8..16].try_into().unwrap()),
+        ),
+        CsoFormat::Cso1 => return Err(invalid("CSO v1 files get decompressed to check them")),
+    };
+
+    if &header[..4] != magic {
+        return Err(invalid("the magic is missing"));
+    }
+
+    if format == CsoFormat::Cso2 && header[20] != 2 {
+        return Err(invalid(&format!("version {} isn't CSO v2", header[20])));
+    }
+
+    if total_bytes == 0 {
+        return Err(invalid("the image is empty"));
+    }
+
+    Ok(())
+}
+
+fn decode_cso(input: &Path, writer: &mut impl Write, interrupt: &Receiver<()>) -> io::Result<bool> {
+    let invalid = |message: &str| {
+        io::Error::new(
+            ErrorKind::InvalidData,
+            format!("{} isn't a cso file: {}", input.display(), message),
+        )
+    };
+
+    let mut reader = File::open(input)?;
+    let mut header = [0; CSO_HEADER_SIZE as usize];
+    reader
+        .read_exact(&mut header)
+        .map_err(|_| invalid("too short"))?;
+
+    if &header[..4] != CSO_MAGIC {
+        return Err(invalid("the magic is missing"));
+    }
+
+    if header[20] > 1 {
+        return Err(invalid(&format!("version {} isn't supported", header[20])));
+    }
+
+    let total_bytes = u64::from_le_bytes(header[8..16].try_into().unwrap());
+    let block_size = u32::from_le_bytes(header[16..20].try_into().unwrap()) as u64;
+    let align = header[21];
+
+    if block_size == 0 {
+        return Err(invalid("the block size is 0"));
+    }
+
+    let blocks = total_bytes.div_ceil(block_size);
+    let mut index = vec![0; (blocks as usize + 1) * 4];
+    reader
+        .read_exact(&mut index)
+        .map_err(|_| invalid("the index is truncated"))?;
+    let index = index
+        .chunks_exact(4)
+        .map(|e| u32::from_le_bytes(e.try_into().unwrap()))
+        .collect::<Vec<_>>();
+
+    let mut compressed = vec![];
+    let mut block = vec![0; block_size as usize];
+
+    for i in 0..blocks as usize {
+        if !interrupt.is_empty() {
+            return Ok(true);
+        }
+
+        let start = ((index[i] & !CSO_PLAIN_BLOCK) as u64) << align;
+        let end = ((index[i + 1] & !CSO_PLAIN_BLOCK) as u64) << align;
+        // the last block only holds the rest of the image, the padding behind it gets dropped
+        let length = block_size.min(total_bytes - i as u64 * block_size) as usize;
+
+        if end < start {
+            return Err(invalid("the index is damaged"));
+        }
+
+        compressed.resize((end - start) as usize, 0);
+        reader.seek(SeekFrom::Start(start))?;
+        reader
+            .read_exact(&mut compressed)
+            .map_err(|_| invalid("a block is truncated"))?;
+
+        if index[i] & CSO_PLAIN_BLOCK != 0 {
+            if compressed.len() < length {
+                return Err(invalid("a block is truncated"));
+            }
+            writer.write_all(&compressed[..length])?;
+        } else {
+            DeflateDecoder::new(compressed.as_slice())
+                .read_exact(&mut block[..length])
+                .map_err(|_| invalid("a block can't be decompressed"))?;
+            writer.write_all(&block[..length])?;
+        }
+    }
+
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam_channel::unbounded;
+    use std::fs::{read, write};
+
+    /// an image whose blocks compress well, don't compress at all and end in a partial block
+    fn image() -> Vec<u8> {
+        let mut state = 0x12345678_u32;
+        let noise = (0..CSO_BLOCK_SIZE).map(|_| {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            (state >> 24) as u8
+        });
+
+        let mut image = vec![0; CSO_BLOCK_SIZE as usize];
+        image.extend(noise);
+        image.extend(b"PSP GAME".repeat(100));
+        image
+    }
+
+    #[test]
+    fn compressed_images_decompress_to_the_original() {
+        let dir = tempfile::tempdir().unwrap();
+        let iso = dir.path().join("game.iso");
+        let cso = dir.path().join("game.cso");
+        let extracted = dir.path().join("extracted.iso");
+        write(&iso, image()).unwrap();
+        let (_sender, interrupt) = unbounded();
+
+        assert!(!compress_cso(&iso, &cso, 9, &interrupt).unwrap());
+
+        let compressed = read(&cso).unwrap();
+        assert_eq!(&compressed[..4], CSO_MAGIC);
+        assert_eq!(
+            u64::from_le_bytes(compressed[8..16].try_into().unwrap()),
+            image().len() as u64
+        );
+        assert_eq!(compressed[20], 1);
+
+        // the noise is stored as it is, the other blocks deflated
+        let index = |i: usize| {
+            let offset = CSO_HEADER_SIZE as usize + i * 4;
+            u32::from_le_bytes(compressed[offset..offset + 4].try_into().unwrap())
+        };
+        assert_eq!(index(0) & CSO_PLAIN_BLOCK, 0);
+        assert_ne!(index(1) & CSO_PLAIN_BLOCK, 0);
+        assert_eq!(index(2) & CSO_PLAIN_BLOCK, 0);
+
+        assert!(!verify_cso(&cso, &interrupt).unwrap());
+        assert!(!decompress_cso(&cso, &extracted, &interrupt).unwrap());
+        assert_eq!(read(&extracted).unwrap(), image());
+    }
+
+    #[test]
+    fn aligned_images_of_other_encoders_are_read() {
+        // two blocks of 4 KiB, aligned to 4 bytes like maxcso does for large images
+        let first = vec![0xAB_u8; 4096];
+        let second = (0..4096).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+        encoder.write_all(&first).unwrap();
+        let deflated = encoder.finish().unwrap();
+
+        let align = 2;
+        let mut cso = CSO_MAGIC.to_vec();
+        cso.extend(CSO_HEADER_SIZE.to_le_bytes());
+        cso.extend(8192_u64.to_le_bytes());
+        cso.extend(4096_u32.to_le_bytes());
+        cso.extend([1, align, 0, 0]);
+
+        let first_offset = (CSO_HEADER_SIZE as usize + 3 * 4).next_multiple_of(4);
+        let second_offset = (first_offset + deflated.len()).next_multiple_of(4);
+        let end = second_offset + second.len();
+        cso.extend(((first_offset >> align) as u32).to_le_bytes());
+        cso.extend(((second_offset >> align) as u32 | CSO_PLAIN_BLOCK).to_le_bytes());
+        cso.extend(((end >> align) as u32).to_le_bytes());
+        cso.resize(first_offset, 0);
+        cso.extend(&deflated);
+        cso.resize(second_offset, 0);
+        cso.extend(&second);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("game.cso");
+        let extracted = dir.path().join("game.iso");
+        write(&path, cso).unwrap();
+        let (_sender, interrupt) = unbounded();
+
+        assert!(!decompress_cso(&path, &extracted, &interrupt).unwrap());
+        assert_eq!(read(&extracted).unwrap(), [first, second].concat());
+    }
+
+    #[test]
+    fn damaged_images_fail_the_verification() {
+        let dir = tempfile::tempdir().unwrap();
+        let iso = dir.path().join("game.iso");
+        let cso = dir.path().join("game.cso");
+        write(&iso, image()).unwrap();
+        let (_sender, interrupt) = unbounded();
+        compress_cso(&iso, &cso, 9, &interrupt).unwrap();
+
+        let mut compressed = read(&cso).unwrap();
+        compressed.truncate(compressed.len() - 10);
+        write(&cso, &compressed).unwrap();
+        assert!(verify_cso(&cso, &interrupt).is_err());
+
+        compressed[..4].copy_from_slice(DAX_MAGIC);
+        write(&cso, &compressed).unwrap();
+        assert!(verify_cso(&cso, &interrupt).is_err());
+    }
+
+    #[test]
+    fn interrupted_compression_returns_true() {
+        let dir = tempfile::tempdir().unwrap();
+        let iso = dir.path().join("game.iso");
+        write(&iso, image()).unwrap();
+        let (sender, interrupt) = unbounded();
+        sender.send(()).unwrap();
+
+        assert!(compress_cso(&iso, &dir.path().join("game.cso"), 9, &interrupt).unwrap());
+    }
+}
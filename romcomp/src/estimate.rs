@@ -0,0 +1,210 @@
+use crate::{convert::Converter, rom_format::RomFormat};
+use crossbeam_channel::Receiver;
+use humansize::{format_size, DECIMAL};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{copy, create_dir_all, hard_link},
+    hash::{BuildHasher, RandomState},
+    io,
+    path::PathBuf,
+};
+use tempfile::{Builder, TempDir};
+
+/// the input and output sizes of a format, both of all files and of the compressed samples
+#[derive(Default)]
+struct FormatEstimate {
+    files: usize,
+    input_size: u64,
+    samples: usize,
+    sample_input_size: u64,
+    sample_output_size: u64,
+}
+
+/// the console names of a format, which the samples are grouped by
+fn format_name(format: RomFormat) -> String {
+    (format & RomFormat::CONSOLES)
+        .iter_names()
+        .map(|(n, _)| n)
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+/// links the inputs of a job into a hidden directory next to them, copying them if linking isn't possible.
+/// returns the directory, which gets deleted once dropped, and the staged input
+fn stage(inputs: &[PathBuf]) -> io::Result<(TempDir, PathBuf)> {
+    let parent = inputs[0].parent().unwrap();
+    let dir = Builder::new()
+        .prefix(".romcomp-estimate-")
+        .tempdir_in(parent)?;
+
+    for input in inputs.iter() {
+        let relative = input.strip_prefix(parent).map_err(|_| {
+            io::Error::other(format!(
+                "{} is outside of {}",
+                input.display(),
+                parent.display()
+            ))
+        })?;
+        let staged = dir.path().join(relative);
+
+        create_dir_all(staged.parent().unwrap())?;
+
+        if hard_link(input, &staged).is_err() {
+            copy(input, &staged)?;
+        }
+    }
+
+    let input = dir.path().join(inputs[0].file_name().unwrap());
+
+    Ok((dir, input))
+}
+
+/// compresses a random sample of the jobs, at least one of each format if possible,
+/// and extrapolates the savings of all jobs from the compression ratio of each format.
+/// the samples and their outputs get deleted afterwards
+pub fn estimate(
+    converter: &Converter,
+    jobs: &[(PathBuf, RomFormat)],
+    samples: usize,
+    interrupt: &Receiver<()>,
+) {
+    let mut formats: BTreeMap<String, FormatEstimate> = BTreeMap::new();
+    let mut candidates: BTreeMap<String, Vec<(u64, Vec<PathBuf>, RomFormat)>> = BTreeMap::new();
+    let random = RandomState::new();
+
+    for (file, format) in jobs.iter() {
+        let name = format_name(*format);
+        let inputs = converter.job(file, *format).inputs;
+        let estimate = formats.entry(name.clone()).or_default();
+
+        estimate.files += 1;
+        estimate.input_size += inputs
+            .iter()
+            .map(|i| converter.file_size(i).unwrap_or(0))
+            .sum::<u64>();
+
+        candidates
+            .entry(name)
+            .or_default()
+            .push((random.hash_one(file), inputs, *format));
+    }
+
+    // every format takes a turn, so rare formats get sampled as well
+    let mut queues = candidates
+        .into_values()
+        .map(|mut c| {
+            c.sort_by_key(|(order, _, _)| *order);
+            c.into_iter()
+        })
+        .collect::<Vec<_>>();
+    let mut picked = vec![];
+
+    while picked.len() < samples {
+        let before = picked.len();
+
+        for queue in queues.iter_mut() {
+            if picked.len() < samples {
+                picked.extend(queue.next());
+            }
+        }
+
+        if picked.len() == before {
+            break;
+        }
+    }
+
+    let mut staged: HashMap<PathBuf, (TempDir, String)> = HashMap::new();
+
+    converter.begin(picked.len());
+
+    for (_, inputs, format) in picked.iter() {
+        if !interrupt.is_empty() {
+            break;
+        }
+
+        match stage(inputs) {
+            Ok((dir, input)) => {
+                converter.convert(&input, *format);
+                staged.insert(input, (dir, format_name(*format)));
+            }
+            Err(e) => println!("Couldn't sample {}: {}", inputs[0].display(), e),
+        }
+    }
+
+    converter.finish();
+
+    for record in converter.records() {
+        if let Some(estimate) = staged
+            .get(&record.input)
+            .and_then(|(_, name)| formats.get_mut(name))
+        {
+            estimate.samples += 1;
+            estimate.sample_input_size += record.input_size;
+            estimate.sample_output_size += record.output_size;
+        }
+    }
+
+    println!(
+        "Estimate from {} of {} files:",
+        formats.values().map(|e| e.samples).sum::<usize>(),
+        jobs.len()
+    );
+    println!(
+        "{:>8} {:>8} {:>12} {:>12} {:>12}  Format",
+        "Files", "Sampled", "Input", "Output", "Saved"
+    );
+
+    let (mut total_input, mut total_output) = (0, 0);
+    let mut unsampled = 0;
+
+    for (name, estimate) in formats.iter() {
+        if estimate.samples == 0 || estimate.sample_input_size == 0 {
+            println!(
+                "{:>8} {:>8} {:>12} {:>12} {:>12}  {}",
+                estimate.files,
+                0,
+                format_size(estimate.input_size, DECIMAL),
+                "?",
+                "?",
+                name
+            );
+            unsampled += estimate.input_size;
+            continue;
+        }
+
+        let ratio = estimate.sample_output_size as f64 / estimate.sample_input_size as f64;
+        let output = (estimate.input_size as f64 * ratio) as u64;
+
+        println!(
+            "{:>8} {:>8} {:>12} {:>12} {:>12}  {}",
+            estimate.files,
+            estimate.samples,
+            format_size(estimate.input_size, DECIMAL),
+            format_size(output, DECIMAL),
+            format_size(estimate.input_size.saturating_sub(output), DECIMAL),
+            name
+        );
+
+        total_input += estimate.input_size;
+        total_output += output;
+    }
+
+    if total_input > 0 {
+        println!(
+            "Estimated savings: {} of {} ({:.2}%)",
+            format_size(total_input.saturating_sub(total_output), DECIMAL),
+            format_size(total_input, DECIMAL),
+            100f64 - (total_output as f64 * 100f64 / total_input as f64)
+        );
+    }
+
+    if unsampled > 0 {
+        println!(
+            "{} of input couldn't be estimated, since none of its files could be sampled",
+            format_size(unsampled, DECIMAL)
+        );
+    }
+
+    // the staged samples and their outputs get deleted now
+    drop(staged);
+}
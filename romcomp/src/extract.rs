@@ -0,0 +1,264 @@
+use crate::{
+    cso::decompress_cso, probe::CHD_MAX_METADATA_ENTRIES, rom_format::CompressionTool,
+    zst::decompress_zstd_seekable,
+};
+use crossbeam_channel::Receiver;
+use log::{debug, error, info, warn};
+use std::{
+    collections::HashMap,
+    fs::{remove_file, File},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+use zip::ZipArchive;
+
+/// the kind of disc stored in a chd file, which decides how chdman extracts it
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum ChdKind {
+    Cd,
+    GdRom,
+    Dvd,
+}
+
+/// how a compressed file gets extracted
+enum Extraction {
+    Zstd,
+    Cso,
+    /// the entry of a zip file holding a single rom
+    Zip(usize),
+    Tool(CompressionTool, Vec<String>),
+}
+
+/// reads the kind of disc from the metadata of a chd file, None if it doesn't hold a disc,
+/// e.g. the hard disk images of MAME
+fn chd_kind(path: &Path) -> io::Result<Option<ChdKind>> {
+    let mut file = File::open(path)?;
+    let mut header = [0; 56];
+    file.read_exact(&mut header)?;
+
+    if &header[..8] != b"MComprHD" {
+        return Ok(None);
+    }
+
+    let read_u64 = |bytes: &[u8]| u64::from_be_bytes(bytes.try_into().unwrap());
+    let mut offset = match u32::from_be_bytes(header[12..16].try_into().unwrap()) {
+        3 | 4 => read_u64(&header[36..44]),
+        5 => read_u64(&header[48..56]),
+        _ => return Ok(None),
+    };
+
+    // every metadata entry starts with its tag, flags, length and the offset of the next entry.
+    for _ in 0..CHD_MAX_METADATA_ENTRIES {
+        if offset == 0 {
+            break;
+        }
+
+        let mut entry = [0; 16];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut entry)?;
+
+        match &entry[..4] {
+            b"CHCD" | b"CHTR" | b"CHT2" => return Ok(Some(ChdKind::Cd)),
+            b"CHGT" | b"CHGD" => return Ok(Some(ChdKind::GdRom)),
+            b"DVD " => return Ok(Some(ChdKind::Dvd)),
+            _ => offset = read_u64(&entry[8..]),
+        }
+    }
+
+    Ok(None)
+}
+
+/// the index of the only rom inside a zip file, zip files with several files are romsets or archives
+/// which weren't created by romcomp
+fn single_entry(path: &Path) -> Result<(usize, PathBuf), String> {
+    let mut zip =
+        ZipArchive::new(File::open(path).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+    let files = (0..zip.len())
+        .filter(|i| zip.by_index_raw(*i).is_ok_and(|e| e.is_file()))
+        .collect::<Vec<_>>();
+
+    if files.len() != 1 {
+        return Err("Only zip files with a single rom get extracted".to_string());
+    }
+
+    let entry = zip.by_index_raw(files[0]).map_err(|e| e.to_string())?;
+
+    // the rom is always extracted next to the zip file, whatever directory it was stored in
+    entry
+        .enclosed_name()
+        .and_then(|n| n.file_name().map(|n| (files[0], path.with_file_name(n))))
+        .ok_or_else(|| format!("{} isn't a valid file name", entry.name()))
+}
+
+/// the files an extraction creates, the first one being the extracted rom, and how it gets extracted.
+/// None if the file isn't a compressed rom
+fn plan(file: &Path) -> Result<Option<(Vec<PathBuf>, Extraction)>, String> {
+    // the tools get the paths as UTF-8 arguments, the targets are derived from the input
+    let input = file
+        .to_str()
+        .ok_or_else(|| "The path isn't valid UTF-8".to_string())?
+        .to_string();
+    let name = file
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| "The file has no name".to_string())?;
+    let extension = file
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    let target = |extension: &str| file.with_extension(extension);
+
+    Ok(match extension.as_str() {
+        "zst" => Some((
+            vec![file.with_file_name(&name[..name.len() - 4])],
+            Extraction::Zstd,
+        )),
+        "cso" => Some((vec![target("iso")], Extraction::Cso)),
+        "zip" => {
+            let (index, target) = single_entry(file)?;
+            Some((vec![target], Extraction::Zip(index)))
+        }
+        "rvz" => Some((
+            vec![target("iso")],
+            Extraction::Tool(
+                CompressionTool::DolphinTool,
+                vec![
+                    "convert".to_string(),
+                    "-f".to_string(),
+                    "iso".to_string(),
+                    "-i".to_string(),
+                    input,
+                    "-o".to_string(),
+                    target("iso").to_string_lossy().into_owned(),
+                ],
+            ),
+        )),
+        "chd" => {
+            let (command, targets) = match chd_kind(file).map_err(|e| e.to_string())? {
+                Some(ChdKind::Cd) => ("extractcd", vec![target("cue"), target("bin")]),
+                // chdman names the track files after the gdi file
+                Some(ChdKind::GdRom) => ("extractcd", vec![target("gdi")]),
+                Some(ChdKind::Dvd) => ("extractdvd", vec![target("iso")]),
+                None => return Err("The chd file doesn't hold a disc".to_string()),
+            };
+            let mut args = vec![
+                command.to_string(),
+                "-i".to_string(),
+                input,
+                "-o".to_string(),
+                targets[0].to_string_lossy().into_owned(),
+            ];
+
+            if let Some(bin) = targets.get(1) {
+                args.extend(["-ob".to_string(), bin.to_string_lossy().into_owned()]);
+            }
+
+            Some((targets, Extraction::Tool(CompressionTool::Chdman, args)))
+        }
+        _ => None,
+    })
+}
+
+/// extracts a zip entry, returns whether the extraction got interrupted
+fn extract_zip(
+    input: &Path,
+    index: usize,
+    output: &Path,
+    interrupt: &Receiver<()>,
+) -> Result<bool, String> {
+    let mut zip = ZipArchive::new(File::open(input).map_err(|e| e.to_string())?)
+        .map_err(|e| e.to_string())?;
+    let mut entry = zip.by_index(index).map_err(|e| e.to_string())?;
+    let mut writer = File::create(output).map_err(|e| e.to_string())?;
+    let mut buffer = vec![0; 1024 * 1024];
+
+    // the zip crate checks the CRC of the entry once it was read completely
+    loop {
+        if !interrupt.is_empty() {
+            return Ok(true);
+        }
+
+        match entry.read(&mut buffer).map_err(|e| e.to_string())? {
+            0 => return Ok(false),
+            n => writer.write_all(&buffer[..n]).map_err(|e| e.to_string())?,
+        }
+    }
+}
+
+/// decompresses every compressed rom next to itself, see --extract.
+/// tools which aren't given skip the files which need them
+pub fn extract(
+    files: impl Iterator<Item = PathBuf>,
+    tools: &HashMap<CompressionTool, String>,
+    interrupt: &Receiver<()>,
+) -> u8 {
+    let mut failed = false;
+
+    for file in files {
+        if !interrupt.is_empty() {
+            break;
+        }
+
+        let (targets, extraction) = match plan(&file) {
+            Ok(Some(plan)) => plan,
+            Ok(None) => continue,
+            Err(e) => {
+                info!("Skipping {}: {}", file.display(), e);
+                continue;
+            }
+        };
+
+        if let Some(existing) = targets.iter().find(|t| t.exists()) {
+            debug!(
+                "Skipping {}: {} already exists",
+                file.display(),
+                existing.display()
+            );
+            continue;
+        }
+
+        let result = match extraction {
+            Extraction::Tool(tool, _) if !tools.contains_key(&tool) => {
+                warn!("Skipping {}: {} isn't available", file.display(), tool);
+                continue;
+            }
+            _ => {
+                debug!("Extracting {} to {}", file.display(), targets[0].display());
+
+                match extraction {
+                    Extraction::Zstd => decompress_zstd_seekable(&file, &targets[0], interrupt)
+                        .map_err(|e| e.to_string()),
+                    Extraction::Cso => {
+                        decompress_cso(&file, &targets[0], interrupt).map_err(|e| e.to_string())
+                    }
+                    Extraction::Zip(index) => extract_zip(&file, index, &targets[0], interrupt),
+                    Extraction::Tool(tool, args) => tool.run(&tools[&tool], &args, interrupt),
+                }
+            }
+        };
+
+        match result {
+            Ok(false) => info!("Extracted {}", targets[0].display()),
+            Ok(true) => {
+                for target in targets.iter() {
+                    let _ = remove_file(target);
+                }
+            }
+            Err(e) => {
+                error!("Failed extraction of {}: {}", file.display(), e);
+                for target in targets.iter() {
+                    let _ = remove_file(target);
+                }
+                failed = true;
+            }
+        }
+    }
+
+    // the same exit code as failed conversions
+    if failed {
+        3
+    } else {
+        0
+    }
+}
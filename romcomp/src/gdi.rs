@@ -0,0 +1,230 @@
+use std::{
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+/// the first sector of the high-density area, which holds the game data of a GD-ROM
+const HIGH_DENSITY_LBA: u32 = 45000;
+
+/// a track of a gdi file, given as: number, LBA, type, sector size, file name and offset
+#[derive(Clone, Debug)]
+pub struct GdiTrack {
+    pub number: u32,
+    /// the first sector of the track on the disc
+    pub lba: u32,
+    /// 0 for audio, 4 for data
+    pub track_type: u8,
+    pub sector_size: u32,
+    /// the track file, relative to the gdi file
+    pub file: String,
+}
+
+impl GdiTrack {
+    /// whether the track belongs to the high-density area instead of the low-density area
+    pub fn high_density(&self) -> bool {
+        self.lba >= HIGH_DENSITY_LBA
+    }
+}
+
+/// splits a line into fields, file names containing spaces are enclosed in double quotes
+fn fields(line: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut rest = line.trim();
+
+    while !rest.is_empty() {
+        let (field, remainder) = match rest.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => rest.split_once(char::is_whitespace).unwrap_or((rest, "")),
+        };
+
+        fields.push(field.to_string());
+        rest = remainder.trim_start();
+    }
+
+    fields
+}
+
+/// reads the tracks of a gdi file in disc order and checks that they describe a valid GD-ROM
+/// whose track files all exist
+pub fn parse_gdi(path: &Path) -> Result<Vec<GdiTrack>, String> {
+    let content = read_to_string(path).map_err(|e| e.to_string())?;
+    let mut lines = content.lines().filter(|l| !l.trim().is_empty());
+
+    let count = lines
+        .next()
+        .and_then(|l| l.trim().parse::<u32>().ok())
+        .ok_or("the track count is missing")?;
+
+    let mut tracks = lines
+        .map(|line| {
+            let f = fields(line);
+
+            if f.len() < 5 {
+                return Err(format!("invalid track line: {}", line.trim()));
+            }
+
+            let number = |i: usize| {
+                f[i].parse::<u32>()
+                    .map_err(|_| format!("invalid track line: {}", line.trim()))
+            };
+
+            Ok(GdiTrack {
+                number: number(0)?,
+                lba: number(1)?,
+                track_type: u8::try_from(number(2)?)
+                    .map_err(|_| format!("invalid track type {} in line: {}", f[2], line.trim()))?,
+                sector_size: number(3)?,
+                file: f[4].clone(),
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    tracks.sort_by_key(|t| t.number);
+
+    if tracks.len() != count as usize {
+        return Err(format!(
+            "{} tracks are declared, but {} are listed",
+            count,
+            tracks.len()
+        ));
+    }
+
+    // a GD-ROM has at least two tracks in the low-density area and one in the high-density area
+    if count < 3 {
+        return Err("a GD-ROM needs at least 3 tracks".to_string());
+    }
+
+    for (i, track) in tracks.iter().enumerate() {
+        if track.number != i as u32 + 1 {
+            return Err(format!("track {} is missing", i + 1));
+        }
+
+        if track.track_type != 0 && track.track_type != 4 {
+            return Err(format!(
+                "track {} has the unknown type {}",
+                track.number, track.track_type
+            ));
+        }
+
+        if track.sector_size != 2352 && track.sector_size != 2048 {
+            return Err(format!(
+                "track {} has the unsupported sector size {}",
+                track.number, track.sector_size
+            ));
+        }
+
+        if i > 0 && track.lba <= tracks[i - 1].lba {
+            return Err(format!(
+                "track {} doesn't start after track {}",
+                track.number, i
+            ));
+        }
+
+        if !path.parent().unwrap().join(&track.file).is_file() {
+            return Err(format!("the track file {} doesn't exist", track.file));
+        }
+    }
+
+    if tracks[2].lba != HIGH_DENSITY_LBA || tracks[..2].iter().any(|t| t.high_density()) {
+        return Err(format!(
+            "track 3 must be the first track of the high-density area at LBA {}",
+            HIGH_DENSITY_LBA
+        ));
+    }
+
+    Ok(tracks)
+}
+
+/// all files of a gdi image: the gdi file itself and every track file
+pub fn gdi_files(path: &Path) -> Vec<PathBuf> {
+    let mut files = vec![path.to_path_buf()];
+
+    for track in parse_gdi(path).unwrap_or_default() {
+        let file = path.parent().unwrap().join(track.file);
+
+        if !files.contains(&file) {
+            files.push(file);
+        }
+    }
+
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::write;
+
+    /// writes a gdi file and the track files it lists, returns its path
+    fn gdi(dir: &Path, content: &str, tracks: &[&str]) -> PathBuf {
+        for track in tracks {
+            write(dir.join(track), b"").unwrap();
+        }
+
+        let path = dir.join("game.gdi");
+        write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn tracks_are_parsed_in_disc_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = gdi(
+            dir.path(),
+            "3\n\
+             3 45000 4 2352 \"track 03.bin\" 0\n\
+             1 0 4 2352 track01.bin 0\n\
+             2 756 0 2352 track02.raw 0\n",
+            &["track01.bin", "track02.raw", "track 03.bin"],
+        );
+
+        let tracks = parse_gdi(&path).unwrap();
+
+        assert_eq!(
+            tracks.iter().map(|t| t.file.as_str()).collect::<Vec<_>>(),
+            ["track01.bin", "track02.raw", "track 03.bin"]
+        );
+        assert_eq!(tracks[1].track_type, 0);
+        assert!(tracks[2].high_density());
+        assert_eq!(gdi_files(&path).len(), 4);
+    }
+
+    #[test]
+    fn track_type_out_of_range_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        // 260 would have been truncated to the data track type 4
+        let path = gdi(
+            dir.path(),
+            "3\n1 0 4 2352 a.bin 0\n2 756 260 2352 b.raw 0\n3 45000 4 2352 c.bin 0\n",
+            &["a.bin", "b.raw", "c.bin"],
+        );
+
+        assert_eq!(
+            parse_gdi(&path).unwrap_err(),
+            "invalid track type 260 in line: 2 756 260 2352 b.raw 0"
+        );
+    }
+
+    #[test]
+    fn invalid_gdi_files_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let tracks = ["a.bin", "b.raw", "c.bin"];
+
+        for content in [
+            // the count doesn't match
+            "4\n1 0 4 2352 a.bin 0\n2 756 0 2352 b.raw 0\n3 45000 4 2352 c.bin 0\n",
+            // track 3 isn't in the high-density area
+            "3\n1 0 4 2352 a.bin 0\n2 756 0 2352 b.raw 0\n3 1000 4 2352 c.bin 0\n",
+            // a missing field
+            "3\n1 0 4 2352 a.bin 0\n2 756 0 b.raw\n3 45000 4 2352 c.bin 0\n",
+            // a missing track file
+            "3\n1 0 4 2352 a.bin 0\n2 756 0 2352 b.raw 0\n3 45000 4 2352 d.bin 0\n",
+        ] {
+            assert!(
+                parse_gdi(&gdi(dir.path(), content, &tracks)).is_err(),
+                "{}",
+                content
+            );
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use crc32fast::Hasher;
+use crossbeam_channel::Receiver;
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::{
+    fs::File,
+    io,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+/// calculates the SHA-1 hash of a file
+pub fn sha1_file(path: &Path) -> io::Result<[u8; 20]> {
+    let mut fh = File::open(path)?;
+    let mut hasher = Sha1::new();
+    let mut buf = vec![0_u8; 1024 * 1024];
+
+    loop {
+        let read = fh.read(&mut buf)?;
+
+        if read == 0 {
+            break;
+        }
+
+        hasher.update(&buf[..read]);
+    }
+
+    Ok(hasher.finalize().into())
+}
+
+/// an input rom as listed by No-Intro and Redump DATs, see --hash
+#[derive(Clone, Debug, Serialize)]
+pub struct InputHash {
+    pub file: PathBuf,
+    #[serde(rename = "bytes")]
+    pub size: u64,
+    pub crc32: String,
+    pub sha1: String,
+}
+
+/// calculates the CRC32 and SHA-1 hashes of a file, reading it only once.
+/// returns None if the hashing got interrupted
+pub fn hash_input(path: &Path, interrupt: &Receiver<()>) -> io::Result<Option<InputHash>> {
+    let mut fh = File::open(path)?;
+    let mut sha1 = Sha1::new();
+    let mut crc32 = Hasher::new();
+    let mut size = 0;
+    let mut buf = vec![0_u8; 1024 * 1024];
+
+    loop {
+        if !interrupt.is_empty() {
+            return Ok(None);
+        }
+
+        let read = fh.read(&mut buf)?;
+
+        if read == 0 {
+            break;
+        }
+
+        sha1.update(&buf[..read]);
+        crc32.update(&buf[..read]);
+        size += read as u64;
+    }
+
+    Ok(Some(InputHash {
+        file: path.to_path_buf(),
+        size,
+        crc32: format!("{:08x}", crc32.finalize()),
+        sha1: sha1
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam_channel::unbounded;
+    use std::fs::write;
+
+    #[test]
+    fn input_hashes_match_the_check_values() {
+        let dir = tempfile::tempdir().unwrap();
+        let rom = dir.path().join("game.bin");
+        write(&rom, b"123456789").unwrap();
+        let (_sender, interrupt) = unbounded();
+
+        let hash = hash_input(&rom, &interrupt).unwrap().unwrap();
+
+        assert_eq!(hash.size, 9);
+        assert_eq!(hash.crc32, "cbf43926");
+        assert_eq!(hash.sha1, "f7c3bc1d808e04732adf679965ccc34ca7ae3441");
+    }
+
+    #[test]
+    fn interrupted_hashing_returns_none() {
+        let dir = tempfile::tempdir().unwrap();
+        let rom = dir.path().join("game.bin");
+        write(&rom, b"123456789").unwrap();
+        let (sender, interrupt) = unbounded();
+        sender.send(()).unwrap();
+
+        assert!(hash_input(&rom, &interrupt).unwrap().is_none());
+    }
+}
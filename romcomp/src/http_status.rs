@@ -0,0 +1,116 @@
+use crate::convert::SkipReason;
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+/// serves the progress of a run over HTTP, for liveness probes and metrics scraping
+pub struct HttpStatus {
+    pub total_files: usize,
+    pub processed_files: Arc<AtomicUsize>,
+    pub failed_files: Arc<Mutex<Vec<(PathBuf, String)>>>,
+    pub skipped_files: Arc<Mutex<Vec<(PathBuf, SkipReason)>>>,
+    pub input_file_size: Arc<AtomicUsize>,
+    pub output_file_size: Arc<AtomicUsize>,
+    pub active_files: Arc<Mutex<Vec<PathBuf>>>,
+}
+
+impl HttpStatus {
+    /// answers requests until the run stops
+    pub fn serve(&self, address: SocketAddr, running: &AtomicBool) -> io::Result<()> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+
+        while running.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    // a misbehaving client must not take down the run
+                    let _ = self.respond(stream);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(Duration::from_millis(50));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn respond(&self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+        let mut request = String::new();
+        BufReader::new(&stream).read_line(&mut request)?;
+
+        let (status, body) = match request.split_whitespace().nth(1) {
+            Some("/healthz") => ("200 OK", "ok\n".to_string()),
+            Some("/metrics") => ("200 OK", self.metrics()),
+            _ => ("404 Not Found", "not found\n".to_string()),
+        };
+
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        )
+    }
+
+    /// the counters of the run in the Prometheus text format
+    fn metrics(&self) -> String {
+        [
+            (
+                "romcomp_files_total",
+                "Files found to be converted",
+                self.total_files,
+            ),
+            (
+                "romcomp_files_processed",
+                "Files converted successfully",
+                self.processed_files.load(Ordering::Relaxed),
+            ),
+            (
+                "romcomp_files_skipped",
+                "Files skipped",
+                self.skipped_files.lock().unwrap().len(),
+            ),
+            (
+                "romcomp_files_failed",
+                "Files which failed to convert",
+                self.failed_files.lock().unwrap().len(),
+            ),
+            (
+                "romcomp_files_active",
+                "Files currently being converted",
+                self.active_files.lock().unwrap().len(),
+            ),
+            (
+                "romcomp_input_bytes",
+                "Size of all converted input files",
+                self.input_file_size.load(Ordering::Relaxed),
+            ),
+            (
+                "romcomp_output_bytes",
+                "Size of all created output files",
+                self.output_file_size.load(Ordering::Relaxed),
+            ),
+        ]
+        .iter()
+        .map(|(name, help, value)| {
+            format!(
+                "# HELP {} {}\n# TYPE {} gauge\n{} {}\n",
+                name, help, name, name, value
+            )
+        })
+        .collect()
+    }
+}
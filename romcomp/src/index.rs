@@ -0,0 +1,66 @@
+use crate::{
+    convert::ConversionRecord,
+    hash::sha1_file,
+    probe::{probe_chd, probe_rvz, ProbeChd, ProbeRvz},
+};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{read_to_string, rename, write},
+    path::{Path, PathBuf},
+};
+
+/// what is known about a compressed file, without having to read it again
+#[derive(Deserialize, Serialize)]
+struct IndexEntry {
+    input: PathBuf,
+    input_size: u64,
+    output_size: u64,
+    /// the SHA-1 of the whole output file
+    sha1: String,
+    chd: Option<ProbeChd>,
+    rvz: Option<ProbeRvz>,
+}
+
+/// adds the outputs of a run to the index file, replacing earlier entries of the same outputs.
+/// entries of outputs which don't exist anymore are dropped
+pub fn update_index(path: &Path, records: &[ConversionRecord]) -> Result<usize> {
+    let mut index: BTreeMap<PathBuf, IndexEntry> = if path.is_file() {
+        serde_json::from_str(&read_to_string(path)?)?
+    } else {
+        BTreeMap::new()
+    };
+
+    index.retain(|output, _| output.is_file());
+
+    let mut added = 0;
+
+    // outputs uploaded with --s3-remove-local don't exist locally anymore
+    for record in records.iter().filter(|r| r.output.is_file()) {
+        index.insert(
+            record.output.clone(),
+            IndexEntry {
+                input: record.input.clone(),
+                input_size: record.input_size,
+                output_size: record.output_size,
+                sha1: sha1_file(&record.output)?
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect(),
+                chd: probe_chd(&record.output),
+                rvz: probe_rvz(&record.output),
+            },
+        );
+        added += 1;
+    }
+
+    // readers must never see a partially written index
+    let mut temp = path.to_path_buf().into_os_string();
+    temp.push(".tmp");
+
+    write(&temp, serde_json::to_string_pretty(&index)?)?;
+    rename(&temp, path)?;
+
+    Ok(added)
+}
@@ -0,0 +1,92 @@
+use crate::rom_format::RomFormat;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{read_to_string, write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// a planned conversion, written by --emit-jobs and executed by --run-jobs
+#[derive(Deserialize, Serialize)]
+pub struct Job {
+    /// the file passed to the converter, e.g. the cue sheet
+    pub input: PathBuf,
+    /// every file the conversion reads, e.g. the cue sheet and its bin files
+    pub inputs: Vec<PathBuf>,
+    /// the names of the format flags
+    pub format: Vec<String>,
+    /// the shell command running the conversion, if it is done by a single tool
+    pub command: Option<String>,
+    /// why there is no command
+    pub note: Option<String>,
+    pub output: Option<PathBuf>,
+}
+
+impl Job {
+    pub fn rom_format(&self) -> Result<RomFormat> {
+        self.format
+            .iter()
+            .try_fold(RomFormat::empty(), |format, name| {
+                RomFormat::from_name(name)
+                    .map(|f| format | f)
+                    .ok_or_else(|| anyhow!("unknown format {}", name))
+            })
+    }
+}
+
+/// independent conversions below common root directories
+#[derive(Deserialize, Serialize)]
+pub struct JobFile {
+    /// the locations the jobs were planned for
+    pub roots: Vec<PathBuf>,
+    pub jobs: Vec<Job>,
+}
+
+impl JobFile {
+    pub fn load(path: &Path) -> Result<JobFile> {
+        let job_file: JobFile = serde_json::from_str(&read_to_string(path)?)?;
+
+        for job in job_file.jobs.iter() {
+            job.rom_format()?;
+        }
+
+        Ok(job_file)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        Ok(write(path, serde_json::to_string_pretty(self)?)?)
+    }
+}
+
+/// a slice of the jobs, given as i/n with i starting at 1.
+/// jobs are dealt out in turns, so every shard gets a similar mix of small and large jobs
+#[derive(Clone, Copy, Debug)]
+pub struct Shard {
+    index: usize,
+    count: usize,
+}
+
+impl Shard {
+    pub fn contains(&self, job: usize) -> bool {
+        job % self.count == self.index - 1
+    }
+}
+
+impl FromStr for Shard {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (index, count) = value
+            .split_once('/')
+            .ok_or_else(|| format!("{} isn't given as i/n", value))?;
+        let index = index.trim().parse::<usize>().map_err(|e| e.to_string())?;
+        let count = count.trim().parse::<usize>().map_err(|e| e.to_string())?;
+
+        if index == 0 || index > count {
+            return Err(format!("{} must be between 1 and {}", index, count));
+        }
+
+        Ok(Shard { index, count })
+    }
+}
@@ -0,0 +1,67 @@
+//! The ROM conversion behind the romcomp command line tool, so other programs (e.g. a GUI frontend or a daemon)
+//! can convert ROMs the same way.
+//!
+//! A [`Converter`] is set up with its builder methods and converts one file at a time with [`Converter::convert`],
+//! which takes the format [`guess_file`] detected. The conversions run in the background, what happens to every file
+//! is sent to the channel given to [`Converter::events`]. Everything else is reported through the [`log`] crate.
+//!
+//! ```no_run
+//! use romcomp::{guess_file, ConversionEvent, Converter, RomFormat};
+//! use std::path::PathBuf;
+//!
+//! let (_interrupt_sender, interrupt) = crossbeam_channel::bounded(1);
+//! let (events, progress) = crossbeam_channel::unbounded();
+//! let location = PathBuf::from("roms");
+//! let converter = Converter::new(&[location.clone()], tempfile::tempdir().unwrap(), 4, interrupt)
+//!     .events(events);
+//! let file = location.join("game.cue");
+//!
+//! converter.begin(1);
+//!
+//! if let Some(format) = guess_file(&file) {
+//!     // a cue sheet or an iso can belong to several consoles, the caller has to pick one
+//!     converter.convert(&file, format.difference(RomFormat::CONSOLES) | RomFormat::PlayStation2);
+//! }
+//!
+//! converter.finish();
+//!
+//! for event in progress.try_iter() {
+//!     if let ConversionEvent::Finished(record) = event {
+//!         println!("{} -> {}", record.input.display(), record.output.display());
+//!     }
+//! }
+//! ```
+
+mod arcade;
+pub mod archive;
+mod audio;
+pub mod bios;
+pub mod config;
+pub mod convert;
+pub mod cso;
+pub mod estimate;
+pub mod extract;
+pub mod gdi;
+mod hash;
+mod http_status;
+pub mod index;
+pub mod jobs;
+pub mod n64;
+pub mod permissions;
+pub mod playlist;
+pub mod probe;
+mod progress;
+mod reflink;
+pub mod report;
+pub mod rom_format;
+pub mod s3;
+pub mod search;
+pub mod state;
+mod status;
+mod tui;
+mod verify;
+mod zst;
+
+pub use convert::{ConversionEvent, ConversionRecord, Converter, SkipReason};
+pub use rom_format::{CompressionOptions, CompressionTool, RomFormat};
+pub use search::guess_file;
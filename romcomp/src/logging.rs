@@ -0,0 +1,39 @@
+use log::LevelFilter;
+use std::{io, path::Path, time::SystemTime};
+
+/// sends the messages to stdout as they always were, errors and warnings included.
+/// verbose shows the debug messages as well. the log file gets every message including the debug ones,
+/// each with its time and level, and is appended to if it exists already
+pub fn init(verbose: bool, log_file: Option<&Path>) -> io::Result<()> {
+    let console = fern::Dispatch::new()
+        .level(if verbose {
+            LevelFilter::Debug
+        } else {
+            LevelFilter::Info
+        })
+        .format(|out, message, _| out.finish(*message))
+        .chain(io::stdout());
+
+    let mut dispatch = fern::Dispatch::new()
+        // the dependencies' own messages are of no interest
+        .level(LevelFilter::Off)
+        .level_for(env!("CARGO_CRATE_NAME"), LevelFilter::Debug)
+        .chain(console);
+
+    if let Some(path) = log_file {
+        dispatch = dispatch.chain(
+            fern::Dispatch::new()
+                .format(|out, message, record| {
+                    out.finish(format_args!(
+                        "{} {:<5} {}",
+                        humantime::format_rfc3339_seconds(SystemTime::now()),
+                        record.level(),
+                        message
+                    ))
+                })
+                .chain(fern::log_file(path)?),
+        );
+    }
+
+    dispatch.apply().map_err(io::Error::other)
+}
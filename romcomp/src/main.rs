@@ -1,240 +1,2072 @@
-mod convert;
-mod rom_format;
-mod search;
+mod logging;
+mod priority;
+mod scan;
 
 use anyhow::Result;
-use clap::{Parser, ValueEnum};
-use convert::Converter;
+use clap::{Parser, Subcommand, ValueEnum};
 use crossbeam_channel::{bounded, Receiver};
-use rom_format::RomFormat;
-use search::guess_file;
+use globset::GlobSet;
+use ignore::WalkBuilder;
+use log::{debug, error, info, log, warn, Level};
+use priority::{set_io_class, set_nice, IoClass};
+use romcomp::{
+    archive::{archived_rom, is_archive},
+    bios::BiosDetector,
+    config::Config,
+    convert::{Converter, DuplicateAction, ExtCase, SizeMode},
+    cso::{CsoBackend, CsoFormat},
+    estimate::estimate,
+    extract::extract,
+    gdi::parse_gdi,
+    index::update_index,
+    jobs::{JobFile, Shard},
+    n64::N64Backend,
+    permissions::{parse_mode, parse_owner, Owner},
+    playlist::{read_m3u, rewrite_m3u, PlaylistFormat},
+    probe::Probe,
+    report::print_report_by_dir,
+    rom_format::{
+        parse_chd_hunk_size, parse_version, CompressionOptions, CompressionTool, Preset, RomFormat,
+        RvzCodec, SourceRomFormat, ZipMethod,
+    },
+    s3::{S3Client, S3Target},
+    search::{
+        content_formats, converted_twin, guess_file, junk_matcher, missing_tracks, path_matcher,
+        root_of,
+    },
+    state::StateFile,
+};
+use scan::{flag_names, print_scan, ScanEntry};
 use std::{
-    fs::canonicalize,
-    io::ErrorKind,
-    path::PathBuf,
-    process::{Command, ExitCode, Stdio},
+    collections::HashSet,
+    fs::{canonicalize, create_dir_all},
+    io::{stderr, ErrorKind, IsTerminal},
+    iter::empty,
+    net::SocketAddr,
+    num::NonZeroUsize,
+    path::{absolute, Path, PathBuf},
+    process::ExitCode,
 };
-use tempfile::tempdir;
-use walkdir::WalkDir;
+use tempfile::{tempdir, Builder};
 
 /// RomComp - a ROM compressor that picks the best compression options for you and supports as many ROM formats as possible
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
+#[command(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
 struct Cli {
-    /// location of ROM(s) to process.
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// locations of ROM(s) to process, all of them get converted in one run.
     /// If its a file, only this file will be processed.
-    /// If its a folder, all ROMs inside that folder will be processed
-    location: PathBuf,
+    /// If its a folder, all ROMs inside that folder will be processed.
+    /// .romcompignore files inside the folder exclude paths using the .gitignore syntax, relative to their own folder.
+    /// They apply in addition to the ignored junk and the ignore list of the config file, which always take precedence
 
-    /// the rom format that should be compressed
+    #[arg(required = true, num_args = 1..)]
+    location: Vec<PathBuf>,
 
-    #[arg(value_enum)]
-    format: SourceRomFormat,
+    /// the rom formats that should be compressed, e.g. psx,n64,nds for a folder mixing them.
+    /// with several formats, each file is converted into the one it is compatible with, like the auto format does
+
+    #[arg(value_enum, required = true, num_args = 1, value_delimiter = ',')]
+    format: Vec<SourceRomFormat>,
 
     /// enable additional debug messages
 
     #[arg(short, long, action)]
     verbose: bool,
 
+    /// also write every message to this file, each with its time and level. the debug messages are always
+    /// included, the file is appended to if it exists already
+
+    #[arg(long)]
+    log_file: Option<PathBuf>,
+
+    /// path to a TOML config file.
+    /// the [tools] table can list candidate binary names for each tool (bitbutcher, chdman, dolphin-tool, flac, maxcso, rom64),
+    /// the first one found will be used, e.g. chdman = ["chdman", "chdman.exe"]
+
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+
     /// how many conversions should be running in parallel?
-    /// default is the amount of available CPU cores
+    /// default is the amount of available CPU cores.
+    /// can also be given relative to the available CPU cores, e.g. 50% or 0.5
 
-    #[arg(short, long, action, default_value_t = num_cpus::get())]
+    #[arg(short, long, action, default_value_t = available_cores(), value_parser = parse_threads)]
     threads: usize,
 
-    /// delete input files after compression
+    /// how many I/O heavy operations (temporary copies and zipping) may run in parallel,
+    /// independent of --threads. useful to avoid thrashing a single hard disk.
+    /// unlimited by default
+
+    #[arg(long)]
+    io_concurrency: Option<NonZeroUsize>,
+
+    /// limit the conversions running in parallel by the size of their inputs instead of only by --threads,
+    /// e.g. 20GB. many small roms still run together, while large discs wait for each other.
+    /// a file larger than the budget is converted once nothing else is running
+
+    #[arg(long, value_parser = parse_size)]
+    io_budget: Option<u64>,
+
+    /// run romcomp and the tools it starts with this CPU priority, from -20 (highest) to 19 (lowest),
+    /// e.g. 19 to keep a NAS usable during a large run. only supported on Linux and macOS
+
+    #[arg(long, allow_negative_numbers = true, value_parser = clap::value_parser!(i32).range(-20..=19))]
+    nice: Option<i32>,
+
+    /// run romcomp and the tools it starts with this I/O scheduling class. only supported on Linux
+
+    #[arg(long, value_enum)]
+    ionice: Option<IoClass>,
+
+    /// clone files instead of copying them when creating temporary copies (e.g. of nds roms and .cue.txt files),
+    /// which is instant and takes no additional space on copy-on-write filesystems like btrfs, XFS or APFS.
+    /// falls back to regular copies where cloning isn't supported
+
+    #[arg(long, action)]
+    reflink: bool,
+
+    /// directory the temporary files (e.g. copies of nds roms) are created in, instead of the system's
+    /// temporary directory. useful if it is a small tmpfs, which would fill up the RAM
+
+    #[arg(long)]
+    temp_dir: Option<PathBuf>,
+
+    /// the casing of the extension of the output files, for frontends which only find e.g. .CHD files.
+    /// existing outputs are found regardless of the casing they were created with
+
+    #[arg(long, value_enum, default_value_t = ExtCase::Lower)]
+    ext_case: ExtCase,
+
+    /// write the outputs below this directory instead of next to their inputs,
+    /// at the same path the inputs have below the location they were found in.
+    /// missing directories get created
+
+    #[arg(short, long, conflicts_with_all = ["rewrite_playlists", "estimate"])]
+    output: Option<PathBuf>,
+
+    /// skip files whose inputs are smaller than the given size, e.g. 500K, 700MB or 1.5GiB.
+    /// units without an i are decimal. the inputs of a cue or gdi file include its tracks
+
+    #[arg(long, value_parser = parse_size)]
+    min_size: Option<u64>,
+
+    /// skip files whose inputs are larger than the given size, see --min-size
+
+    #[arg(long, value_parser = parse_size)]
+    max_size: Option<u64>,
+
+    /// delete the output again and keep the input if the conversion saved less than the given percentage
+    /// of the input size, e.g. 5 or 5%. 0 only drops outputs which are larger than their input.
+    /// recompressed arcade sets and cso images are always kept only if they got smaller
+
+    #[arg(long, value_parser = parse_percentage)]
+    min_savings: Option<f64>,
+
+    /// how the sizes of inputs and outputs are measured for the savings, --min-size, --max-size, --min-savings,
+    /// --io-budget and the reports. ondisk counts the allocated space, which differs between filesystems
+    /// and is smaller for sparse files or on compressing filesystems, logical counts the length of the files
+
+    #[arg(long, value_enum, default_value_t = SizeMode::OnDisk)]
+    size_mode: SizeMode,
+
+    /// recompress PSP images which are cso or dax images already instead of skipping them.
+    /// a cso image is only replaced if the recompressed image is smaller
+
+    #[arg(long, action)]
+    recompress: bool,
+
+    /// delete input files after compression. the inputs are only deleted once the output was verified:
+    /// chd files with chdman verify, rvz files with dolphin-tool verify, zip and cso files by decompressing them
 
     #[arg(short = 'R', long = "remove", action)]
     remove_after_compression: bool,
 
     /// flatten directory structure by moving the output file into parent directories until its not the only file in the directory anymore.
-    /// can only be used in conjunction with --remove,
-    /// can only be used if the input location is a directory, flatten will never move files outside that given location
+    /// can only be used in conjunction with --remove or --output,
+    /// can only be used if the input locations are directories, flatten will never move files outside the location they were found in
+    /// or outside of --output
 
     #[arg(short, long, action)]
     flatten: bool,
-}
 
-#[derive(ValueEnum, Clone, Eq, PartialEq, Debug)]
-enum SourceRomFormat {
-    Nds,
-    N64,
-    Psx,
-    Ps2,
-    Psp,
-    Wii,
+    /// don't check input files for obvious damage (e.g. truncated iso files) before compressing them
+
+    #[arg(long, action)]
+    no_sanity_checks: bool,
+
+    /// hunk size in bytes used when creating CHD files.
+    /// must be a multiple of the CD frame size (2448 bytes) and no larger than 1 MiB.
+    /// uses chdman's default if not given. only used for CDs, DVD images (PS2 DVDs and PS3 discs)
+    /// always use chdman's default, a warning is logged for each of them
+
+    #[arg(long, value_parser = parse_chd_hunk_size)]
+    chd_hunk_size: Option<u32>,
+
+    /// block size in bytes used when creating RVZ files, a power of 2 between 32 KiB and 2 MiB.
+    /// larger blocks compress better, but make random access slower. 128 KiB if not given
+
+    #[arg(long, value_parser = parse_rvz_block_size)]
+    rvz_block_size: Option<u32>,
+
+    /// compression codec used when creating RVZ files, uses the codec of --preset if not given
+
+    #[arg(long, value_enum)]
+    rvz_compression: Option<RvzCodec>,
+
+    /// compression level used when creating RVZ files, 1 to 22 for zstd and 1 to 9 for bzip2, lzma and lzma2.
+    /// none doesn't take a level. uses the level of --preset if not given, limited to the range of the codec
+
+    #[arg(long)]
+    rvz_level: Option<u8>,
+
+    /// remove the unused parts of GameCube and Wii discs when creating RVZ files, using dolphin-tool's scrubbing.
+    /// rvz stores the junk data of the discs efficiently already, so this only helps discs with other unused data.
+    /// irreversible, the scrubbed discs no longer match their Redump hashes, so the originals can't be removed with --remove.
+    /// the scrubbed outputs are always checked with dolphin-tool verify
+
+    #[arg(long, action, conflicts_with = "remove_after_compression")]
+    rvz_scrub: bool,
+
+    /// defaults for the compression settings of every format, explicit settings like --chd-hunk-size, --rvz-level
+    /// or the rvz settings of sidecar files take precedence.
+    /// fast: chdman cdzl and cdfl only (zlib and huff for DVDs), rvz zstd level 1, maxcso --fast, cso and zip deflate level 1, zstd level 1, flac --fast.
+    /// balanced: the defaults of every tool, rvz zstd level 5, zip deflate level 6, zstd level 3.
+    /// max: rvz zstd level 22, maxcso --use-zopfli, zip zopfli, zstd level 19, which takes much longer
+
+    #[arg(long, value_enum, default_value_t = Preset::Balanced, alias = "compression-preset")]
+    preset: Preset,
+
+    /// update m3u playlists after compression so that they reference the compressed files instead.
+    /// recommended in conjunction with --remove, which would otherwise leave playlists pointing to deleted files
+
+    #[arg(long, action)]
+    rewrite_playlists: bool,
+
+    /// when using the auto format, take the format of each file from the name of the
+    /// top-level folder it is located in below the input location, e.g. PSX/Game/Game.cue.
+    /// folder names are matched case-insensitively against the format names (nds, n64, psx, ps2, psp, wii, gcn, dc).
+    /// files in other folders or directly inside the input location fall back to detecting the format from the file itself
+
+    #[arg(long, action)]
+    format_by_dir: bool,
+
+    /// don't ignore hidden files and directories and operating system clutter like Thumbs.db during the directory walk.
+    /// additional names to ignore can be set as glob patterns with the ignore list of the config file
+
+    #[arg(long, action)]
+    no_ignore_junk: bool,
+
+    /// only process files whose path relative to their location matches one of the given glob patterns,
+    /// e.g. "USA/**" or "*.iso". can be given multiple times
+
+    #[arg(long)]
+    include: Vec<String>,
+
+    /// skip files whose path relative to their location matches one of the given glob patterns,
+    /// even if they match --include. can be given multiple times
+
+    #[arg(long)]
+    exclude: Vec<String>,
+
+    /// periodically write the progress of the run as JSON to the given file, e.g. for monitoring
+
+    #[arg(long)]
+    status_file: Option<PathBuf>,
+
+    /// recompress files whose output already exists if the input file was modified more recently than the output
+
+    #[arg(short, long, action)]
+    update: bool,
+
+    /// follow symlinks to files and directories inside the locations, which are skipped otherwise.
+    /// a file reachable through several links is only converted once, and links back to their own parent
+    /// directories are skipped with a warning. the outputs are created next to the links
+
+    #[arg(long, action)]
+    follow_symlinks: bool,
+
+    /// recompress files whose output already exists, e.g. after changing the compression settings.
+    /// the existing output is deleted right before the conversion starts, with --remove the input is still only
+    /// deleted once the new output was verified
+
+    #[arg(long, action, conflicts_with = "update")]
+    overwrite: bool,
+
+    /// print the space saved per directory after the run
+
+    #[arg(long, action)]
+    report_by_dir: bool,
+
+    /// write the input, output, sizes and outcome of every file plus the totals of the run as JSON
+    /// to the given file once finished
+
+    #[arg(long, conflicts_with_all = ["dry_run", "estimate", "print_commands", "emit_jobs"])]
+    report: Option<PathBuf>,
+
+    /// record the size, CRC32 and SHA-1 of every input rom in the --report, e.g. to check the dumps against
+    /// No-Intro or Redump DATs after they were removed. every track of a disc is hashed on its own,
+    /// a rom extracted from an archive instead of the archive
+
+    #[arg(long, action, requires = "report")]
+    hash: bool,
+
+    /// compress a random sample of the given amount of files (5 by default, at least one of each format if possible)
+    /// and estimate the savings of all files from it, instead of compressing everything. the amount is given as e.g. --estimate=10.
+    /// the samples are compressed next to their inputs in hidden directories, which get deleted afterwards
+
+    #[arg(long, num_args = 0..=1, default_missing_value = "5", conflicts_with_all = ["s3", "print_commands", "emit_jobs", "remove_after_compression"])]
+    estimate: Option<NonZeroUsize>,
+
+    /// record the outputs of the run in the given JSON index file, so the library can be queried without reading every file.
+    /// each entry holds the sizes, the SHA-1 and the header details of chd (track layout) and rvz files (game id and title).
+    /// entries of earlier runs are kept as long as their outputs exist
+
+    #[arg(long)]
+    index: Option<PathBuf>,
+
+    /// record every converted input in the given file as soon as it's done, and skip the inputs recorded in it
+    /// by earlier runs. this resumes an interrupted run without relying on the outputs, which --flatten or --s3-remove-local
+    /// might have moved away. an input is converted again if its size or modification time changed
+
+    #[arg(long, conflicts_with_all = ["dry_run", "estimate", "print_commands", "emit_jobs"])]
+    state: Option<PathBuf>,
+
+    /// copy extended attributes (e.g. Finder tags or SELinux labels) from the input file to the output file.
+    /// for multi-file inputs, the attributes of the main file (e.g. the cue sheet) are copied
+
+    #[arg(long, action)]
+    preserve_xattrs: bool,
+
+    /// change the mode of every output file to the given octal mode, e.g. 644 (Unix only)
+
+    #[arg(long, value_parser = parse_mode)]
+    output_mode: Option<u32>,
+
+    /// change the owner of every output file, given as user:group, user or :group (Unix only, usually requires root)
+
+    #[arg(long, value_parser = parse_owner)]
+    output_owner: Option<Owner>,
+
+    /// archive bin / cue images containing audio tracks into a zip file instead of converting them to chd.
+    /// audio tracks get compressed losslessly with flac, data tracks are kept as they are
+
+    #[arg(long, action)]
+    audio_flac: bool,
+
+    /// wrap single file roms (iso, n64, v64, z64, ndd and nds) into the zstd seekable format (.zst) instead of converting them,
+    /// which compresses them without giving up fast random access. no external tools are needed for these roms
+
+    #[arg(long, action)]
+    zstd_seekable: bool,
+
+    /// the amount of uncompressed data in each frame of the files created by --zstd-seekable, in bytes.
+    /// smaller frames allow faster seeking, larger ones compress better. 1 MiB by default
+
+    #[arg(long, value_parser = parse_zstd_frame_size, requires = "zstd_seekable")]
+    zstd_frame_size: Option<u32>,
+
+    /// decompress the files created by romcomp back into the original roms instead of compressing anything:
+    /// chd files with chdman, rvz files with dolphin-tool, cso files, zip files holding a single rom
+    /// and the .zst files created by --zstd-seekable.
+    /// the format is ignored, existing files are never overwritten
+
+    #[arg(long, action, conflicts_with_all = ["zstd_seekable", "remove_after_compression", "print_commands"])]
+    extract: bool,
+
+    /// convert the rom inside zip and 7z archives holding a single one, e.g. a zipped iso or a cue sheet with its bin files.
+    /// each archive is extracted into the temporary directory once its conversion is about to start,
+    /// the output is written next to the archive and --remove deletes the archive
+
+    #[arg(long, action, conflicts_with_all = ["extract", "print_commands", "emit_jobs", "run_jobs", "estimate"])]
+    extract_archives: bool,
+
+    /// encrypt zip outputs with AES-256 using the given password, which is better passed with the environment variable.
+    /// emulators can't read encrypted zip files, so this is only meant for archiving and transferring roms.
+    /// requires the encrypt feature
+
+    #[arg(long, env = "ROMCOMP_ENCRYPT_PASSWORD", hide_env_values = true)]
+    encrypt_password: Option<String>,
+
+    /// print why each file gets compressed or skipped: the detected format, the selected tool and the output path.
+    /// more detailed than --verbose, but only about the decisions
+
+    #[arg(long, action)]
+    explain: bool,
+
+    /// abort if any tool needed for the selected format (or any format when using auto) is missing or older than
+    /// its minimum version from the min_versions table of the config file, instead of skipping the affected roms,
+    /// falling back to the internal cso encoder or only warning about the version
+
+    #[arg(long, action)]
+    strict: bool,
+
+    /// stop starting new conversions once a conversion failed, e.g. because a tool crashes on every file.
+    /// conversions which are running already get finished. all files are converted regardless of failures by default
+
+    #[arg(long, action)]
+    fail_fast: bool,
+
+    /// how often a tool which failed gets run again before its conversion fails, e.g. because the file was locked
+    /// by another program. the delay between the attempts starts at a second and doubles with every attempt
+
+    #[arg(long, default_value_t = 0)]
+    retries: usize,
+
+    /// only print the shell command converting each file, e.g. to run them on other machines, and exit without converting anything.
+    /// files which romcomp has to process itself (e.g. zipping) are listed as comments
+
+    #[arg(long, action, conflicts_with = "tui")]
+    print_commands: bool,
+
+    /// print the planned output of every file, whether it would be skipped and the size of its inputs,
+    /// without converting, moving or deleting anything. --flatten is taken into account as far as it can be predicted
+
+    #[arg(long, action, conflicts_with_all = ["print_commands", "emit_jobs", "estimate", "index", "generate_playlist", "rewrite_playlists"])]
+    dry_run: bool,
+
+    /// write every planned conversion as a JSON job to the given file and exit without converting anything.
+    /// each job lists its input files, its format, the command running it and its expected output
+
+    #[arg(long, conflicts_with = "print_commands")]
+    emit_jobs: Option<PathBuf>,
+
+    /// convert the jobs of a file written by --emit-jobs instead of searching the location for roms,
+    /// which is taken from the job file as well. the settings (e.g. the tools and --remove) are the ones of this run
+
+    #[arg(long, conflicts_with_all = ["location", "format", "emit_jobs", "print_commands", "also", "extract"])]
+    run_jobs: Option<PathBuf>,
+
+    /// only run a slice of the jobs of --run-jobs, given as i/n, e.g. 2/4 for the second of four machines
+
+    #[arg(long, requires = "run_jobs")]
+    shard: Option<Shard>,
+
+    /// serve /healthz (liveness) and /metrics (the counters in the Prometheus text format) over HTTP
+    /// on the given address, e.g. 0.0.0.0:9100, while running
+
+    #[arg(long)]
+    http_status: Option<SocketAddr>,
+
+    /// upload every output to S3-compatible storage, given as bucket/prefix. requires the s3 feature.
+    /// the endpoint (AWS_ENDPOINT_URL), region (AWS_REGION) and credentials (AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY,
+    /// AWS_SESSION_TOKEN) are read from the environment
+
+    #[arg(long)]
+    s3: Option<S3Target>,
+
+    /// delete the local output once it was uploaded with --s3
+
+    #[arg(long, action, requires = "s3")]
+    s3_remove_local: bool,
+
+    /// show a full-screen view of the running conversions instead of the line-based output
+
+    #[arg(long, action)]
+    tui: bool,
+
+    /// show an overall progress bar and a spinner per running conversion on stderr.
+    /// if stdout is the same terminal, only finished and failed files are printed while it is shown
+
+    #[arg(long, action, conflicts_with_all = ["tui", "print_commands", "emit_jobs", "dry_run"])]
+    progress: bool,
+
+    /// how to create cso files. uses maxcso if it is available and falls back to the internal encoder otherwise
+
+    #[arg(long, value_enum)]
+    cso_backend: Option<CsoBackend>,
+
+    /// the format of the PSP images created by maxcso. zso images end with .zso, dax images with .dax.
+    /// the internal encoder only creates cso1 images, so maxcso is required for the others
+
+    #[arg(long, value_enum, default_value_t = CsoFormat::Cso1)]
+    cso_format: CsoFormat,
+
+    /// block size in bytes used by maxcso, a power of 2 between 2 KiB and 1 MiB.
+    /// uses maxcso's default, which depends on the size of the image, if not given. dax images always use 8 KiB.
+    /// maxcso uses as many threads as --threads allows conversions
+
+    #[arg(long, value_parser = parse_cso_block_size)]
+    cso_block: Option<u32>,
+
+    /// also convert files with the other tools their format supports and keep whichever output is the smallest,
+    /// e.g. chd or cso for ps2 isos
+
+    #[arg(long, action)]
+    best_of: bool,
+
+    /// leave out the padding (0x00 or 0xFF bytes) at the end of n64 roms when zipping them.
+    /// the input files are never modified, nds roms are always trimmed
+
+    #[arg(long, action)]
+    trim: bool,
+
+    /// how to convert n64 and v64 roms into z64 roms before zipping them
+
+    #[arg(long, value_enum, default_value_t = N64Backend::Internal)]
+    n64_backend: N64Backend,
+
+    /// the compression method of the zip files created for n64 and nds roms. stored is much faster and hardly larger
+    /// for trimmed nds roms. zstd and bzip2 need romcomp to be built with the zip-zstd or zip-bzip2 feature
+    /// and can't be read by most emulators
+
+    #[arg(long, value_enum, default_value_t = ZipMethod::Deflated)]
+    zip_method: ZipMethod,
+
+    /// write a playlist of all compressed files to the given path after the run
+
+    #[arg(long)]
+    generate_playlist: Option<PathBuf>,
+
+    /// the kind of playlist written by --generate-playlist
+
+    #[arg(long, value_enum, default_value_t = PlaylistFormat::RetroArch, requires = "generate_playlist")]
+    playlist_format: PlaylistFormat,
+
+    /// hash all outputs and detect outputs with identical content, e.g. the same game stored under different names
+
+    #[arg(long, action)]
+    detect_dupe_outputs: bool,
+
+    /// what to do with duplicate outputs found by --detect-dupe-outputs
+
+    #[arg(long, value_enum, default_value_t = DuplicateAction::Report, requires = "detect_dupe_outputs")]
+    dupe_action: DuplicateAction,
+
+    /// additionally compress every compatible file into this format, e.g. a ps2 iso into both chd and cso with --also psp.
+    /// can be given multiple times
+
+    #[arg(long, value_enum, conflicts_with = "remove_after_compression")]
+    also: Vec<SourceRomFormat>,
 }
 
-fn ctrl_channel() -> Result<Receiver<()>> {
-    let (sender, receiver) = bounded(100);
+#[derive(Subcommand)]
+enum Command {
+    /// print what romcomp detects in a single file without converting it:
+    /// the detected formats, the magic bytes, the cue sheet tracks and the headers of iso, wii, n64 and nds images
+    Probe {
+        file: PathBuf,
 
-    ctrlc::set_handler(move || {
-        let _ = sender.send(());
-    })?;
+        /// print the details as JSON
+
+        #[arg(long, action)]
+        json: bool,
+    },
+    /// list the roms romcomp finds below the locations without converting them:
+    /// the detected formats, the output each one would be converted into, its size and whether the output exists already
+    Scan {
+        #[arg(required = true, num_args = 1..)]
+        location: Vec<PathBuf>,
+
+        /// prefer the format named by the top-level folder, see the --format-by-dir option of the conversion
+
+        #[arg(long, action)]
+        format_by_dir: bool,
+
+        /// follow symlinks, see the --follow-symlinks option of the conversion
+
+        #[arg(long, action)]
+        follow_symlinks: bool,
+
+        /// path to a TOML config file, only its ignore list is used
+
+        #[arg(short, long)]
+        config: Option<PathBuf>,
+
+        /// print the files as JSON
 
-    Ok(receiver)
+        #[arg(long, action)]
+        json: bool,
+    },
 }
 
-fn main() -> Result<ExitCode> {
-    let ctrl_c_events = ctrl_channel()?;
-    let cli = Cli::parse();
+/// the CPU cores this process may use, a single one if that is unknown
+fn available_cores() -> usize {
+    std::thread::available_parallelism().map_or(1, NonZeroUsize::get)
+}
 
-    let location = canonicalize(cli.location.clone());
+fn parse_threads(value: &str) -> Result<usize, String> {
+    let fraction = if let Some(percentage) = value.strip_suffix('%') {
+        percentage
+            .trim()
+            .parse::<f64>()
+            .map_err(|e| e.to_string())?
+            / 100f64
+    } else if value.contains('.') {
+        value.parse::<f64>().map_err(|e| e.to_string())?
+    } else {
+        // no conversion could ever start without a thread
+        return match value.parse::<usize>().map_err(|e| e.to_string())? {
+            0 => Err("at least 1 thread is needed".to_string()),
+            threads => Ok(threads),
+        };
+    };
 
-    if !location.as_ref().map(|l| l.exists()).unwrap_or(false) {
-        println!("The path {} doesn't exist.", cli.location.to_str().unwrap());
-        return Ok(ExitCode::from(1));
+    if !fraction.is_finite() || fraction <= 0f64 {
+        return Err(format!(
+            "{} isn't a valid fraction of the available CPU cores",
+            value
+        ));
     }
 
-    let location = location.unwrap();
+    Ok(((available_cores() as f64 * fraction).floor() as usize).max(1))
+}
 
-    let fmt = match cli.format {
-        SourceRomFormat::N64 => RomFormat::Nintendo64,
-        SourceRomFormat::Psx => RomFormat::PlayStationX,
-        SourceRomFormat::Ps2 => RomFormat::PlayStation2,
-        SourceRomFormat::Psp => RomFormat::PlayStationPortable,
-        SourceRomFormat::Nds => RomFormat::NintendoDS,
-        SourceRomFormat::Wii => RomFormat::NintendoWii,
-    };
+/// parses percentages like 5, 5% or 12.5%, between 0 and 100
+fn parse_percentage(value: &str) -> Result<f64, String> {
+    let percentage = value
+        .trim()
+        .strip_suffix('%')
+        .unwrap_or(value.trim())
+        .trim()
+        .parse::<f64>()
+        .map_err(|e| e.to_string())?;
 
-    if cli.flatten && !cli.remove_after_compression {
-        println!("--flatten can only be used in conjunction with the --remove parameter.");
-        return Ok(ExitCode::from(1));
+    if !(0f64..=100f64).contains(&percentage) {
+        return Err(format!("{} isn't a percentage between 0 and 100", value));
     }
 
-    if cli.flatten && !location.is_dir() {
-        println!("--flatten can only be used if the input location is a directory");
-        return Ok(ExitCode::from(1));
+    Ok(percentage)
+}
+
+/// parses sizes like 700MB, 1.5GiB or 500M. units without an i are decimal, like the sizes romcomp prints
+fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number = number
+        .parse::<f64>()
+        .map_err(|_| format!("{} isn't a valid size", value))?;
+
+    let unit = unit.trim().to_lowercase();
+    let (prefix, base) = match unit.strip_suffix("ib") {
+        Some(prefix) => (prefix, 1024f64),
+        None => (unit.strip_suffix('b').unwrap_or(&unit), 1000f64),
+    };
+    let exponent = match prefix {
+        "" => 0,
+        "k" => 1,
+        "m" => 2,
+        "g" => 3,
+        "t" => 4,
+        _ => return Err(format!("{} isn't a known unit", unit)),
+    };
+
+    Ok((number * base.powi(exponent)).round() as u64)
+}
+
+fn parse_rvz_block_size(value: &str) -> Result<u32, String> {
+    const MIN_BLOCK_SIZE: u32 = 32 * 1024;
+    const MAX_BLOCK_SIZE: u32 = 2 * 1024 * 1024;
+
+    let size = value.parse::<u32>().map_err(|e| e.to_string())?;
+
+    if !size.is_power_of_two() || !(MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE).contains(&size) {
+        return Err(format!(
+            "{} must be a power of 2 between {} and {}",
+            size, MIN_BLOCK_SIZE, MAX_BLOCK_SIZE
+        ));
     }
 
-    if cli.format == SourceRomFormat::Psx || cli.format == SourceRomFormat::Ps2 {
-        match Command::new("chdman")
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .spawn()
-        {
-            Err(e) => {
-                if let ErrorKind::NotFound = e.kind() {
-                    println!("You'll need to have CHDMAN available on your PATH if you want to convert these ROMs. Please run this application from Docker or install CHDMAN manually and try again.");
-                    return Ok(ExitCode::from(2));
-                }
-            }
-            _ => (),
-        }
+    Ok(size)
+}
+
+fn parse_cso_block_size(value: &str) -> Result<u32, String> {
+    // a block holds at least a whole sector of the image
+    const MIN_BLOCK_SIZE: u32 = 2048;
+    const MAX_BLOCK_SIZE: u32 = 1024 * 1024;
+
+    let size = value.parse::<u32>().map_err(|e| e.to_string())?;
+
+    if !size.is_power_of_two() || !(MIN_BLOCK_SIZE..=MAX_BLOCK_SIZE).contains(&size) {
+        return Err(format!(
+            "{} must be a power of 2 between {} and {}",
+            size, MIN_BLOCK_SIZE, MAX_BLOCK_SIZE
+        ));
     }
 
-    if cli.format == SourceRomFormat::N64 {
-        match Command::new("rom64")
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .spawn()
-        {
-            Err(e) => {
-                if let ErrorKind::NotFound = e.kind() {
-                    println!("You'll need to have ROM64 available on your PATH if you want to convert these ROMs. Please run this application from Docker or install ROM64 manually and try again.");
-                    return Ok(ExitCode::from(2));
-                }
-            }
-            _ => (),
-        }
+    Ok(size)
+}
+
+fn parse_zstd_frame_size(value: &str) -> Result<u32, String> {
+    const MAX_FRAME_SIZE: u32 = 256 * 1024 * 1024;
+
+    let size = value.parse::<u32>().map_err(|e| e.to_string())?;
+
+    if size == 0 || size > MAX_FRAME_SIZE {
+        return Err(format!(
+            "{} must be larger than 0 and no larger than {}",
+            size, MAX_FRAME_SIZE
+        ));
     }
 
-    if cli.format == SourceRomFormat::Nds {
-        match Command::new("BitButcher")
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .spawn()
-        {
-            Err(e) => {
-                if let ErrorKind::NotFound = e.kind() {
-                    println!("You'll need to have BITBUTCHER available on your PATH if you want to convert these ROMs. Please run this application from Docker or install BITBUTCHER manually and try again.");
-                    return Ok(ExitCode::from(2));
-                }
-            }
-            _ => (),
+    Ok(size)
+}
+
+/// the names of the files as a list for messages
+fn file_names(files: &[PathBuf]) -> String {
+    files
+        .iter()
+        .map(|f| f.file_name().unwrap().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// whether a file is selected by --include and --exclude, matched against its path relative to its location.
+/// a file given as location itself is matched by its name
+fn is_selected(file: &Path, locations: &[PathBuf], include: &GlobSet, exclude: &GlobSet) -> bool {
+    let relative = root_of(file, locations)
+        .and_then(|r| file.strip_prefix(r).ok())
+        .filter(|r| !r.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new(file.file_name().unwrap()));
+
+    (include.is_empty() || include.is_match(relative)) && !exclude.is_match(relative)
+}
+
+/// the files of a symlink loop, the link and the directory it points back to
+fn file_loop(error: &ignore::Error) -> Option<(&Path, &Path)> {
+    match error {
+        ignore::Error::Loop { ancestor, child } => Some((child, ancestor)),
+        ignore::Error::WithPath { err, .. } | ignore::Error::WithDepth { err, .. } => {
+            file_loop(err)
         }
+        _ => None,
     }
+}
 
-    if cli.format == SourceRomFormat::Psp {
-        match Command::new("maxcso")
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .spawn()
-        {
-            Err(e) => {
-                if let ErrorKind::NotFound = e.kind() {
-                    println!("You'll need to have MAXCSO available on your PATH if you want to convert these ROMs. Please run this application from Docker or install MAXCSO manually and try again.");
-                    return Ok(ExitCode::from(2));
-                }
-            }
-            _ => (),
-        }
+/// all files below the locations, or the locations themselves if they are files.
+/// with follow_links, a file reachable through several symlinks is only returned once
+fn walk(
+    locations: &[PathBuf],
+    junk: GlobSet,
+    follow_links: bool,
+) -> Box<dyn Iterator<Item = PathBuf>> {
+    let files = locations
+        .iter()
+        .filter(|l| !l.is_dir())
+        .cloned()
+        .collect::<Vec<_>>();
+    let mut dirs = locations.iter().filter(|l| l.is_dir());
+
+    let Some(first) = dirs.next() else {
+        return Box::new(files.into_iter());
+    };
+
+    let mut builder = WalkBuilder::new(first);
+    let mut visited = HashSet::new();
+
+    for dir in dirs {
+        builder.add(dir);
     }
 
-    if cli.format == SourceRomFormat::Wii {
-        match Command::new("dolphin-tool")
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .spawn()
-        {
-            Err(e) => {
-                if let ErrorKind::NotFound = e.kind() {
-                    println!("You'll need to have DOLPHIN-TOOL available on your PATH if you want to convert these ROMs. Please run this application from Docker or install DOLPHIN-TOOL manually and try again.");
-                    return Ok(ExitCode::from(2));
-                }
-            }
-            _ => (),
+    Box::new(
+        files.into_iter().chain(
+            builder
+                .standard_filters(false)
+                .follow_links(follow_links)
+                .add_custom_ignore_filename(".romcompignore")
+                .filter_entry(move |e| e.depth() == 0 || !junk.is_match(e.file_name()))
+                .build()
+                .filter_map(|e| {
+                    // the walk doesn't descend into the loop, everything else below the link is still found
+                    if let Some((link, ancestor)) = e.as_ref().err().and_then(file_loop) {
+                        warn!(
+                            "Skipping {}: It links back to {}",
+                            link.display(),
+                            ancestor.display()
+                        );
+                    }
+
+                    e.ok()
+                })
+                .filter(|e| e.file_type().is_some_and(|t| t.is_file()))
+                .map(|e| e.into_path())
+                .filter(move |f| {
+                    !follow_links || canonicalize(f).map_or(true, |c| visited.insert(c))
+                }),
+        ),
+    )
+}
+
+/// picks the format of a file when using the auto format.
+/// the top-level folder name is preferred if --format-by-dir is used, otherwise the file must only be compatible
+/// with a single available format, after ruling out the consoles its content doesn't fit.
+/// returns the remaining candidates if the format couldn't be determined.
+/// the image is the file whose content is read, which differs from the file for roms extracted from archives
+fn detect_format(
+    file: &Path,
+    image: &Path,
+    guess: RomFormat,
+    root: &Path,
+    available: RomFormat,
+    format_by_dir: bool,
+) -> Result<RomFormat, RomFormat> {
+    let candidates = guess & available & RomFormat::CONSOLES;
+
+    if format_by_dir {
+        let dir_format = file
+            .strip_prefix(root)
+            .ok()
+            .filter(|p| p.components().count() > 1)
+            .and_then(|p| p.components().next())
+            .and_then(|c| c.as_os_str().to_str())
+            .and_then(|c| SourceRomFormat::from_str(c, true).ok())
+            .and_then(|f| f.rom_format());
+
+        if let Some(f) = dir_format.filter(|f| candidates.contains(*f)) {
+            return Ok(f);
         }
     }
 
-    if location.is_file()
-        && !guess_file(&location)
-            .map(|f| f.contains(fmt))
-            .unwrap_or(false)
-    {
-        println!(
-            "The input file isn't recognized as proper file format for a {:?} rom",
-            cli.format
-        );
-        return Ok(ExitCode::from(1));
+    // e.g. an iso fits every PlayStation and the Wii by its extension, reading it is only worth it then
+    let candidates = match candidates.bits().count_ones() {
+        0 | 1 => candidates,
+        _ => content_formats(image)
+            .map(|c| candidates & c)
+            .filter(|c| !c.is_empty())
+            .unwrap_or(candidates),
+    };
+
+    match candidates.bits().count_ones() {
+        1 => Ok(candidates),
+        _ => Err(candidates),
     }
+}
 
-    let tmp = tempdir()?;
+/// the scan command, walks the locations like a conversion with the auto format but only lists the files.
+/// every format counts as available, the tools aren't looked up
+fn scan(
+    requested_locations: &[PathBuf],
+    config: Option<&Path>,
+    format_by_dir: bool,
+    follow_symlinks: bool,
+    json: bool,
+) -> Result<ExitCode> {
+    let mut locations: Vec<PathBuf> = vec![];
 
-    let converter = Converter::new(&location, tmp, cli.threads, ctrl_c_events.clone())
-        .verbose(cli.verbose)
-        .remove_after_compression(cli.remove_after_compression)
-        .flatten(cli.flatten);
-
-    println!(
-        "Start ROM compression with {} simultaneous processes",
-        cli.threads
-    );
-
-    if location.is_dir() {
-        for entry in WalkDir::new(location).into_iter().filter_map(|e| e.ok()) {
-            if entry.file_type().is_file() {
-                let guess = guess_file(&entry.path().to_path_buf());
-                if guess.is_some_and(|f| f.contains(fmt)) {
-                    if !ctrl_c_events.is_empty() {
-                        break;
-                    }
-                    converter.convert(
-                        &entry.path().to_path_buf(),
-                        (guess.unwrap() & RomFormat::FILE_FORMATS) | fmt,
-                    );
-                }
+    for requested_location in requested_locations.iter() {
+        match canonicalize(requested_location) {
+            Ok(location) if !locations.contains(&location) => locations.push(location),
+            Ok(_) => {}
+            Err(_) => {
+                error!("The path {} doesn't exist.", requested_location.display());
+                return Ok(ExitCode::from(1));
             }
         }
-    } else {
-        converter.convert(
-            &location,
-            (guess_file(&location).unwrap() & RomFormat::FILE_FORMATS) | fmt,
-        );
     }
 
-    converter.finish();
+    let config = match config.map(Config::load).transpose() {
+        Ok(config) => config.unwrap_or_default(),
+        Err(e) => {
+            error!(
+                "The config file {} couldn't be read: {}",
+                config.unwrap().display(),
+                e
+            );
+            return Ok(ExitCode::from(1));
+        }
+    };
 
-    Ok(ExitCode::from(0))
+    let junk = match junk_matcher(&config.ignore) {
+        Ok(junk) => junk,
+        Err(e) => {
+            error!("Invalid ignore pattern in the config file: {}", e);
+            return Ok(ExitCode::from(1));
+        }
+    };
+
+    // only used for the output names, nothing gets converted
+    let (_, interrupt) = bounded(0);
+    let converter = Converter::new(&locations, tempdir()?, 1, interrupt);
+    // arcade romsets are never picked by the auto format
+    let available = RomFormat::CONSOLES.difference(RomFormat::Arcade);
+    let mut entries = vec![];
+
+    for file in walk(&locations, junk, follow_symlinks) {
+        let Some(guess) = guess_file(&file) else {
+            debug!("Skipping {}: The file isn't recognized", file.display());
+            continue;
+        };
+
+        let root = root_of(&file, &locations).unwrap();
+        let format = detect_format(&file, &file, guess, root, available, format_by_dir)
+            .ok()
+            .filter(|_| !guess.contains(RomFormat::M3U));
+        let output = format.and_then(|f| {
+            converter.get_output_file_name(&file, (guess & RomFormat::FILE_FORMATS) | f)
+        });
+
+        entries.push(ScanEntry {
+            size: Converter::input_files(&file, guess)
+                .iter()
+                .filter_map(|f| f.metadata().ok())
+                .map(|m| m.len())
+                .sum(),
+            detected: flag_names(guess),
+            format: format.map(|f| flag_names(f).join(" | ")),
+            output_exists: output.as_ref().is_some_and(|o| o.exists()),
+            output,
+            file,
+        });
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+    } else {
+        print_scan(&entries);
+    }
+
+    Ok(ExitCode::from(0))
+}
+
+/// the first receiver gets every ctrl+c, the second one every ctrl+c but the first.
+/// the first ctrl+c lets the running conversions finish, any further one aborts them
+fn ctrl_channel() -> Result<(Receiver<()>, Receiver<()>)> {
+    let (sender, receiver) = bounded(100);
+    let (abort_sender, abort_receiver) = bounded(100);
+
+    ctrlc::set_handler(move || {
+        if !sender.is_empty() {
+            let _ = abort_sender.send(());
+        }
+
+        let _ = sender.send(());
+    })?;
+
+    Ok((receiver, abort_receiver))
+}
+
+fn main() -> Result<ExitCode> {
+    let (ctrl_c_events, abort_events) = ctrl_channel()?;
+    let cli = Cli::parse();
+
+    if let Err(e) = logging::init(cli.verbose, cli.log_file.as_deref()) {
+        println!(
+            "The log file {} couldn't be opened: {}",
+            cli.log_file.as_ref().unwrap().display(),
+            e
+        );
+        return Ok(ExitCode::from(1));
+    }
+
+    if let Some(Command::Probe { file, json }) = cli.command.as_ref() {
+        return match Probe::new(file.clone()) {
+            Ok(probe) if *json => {
+                println!("{}", serde_json::to_string_pretty(&probe)?);
+                Ok(ExitCode::from(0))
+            }
+            Ok(probe) => {
+                probe.print();
+                Ok(ExitCode::from(0))
+            }
+            Err(e) => {
+                error!("The file {} couldn't be probed: {}", file.display(), e);
+                Ok(ExitCode::from(1))
+            }
+        };
+    }
+
+    if let Some(Command::Scan {
+        location,
+        format_by_dir,
+        follow_symlinks,
+        config,
+        json,
+    }) = cli.command.as_ref()
+    {
+        return scan(
+            location,
+            config.as_deref(),
+            *format_by_dir,
+            *follow_symlinks,
+            *json,
+        );
+    }
+
+    // the worker threads and tools inherit the priority, so it has to be set before any of them starts
+    if let Some(nice) = cli.nice {
+        match set_nice(nice) {
+            Ok(()) => debug!("Running with the CPU priority {}", nice),
+            Err(e) if e.kind() == ErrorKind::Unsupported => {
+                warn!("--nice isn't supported on this platform, ignoring it.")
+            }
+            Err(e) => {
+                error!("The CPU priority couldn't be set to {}: {}", nice, e);
+                return Ok(ExitCode::from(1));
+            }
+        }
+    }
+
+    if let Some(class) = cli.ionice {
+        match set_io_class(class) {
+            Ok(()) => debug!("Running with the I/O scheduling class {:?}", class),
+            Err(e) if e.kind() == ErrorKind::Unsupported => {
+                warn!("--ionice isn't supported on this platform, ignoring it.")
+            }
+            Err(e) => {
+                error!("The I/O scheduling class couldn't be set: {}", e);
+                return Ok(ExitCode::from(1));
+            }
+        }
+    }
+
+    let job_file = match cli.run_jobs.as_ref().map(|j| JobFile::load(j)).transpose() {
+        Ok(job_file) => job_file,
+        Err(e) => {
+            error!(
+                "The job file {} couldn't be read: {}",
+                cli.run_jobs.as_ref().unwrap().display(),
+                e
+            );
+            return Ok(ExitCode::from(1));
+        }
+    };
+
+    let requested_locations = job_file
+        .as_ref()
+        .map(|j| j.roots.clone())
+        .unwrap_or_else(|| cli.location.clone());
+    let mut locations: Vec<PathBuf> = vec![];
+
+    for requested_location in requested_locations.iter() {
+        let location = canonicalize(requested_location);
+
+        if !location.as_ref().map(|l| l.exists()).unwrap_or(false) {
+            error!(
+                "The path {} doesn't exist.",
+                requested_location.to_str().unwrap()
+            );
+            return Ok(ExitCode::from(1));
+        }
+
+        let location = location.unwrap();
+
+        // the same location given twice would only be searched twice
+        if !locations.contains(&location) {
+            locations.push(location);
+        }
+    }
+
+    // an unusable temporary directory fails right away instead of after the tools were looked up
+    let tmp = match cli.temp_dir.as_ref() {
+        Some(dir) if !dir.is_dir() => {
+            error!("The temporary directory {} doesn't exist.", dir.display());
+            return Ok(ExitCode::from(1));
+        }
+        Some(dir) => match Builder::new().tempdir_in(dir) {
+            Ok(tmp) => tmp,
+            Err(e) => {
+                error!(
+                    "Couldn't create a temporary directory in {}: {}",
+                    dir.display(),
+                    e
+                );
+                return Ok(ExitCode::from(1));
+            }
+        },
+        None => tempdir()?,
+    };
+
+    if cli.format.len() > 1
+        && cli
+            .format
+            .iter()
+            .any(|f| matches!(f, SourceRomFormat::Auto | SourceRomFormat::Arcade))
+    {
+        error!("The auto and arcade formats can't be combined with other formats.");
+        return Ok(ExitCode::from(1));
+    }
+
+    let requested: Vec<RomFormat> = cli.format.iter().filter_map(|f| f.rom_format()).collect();
+    // a single format is forced onto every file, several ones are detected like the auto format does.
+    // jobs can be of any format
+    let fmt = match requested.as_slice() {
+        [fmt] => Some(*fmt),
+        _ => None,
+    };
+
+    if cli.format_by_dir && fmt.is_some() {
+        error!("--format-by-dir can only be used in conjunction with the auto format or several formats.");
+        return Ok(ExitCode::from(1));
+    }
+
+    // the output directory only contains outputs, so they can be flattened without removing the inputs
+    if cli.flatten && !cli.remove_after_compression && cli.output.is_none() {
+        error!(
+            "--flatten can only be used in conjunction with the --remove or --output parameter."
+        );
+        return Ok(ExitCode::from(1));
+    }
+
+    if fmt == Some(RomFormat::Arcade) && !cli.remove_after_compression {
+        error!("The arcade format replaces the original romsets, so it can only be used in conjunction with the --remove parameter.");
+        return Ok(ExitCode::from(1));
+    }
+
+    if fmt == Some(RomFormat::Arcade) && cli.extract_archives {
+        error!("The arcade format recompresses zip files as romsets, so it can't be used in conjunction with the --extract-archives parameter.");
+        return Ok(ExitCode::from(1));
+    }
+
+    if fmt == Some(RomFormat::Arcade) && cli.output.is_some() {
+        error!("The arcade format replaces the original romsets, so it can't be used in conjunction with the --output parameter.");
+        return Ok(ExitCode::from(1));
+    }
+
+    let output_root = match cli.output.as_ref() {
+        // a dry run must not create anything, the directory is created by the actual run
+        Some(output) if cli.dry_run => Some(output.canonicalize().or_else(|_| absolute(output))?),
+        Some(output) => match create_dir_all(output).and_then(|_| output.canonicalize()) {
+            Ok(o) => Some(o),
+            Err(e) => {
+                error!(
+                    "Couldn't create the output directory {}: {}",
+                    output.display(),
+                    e
+                );
+                return Ok(ExitCode::from(1));
+            }
+        },
+        None => None,
+    };
+
+    if cli.flatten && !locations.iter().all(|l| l.is_dir()) {
+        error!("--flatten can only be used if the input locations are directories");
+        return Ok(ExitCode::from(1));
+    }
+
+    match cli.encrypt_password.as_deref() {
+        Some("") => {
+            error!("--encrypt-password must not be empty.");
+            return Ok(ExitCode::from(1));
+        }
+        Some(_) if cfg!(not(feature = "encrypt")) => {
+            error!("--encrypt-password requires romcomp to be built with the encrypt feature.");
+            return Ok(ExitCode::from(1));
+        }
+        Some(_) => warn!(
+            "Warning: zip outputs get encrypted and can't be read by emulators anymore, keep the password safe."
+        ),
+        None => {}
+    }
+
+    if let Err(feature) = cli.zip_method.compression_method() {
+        error!(
+            "--zip-method {} requires romcomp to be built with the {} feature.",
+            cli.zip_method.to_possible_value().unwrap().get_name(),
+            feature
+        );
+        return Ok(ExitCode::from(1));
+    }
+
+    let config = match cli.config.as_ref().map(|c| Config::load(c)).transpose() {
+        Ok(config) => config.unwrap_or_default(),
+        Err(e) => {
+            error!(
+                "The config file {} couldn't be read: {}",
+                cli.config.as_ref().unwrap().display(),
+                e
+            );
+            return Ok(ExitCode::from(1));
+        }
+    };
+
+    let junk = if cli.no_ignore_junk {
+        GlobSet::empty()
+    } else {
+        match junk_matcher(&config.ignore) {
+            Ok(junk) => junk,
+            Err(e) => {
+                error!("Invalid ignore pattern in the config file: {}", e);
+                return Ok(ExitCode::from(1));
+            }
+        }
+    };
+
+    let (include, exclude) = match (path_matcher(&cli.include), path_matcher(&cli.exclude)) {
+        (Ok(include), Ok(exclude)) => (include, exclude),
+        (Err(e), _) | (_, Err(e)) => {
+            error!("Invalid --include or --exclude pattern: {}", e);
+            return Ok(ExitCode::from(1));
+        }
+    };
+
+    if cli.extract {
+        // a missing tool only skips the files which need it
+        let tools = [CompressionTool::Chdman, CompressionTool::DolphinTool]
+            .into_iter()
+            .filter_map(|tool| {
+                let candidates = config
+                    .tools
+                    .get(&tool)
+                    .cloned()
+                    .unwrap_or_else(|| vec![tool.to_string()]);
+
+                CompressionTool::resolve(&candidates).map(|binary| (tool, binary))
+            })
+            .collect();
+
+        return Ok(ExitCode::from(extract(
+            walk(&locations, junk, cli.follow_symlinks)
+                .filter(|f| is_selected(f, &locations, &include, &exclude)),
+            &tools,
+            &ctrl_c_events,
+        )));
+    }
+
+    let bios = match BiosDetector::new(&config.bios.names, &config.bios.files) {
+        Ok(bios) => bios,
+        Err(e) => {
+            error!("Invalid BIOS pattern in the config file: {}", e);
+            return Ok(ExitCode::from(1));
+        }
+    };
+
+    let mut compression_options = CompressionOptions {
+        chd_hunk_size: cli.chd_hunk_size,
+        rvz_level: cli.rvz_level,
+        rvz_codec: cli.rvz_compression,
+        rvz_block_size: cli.rvz_block_size,
+        rvz_scrub: cli.rvz_scrub,
+        cso_format: cli.cso_format,
+        cso_block_size: cli.cso_block,
+        cso_threads: Some(cli.threads),
+        zstd_frame_size: cli.zstd_frame_size,
+        zip_method: cli.zip_method,
+        encrypt_password: cli.encrypt_password.clone(),
+        preset: cli.preset,
+        ..Default::default()
+    };
+
+    if cli
+        .min_size
+        .zip(cli.max_size)
+        .is_some_and(|(min, max)| min > max)
+    {
+        error!("--min-size must not be larger than --max-size.");
+        return Ok(ExitCode::from(1));
+    }
+
+    if let Err(e) = compression_options.rvz_settings() {
+        error!("Invalid rvz settings: {}", e);
+        return Ok(ExitCode::from(1));
+    }
+
+    if cli.cso_format == CsoFormat::Dax && cli.cso_block.is_some_and(|s| s != 8192) {
+        error!("Invalid cso settings: dax images always use blocks of 8192 bytes");
+        return Ok(ExitCode::from(1));
+    }
+
+    if cli.cso_backend == Some(CsoBackend::Internal) {
+        if let Err(e) = compression_options.internal_cso_settings() {
+            error!("Invalid cso settings: {}", e);
+            return Ok(ExitCode::from(1));
+        }
+    }
+
+    // the formats whose tools are available.
+    // a missing tool is fatal if a specific format was requested, the auto format just skips the affected formats
+    let mut available = RomFormat::empty();
+    let mut missing: HashSet<CompressionTool> = HashSet::new();
+
+    if cli.also.contains(&SourceRomFormat::Auto) {
+        error!("--also requires a specific format.");
+        return Ok(ExitCode::from(1));
+    }
+
+    let also: Vec<RomFormat> = cli.also.iter().filter_map(|f| f.rom_format()).collect();
+
+    let formats: Vec<RomFormat> = if requested.is_empty() {
+        // any zip could be a romset, including the outputs of romcomp itself
+        SourceRomFormat::value_variants()
+            .iter()
+            .filter_map(|f| f.rom_format())
+            .filter(|f| *f != RomFormat::Arcade)
+            .collect()
+    } else {
+        requested.iter().chain(also.iter()).copied().collect()
+    };
+
+    if cli.cso_backend == Some(CsoBackend::Internal) {
+        compression_options.internal.insert(CompressionTool::MaxCSO);
+    }
+
+    if cli.n64_backend == N64Backend::Internal {
+        compression_options.internal.insert(CompressionTool::Rom64);
+    }
+
+    for f in formats {
+        // the roms of these consoles only come as single files, which --zstd-seekable wraps without any tool
+        if cli.zstd_seekable
+            && !f.intersects(
+                RomFormat::PlayStationX
+                    | RomFormat::PlayStation2
+                    | RomFormat::PlayStation3
+                    | RomFormat::NintendoWii
+                    | RomFormat::NintendoGameCube
+                    | RomFormat::Dreamcast,
+            )
+        {
+            available |= f;
+            continue;
+        }
+
+        if let Some(tool) = f.compression_tool() {
+            if !compression_options.binaries.contains_key(&tool)
+                && !compression_options.internal.contains(&tool)
+                && !missing.contains(&tool)
+            {
+                let candidates = config
+                    .tools
+                    .get(&tool)
+                    .cloned()
+                    .unwrap_or_else(|| vec![tool.to_string()]);
+
+                match CompressionTool::resolve(&candidates) {
+                    Some(binary) => {
+                        compression_options.binaries.insert(tool, binary);
+                    }
+                    None if tool == CompressionTool::MaxCSO
+                        && cli.cso_backend.is_none()
+                        && !cli.strict
+                        && compression_options.internal_cso_settings().is_ok() =>
+                    {
+                        warn!("MAXCSO isn't available on your PATH, using the internal cso encoder instead.");
+                        compression_options.internal.insert(tool);
+                    }
+                    None => {
+                        let name = tool.to_string().to_uppercase();
+
+                        if requested.contains(&f) || also.contains(&f) || cli.strict {
+                            error!("You'll need to have {} available on your PATH if you want to convert these ROMs. Please run this application from Docker or install {} manually and try again.", name, name);
+                            return Ok(ExitCode::from(2));
+                        }
+
+                        warn!(
+                            "{} isn't available on your PATH, ROMs which need it will be skipped.",
+                            name
+                        );
+                        missing.insert(tool);
+                    }
+                }
+            }
+
+            if missing.contains(&tool) {
+                continue;
+            }
+        }
+
+        available |= f;
+    }
+
+    // the formats the walk picks from, the ones of --also are only added to the files of the requested ones
+    let detectable = if requested.is_empty() {
+        available
+    } else {
+        available & requested.iter().fold(RomFormat::empty(), |a, f| a | *f)
+    };
+
+    if cli.audio_flac {
+        let tool = CompressionTool::Flac;
+        let candidates = config
+            .tools
+            .get(&tool)
+            .cloned()
+            .unwrap_or_else(|| vec![tool.to_string()]);
+
+        match CompressionTool::resolve(&candidates) {
+            Some(binary) => {
+                compression_options.binaries.insert(tool, binary);
+            }
+            None => {
+                error!("You'll need to have FLAC available on your PATH if you want to archive audio CDs. Please run this application from Docker or install FLAC manually and try again.");
+                return Ok(ExitCode::from(2));
+            }
+        }
+    }
+
+    if cli.best_of {
+        for tool in (available | RomFormat::FILE_FORMATS).alternative_tools() {
+            if compression_options.binaries.contains_key(&tool)
+                || compression_options.internal.contains(&tool)
+            {
+                continue;
+            }
+
+            let candidates = config
+                .tools
+                .get(&tool)
+                .cloned()
+                .unwrap_or_else(|| vec![tool.to_string()]);
+
+            match CompressionTool::resolve(&candidates) {
+                Some(binary) => {
+                    compression_options.binaries.insert(tool, binary);
+                }
+                None if tool == CompressionTool::MaxCSO
+                    && cli.cso_backend.is_none()
+                    && !cli.strict
+                    && compression_options.internal_cso_settings().is_ok() =>
+                {
+                    compression_options.internal.insert(tool);
+                }
+                None if cli.strict => {
+                    error!(
+                        "{} isn't available on your PATH, but is needed for --best-of.",
+                        tool.to_string().to_uppercase()
+                    );
+                    return Ok(ExitCode::from(2));
+                }
+                None => warn!(
+                    "{} isn't available on your PATH, --best-of won't try it.",
+                    tool.to_string().to_uppercase()
+                ),
+            }
+        }
+    }
+
+    let mut min_versions = config.min_versions.iter().collect::<Vec<_>>();
+
+    min_versions.sort_by_key(|(tool, _)| tool.to_string());
+
+    for (tool, min_version) in min_versions {
+        let Some(binary) = compression_options.binaries.get(tool) else {
+            continue;
+        };
+        let name = tool.to_string().to_uppercase();
+        // the versions were checked when loading the config file
+        let min = parse_version(min_version).unwrap();
+
+        let problem = match tool.version(binary) {
+            Some(version) if version >= min => continue,
+            Some(version) => format!(
+                "{} is version {}, but at least {} is needed",
+                name,
+                version
+                    .iter()
+                    .map(|n| n.to_string())
+                    .collect::<Vec<_>>()
+                    .join("."),
+                min_version
+            ),
+            None => format!(
+                "The version of {} couldn't be determined, but at least {} is needed",
+                name, min_version
+            ),
+        };
+
+        if cli.strict {
+            error!("{}.", problem);
+            return Ok(ExitCode::from(2));
+        }
+
+        warn!("{}, the conversions might fail.", problem);
+    }
+
+    if let Some(location) = locations.iter().find(|l| {
+        job_file.is_none()
+            && l.is_file()
+            && !(cli.extract_archives && is_archive(l))
+            && !guess_file(l)
+                .map(|f| f.intersects(available))
+                .unwrap_or(false)
+    }) {
+        let missing = missing_tracks(location);
+
+        if missing.is_empty() {
+            error!(
+                "The input file {} isn't recognized as proper file format for a {} rom",
+                location.display(),
+                cli.format
+                    .iter()
+                    .map(|f| format!("{:?}", f))
+                    .collect::<Vec<_>>()
+                    .join(" or ")
+            );
+        } else {
+            error!(
+                "The cue sheet {} references missing track files: {}",
+                location.display(),
+                file_names(&missing)
+            );
+        }
+        return Ok(ExitCode::from(1));
+    }
+
+    if cfg!(not(unix)) && (cli.output_mode.is_some() || cli.output_owner.is_some()) {
+        warn!("--output-mode and --output-owner are only supported on Unix and will be ignored.");
+    }
+
+    let s3 = match cli.s3.clone() {
+        Some(_) if cfg!(not(feature = "s3")) => {
+            error!("--s3 requires romcomp to be built with the s3 feature.");
+            return Ok(ExitCode::from(1));
+        }
+        Some(target) => match S3Client::from_env(target) {
+            Ok(client) => Some(client),
+            Err(e) => {
+                error!("Error setting up the S3 upload: {}", e);
+                return Ok(ExitCode::from(1));
+            }
+        },
+        None => None,
+    };
+
+    let state = match cli.state.as_ref().map(|s| StateFile::open(s)).transpose() {
+        Ok(state) => state,
+        Err(e) => {
+            error!("Error opening the state file: {}", e);
+            return Ok(ExitCode::from(1));
+        }
+    };
+
+    let tui = cli.tui && stderr().is_terminal();
+
+    if cli.tui && !tui {
+        warn!("--tui requires a terminal, falling back to the line-based output.");
+    }
+
+    let progress = cli.progress && stderr().is_terminal();
+
+    if cli.progress && !progress {
+        warn!("--progress requires a terminal, falling back to the line-based output.");
+    }
+
+    let converter = Converter::new(&locations, tmp, cli.threads, abort_events)
+        .stop(ctrl_c_events.clone())
+        .remove_after_compression(cli.remove_after_compression)
+        .flatten(cli.flatten)
+        .sanity_checks(!cli.no_sanity_checks)
+        .update(cli.update)
+        .overwrite(cli.overwrite)
+        .preserve_xattrs(cli.preserve_xattrs)
+        .output_mode(cli.output_mode)
+        .output_owner(cli.output_owner)
+        .trim(cli.trim)
+        .best_of(cli.best_of)
+        .audio_flac(cli.audio_flac)
+        .zstd_seekable(cli.zstd_seekable)
+        .explain(cli.explain)
+        .dry_run(cli.dry_run)
+        .tui(tui)
+        .progress(progress)
+        .http_status(cli.http_status)
+        .s3(s3)
+        .s3_remove_local(cli.s3_remove_local)
+        .io_concurrency(cli.io_concurrency.map(|c| c.get()))
+        .io_budget(cli.io_budget)
+        .reflink(cli.reflink)
+        .ext_case(cli.ext_case)
+        .output_root(output_root)
+        .size_range(cli.min_size, cli.max_size)
+        .min_savings(cli.min_savings)
+        .size_mode(cli.size_mode)
+        .recompress(cli.recompress)
+        .fail_fast(cli.fail_fast)
+        .retries(cli.retries)
+        .status_file(cli.status_file.clone())
+        .report(cli.report.clone())
+        .state(state)
+        .hash(cli.hash)
+        .detect_duplicate_outputs(cli.detect_dupe_outputs.then_some(cli.dupe_action))
+        .compression_options(compression_options);
+
+    if !cli.print_commands && cli.emit_jobs.is_none() {
+        info!(
+            "Start ROM compression with {} simultaneous processes",
+            cli.threads
+        );
+    }
+
+    let files: Box<dyn Iterator<Item = PathBuf>> = if job_file.is_some() {
+        Box::new(empty())
+    } else {
+        walk(&locations, junk, cli.follow_symlinks)
+    };
+
+    // files can be reached both through the walk and through playlists,
+    // but must only be converted once
+    let mut dispatched: HashSet<PathBuf> = HashSet::new();
+    let mut jobs: Vec<(PathBuf, RomFormat)> = vec![];
+    let mut playlists: Vec<(PathBuf, Vec<(PathBuf, PathBuf)>)> = vec![];
+    let mut archives: Vec<PathBuf> = vec![];
+
+    for file in files {
+        if !is_selected(&file, &locations, &include, &exclude) {
+            if cli.explain {
+                info!(
+                    "Explain {}: Skip, not selected by --include or --exclude",
+                    file.display()
+                );
+            }
+            continue;
+        }
+
+        // the rom inside an archive is only known once the archive was extracted, see below
+        if cli.extract_archives && is_archive(&file) {
+            if dispatched.insert(file.clone()) {
+                archives.push(file);
+            }
+            continue;
+        }
+
+        let guess = match guess_file(&file) {
+            Some(guess) if guess.intersects(available) => guess,
+            Some(guess) if guess.contains(RomFormat::Arcade) => {
+                if cli.explain {
+                    info!(
+                        "Explain {}: Skip, zip files are only recompressed as romsets with the arcade format",
+                        file.display()
+                    );
+                }
+                continue;
+            }
+            Some(guess) => {
+                if cli.explain {
+                    info!(
+                        "Explain {}: format {:?}: Skip, no tool for this format is available",
+                        file.display(),
+                        guess
+                    );
+                }
+                continue;
+            }
+            None => {
+                // unlike other unrecognized files, a broken gdi file, a cue sheet with missing tracks or a cdi image
+                // is clearly meant to be converted
+                let level = if cli.explain {
+                    Level::Info
+                } else {
+                    Level::Debug
+                };
+                let extension = file.extension().and_then(|e| e.to_str()).unwrap_or("");
+
+                let missing = missing_tracks(&file);
+
+                if extension.eq_ignore_ascii_case("gdi") {
+                    if let Err(e) = parse_gdi(&file) {
+                        log!(level, "Skipping {}: {}", file.display(), e);
+                    }
+                } else if !missing.is_empty() {
+                    log!(
+                        level,
+                        "Skipping {}: The cue sheet references missing track files: {}",
+                        file.display(),
+                        file_names(&missing)
+                    );
+                } else if extension.eq_ignore_ascii_case("cdi") {
+                    log!(
+                        level,
+                        "Skipping {}: chdman can't read DiscJuggler images, convert it to gdi first",
+                        file.display()
+                    );
+                }
+                continue;
+            }
+        };
+
+        let fmt = match fmt.ok_or(RomFormat::empty()).or_else(|_| {
+            let root = root_of(&file, &locations).unwrap();
+            detect_format(&file, &file, guess, root, detectable, cli.format_by_dir)
+        }) {
+            Ok(fmt) => fmt,
+            // unlike a file no tool is available for, a disc fitting several consoles is clearly meant to be converted
+            Err(candidates) if candidates.bits().count_ones() > 1 => {
+                warn!(
+                    "Skipping {}: It could be a {} rom, choose its format or use --format-by-dir",
+                    file.display(),
+                    flag_names(candidates).join(" or ")
+                );
+                continue;
+            }
+            Err(_) => {
+                if cli.explain {
+                    info!(
+                        "Explain {}: format {:?}: Skip, the format couldn't be determined",
+                        file.display(),
+                        guess
+                    );
+                } else {
+                    debug!(
+                        "Skipping {}: The format couldn't be determined",
+                        file.display()
+                    );
+                }
+                continue;
+            }
+        };
+
+        if !ctrl_c_events.is_empty() {
+            break;
+        }
+
+        // game.cue and game.cue.txt would be converted into the same output, the .cue file wins
+        let is_twin = |file: &PathBuf| match converted_twin(file) {
+            Some(twin) => {
+                info!(
+                    "Skipping {}: {} describes the same disc and gets converted instead",
+                    file.display(),
+                    twin.display()
+                );
+                true
+            }
+            None => false,
+        };
+
+        // BIOS files living next to the games must neither be converted nor removed
+        let is_bios = |file: &PathBuf| match bios.detect(file) {
+            Some(found) => {
+                if cli.explain {
+                    info!(
+                        "Explain {}: Skip, {} looks like a BIOS or firmware file",
+                        file.display(),
+                        found.display()
+                    );
+                } else {
+                    // a game which was mistaken for a BIOS must never be skipped silently
+                    info!(
+                        "Skipping {}: {} looks like a BIOS or firmware file",
+                        file.display(),
+                        found.display()
+                    );
+                }
+                true
+            }
+            None => false,
+        };
+
+        if guess.contains(RomFormat::M3U) {
+            let mut outputs = vec![];
+
+            for entry in read_m3u(&file).unwrap_or_default() {
+                if let Some(entry_guess) = guess_file(&entry) {
+                    let entry_fmt = (entry_guess & RomFormat::FILE_FORMATS) | fmt;
+
+                    if let Some(output) = converter.get_output_file_name(&entry, entry_fmt) {
+                        outputs.push((entry.clone(), output));
+                    }
+
+                    if !is_twin(&entry) && !is_bios(&entry) && dispatched.insert(entry.clone()) {
+                        jobs.push((entry, entry_fmt));
+                    }
+                }
+            }
+
+            playlists.push((file, outputs));
+        } else if !is_twin(&file) && !is_bios(&file) && dispatched.insert(file.clone()) {
+            jobs.push((file, (guess & RomFormat::FILE_FORMATS) | fmt));
+        }
+    }
+
+    // additional outputs of the same inputs, as long as they don't end up in the same output file
+    if !also.is_empty() {
+        jobs = jobs
+            .into_iter()
+            .flat_map(|(file, fmt)| {
+                let guess = guess_file(&file).unwrap_or(RomFormat::empty());
+                let mut outputs = vec![converter.get_output_file_name(&file, fmt)];
+                let mut formats = vec![(file.clone(), fmt)];
+
+                for a in also
+                    .iter()
+                    .filter(|a| guess.contains(**a) && available.contains(**a))
+                {
+                    let additional = (fmt & RomFormat::FILE_FORMATS) | *a;
+                    let output = converter.get_output_file_name(&file, additional);
+
+                    if !outputs.contains(&output) {
+                        outputs.push(output);
+                        formats.push((file.clone(), additional));
+                    }
+                }
+
+                formats
+            })
+            .collect();
+    }
+
+    if let Some(job_file) = job_file.as_ref() {
+        for (i, job) in job_file.jobs.iter().enumerate() {
+            if cli.shard.is_some_and(|s| !s.contains(i)) {
+                continue;
+            }
+
+            let format = job.rom_format()?;
+
+            if !available.contains(format & RomFormat::CONSOLES) {
+                info!(
+                    "Skipping {}: No tool for this format is available",
+                    job.input.display()
+                );
+                continue;
+            }
+
+            jobs.push((job.input.clone(), format));
+        }
+    }
+
+    if let Some(path) = cli.emit_jobs.as_ref() {
+        let job_file = JobFile {
+            roots: locations.clone(),
+            jobs: jobs
+                .iter()
+                .map(|(file, fmt)| converter.job(file, *fmt))
+                .collect(),
+        };
+
+        return match job_file.write(path) {
+            Ok(()) => {
+                info!("Written {} jobs to {}", job_file.jobs.len(), path.display());
+                Ok(ExitCode::from(0))
+            }
+            Err(e) => {
+                error!("Error writing the job file {}: {}", path.display(), e);
+                Ok(ExitCode::from(1))
+            }
+        };
+    }
+
+    if cli.print_commands {
+        for (file, fmt) in jobs.iter() {
+            match converter.command_line(file, *fmt) {
+                Ok(command) => println!("{}", command),
+                Err(reason) => println!("# {}: {}", file.display(), reason),
+            }
+        }
+
+        return Ok(ExitCode::from(0));
+    }
+
+    if let Some(samples) = cli.estimate {
+        estimate(&converter, &jobs, samples.get(), &ctrl_c_events);
+        return Ok(ExitCode::from(0));
+    }
+
+    // the rom inside an archive as it gets converted, the skipped archives are counted by the converter
+    let archived_job = |archive: &PathBuf| -> Option<(PathBuf, RomFormat)> {
+        let skip = |reason: &str| {
+            let level = if cli.explain {
+                Level::Info
+            } else {
+                Level::Debug
+            };
+
+            log!(level, "Skipping {}: {}", archive.display(), reason);
+            converter.skip_archive(archive, reason);
+        };
+
+        let dir = match converter.extract_archive(archive) {
+            Ok(Some(dir)) => dir,
+            Ok(None) => return None,
+            Err(e) => {
+                warn!("Skipping {}: {}", archive.display(), e);
+                converter.skip_archive(archive, &e);
+                return None;
+            }
+        };
+
+        let rom = match archived_rom(&dir) {
+            Ok(rom) => rom,
+            Err(e) => {
+                skip(&e);
+                return None;
+            }
+        };
+
+        let Some(guess) = guess_file(&rom).filter(|g| g.intersects(available)) else {
+            skip(&format!(
+                "No tool for the format of {} is available",
+                rom.file_name().unwrap().to_str().unwrap()
+            ));
+            return None;
+        };
+
+        // the rom is converted as if it was next to the archive
+        let located = archive.with_file_name(rom.file_name().unwrap());
+        let fmt = match fmt.ok_or(RomFormat::empty()).or_else(|_| {
+            let root = root_of(archive, &locations).unwrap();
+            detect_format(&located, &rom, guess, root, detectable, cli.format_by_dir)
+        }) {
+            Ok(fmt) => fmt,
+            Err(candidates) if candidates.bits().count_ones() > 1 => {
+                let reason = format!(
+                    "It could be a {} rom, choose its format or use --format-by-dir",
+                    flag_names(candidates).join(" or ")
+                );
+
+                warn!("Skipping {}: {}", archive.display(), reason);
+                converter.skip_archive(archive, &reason);
+                return None;
+            }
+            Err(_) => {
+                skip("The format couldn't be determined");
+                return None;
+            }
+        };
+        let fmt = (guess & RomFormat::FILE_FORMATS) | fmt;
+
+        // e.g. a zip file created by romcomp itself, which would be replaced by its own output.
+        // only the casing of the extension may differ, see --ext-case
+        if converter.get_output_file_name(&rom, fmt).is_some_and(|o| {
+            o.with_extension("") == archive.with_extension("")
+                && o.extension()
+                    .zip(archive.extension())
+                    .is_some_and(|(o, a)| o.eq_ignore_ascii_case(a))
+        }) {
+            skip("The archive is the output of its own rom");
+            return None;
+        }
+
+        if let Some(found) = bios.detect(&rom) {
+            let reason = format!("{} looks like a BIOS or firmware file", found.display());
+
+            info!("Skipping {}: {}", archive.display(), reason);
+            converter.skip_archive(archive, &reason);
+            return None;
+        }
+
+        Some((rom, fmt))
+    };
+
+    converter.begin(jobs.len() + archives.len());
+
+    for (file, fmt) in jobs.iter() {
+        if !ctrl_c_events.is_empty() || converter.stopped_by_failure() {
+            break;
+        }
+
+        converter.convert(file, *fmt);
+    }
+
+    // archives are only extracted once their conversion is about to start,
+    // so the temporary directory doesn't need to hold all of them at once
+    for archive in archives.iter() {
+        if !ctrl_c_events.is_empty() || converter.stopped_by_failure() {
+            break;
+        }
+
+        if let Some((rom, fmt)) = archived_job(archive) {
+            converter.convert(&rom, fmt);
+        }
+    }
+
+    if converter.stopped_by_failure() {
+        warn!("Stopped starting new conversions after a conversion failed, see --fail-fast");
+    }
+
+    converter.finish();
+
+    if cli.report_by_dir {
+        print_report_by_dir(&converter.records());
+    }
+
+    if let Some(path) = cli.index.as_ref() {
+        match update_index(path, &converter.records()) {
+            Ok(added) => info!("Added {} outputs to the index {}", added, path.display()),
+            Err(e) => warn!("Error updating the index {}: {:?}", path.display(), e),
+        }
+    }
+
+    if let Some(path) = cli.generate_playlist.as_ref() {
+        match cli.playlist_format.write(path, &converter.records()) {
+            Ok(()) => info!("Written playlist {}", path.display()),
+            Err(e) => warn!("Error writing playlist {}: {:?}", path.display(), e),
+        }
+    }
+
+    if cli.rewrite_playlists {
+        for (playlist, outputs) in playlists.iter() {
+            match rewrite_m3u(playlist, outputs) {
+                Ok(true) => info!("Updated playlist {}", playlist.display()),
+                Ok(false) => (),
+                Err(e) => warn!("Error updating playlist {}: {:?}", playlist.display(), e),
+            }
+        }
+    }
+
+    // the rest of the batch was converted anyway, but scripts need to notice the failed files
+    if converter.had_failures() {
+        return Ok(ExitCode::from(3));
+    }
+
+    Ok(ExitCode::from(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::write;
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_cycles_end_and_linked_files_are_found_once() {
+        use std::os::unix::fs::symlink;
+
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+        let roms = root.join("roms");
+        create_dir_all(&roms).unwrap();
+        write(roms.join("game.iso"), b"game").unwrap();
+        // a link back to its own parent, and a second way to the same rom
+        symlink(&roms, roms.join("loop")).unwrap();
+        symlink(roms.join("game.iso"), root.join("link.iso")).unwrap();
+
+        let followed = walk(&[root.to_path_buf()], GlobSet::empty(), true).collect::<Vec<_>>();
+        assert_eq!(followed.len(), 1, "{:?}", followed);
+        assert_eq!(
+            canonicalize(&followed[0]).unwrap(),
+            canonicalize(roms.join("game.iso")).unwrap()
+        );
+
+        // without following them, links are passed by
+        assert_eq!(
+            walk(&[root.to_path_buf()], GlobSet::empty(), false).collect::<Vec<_>>(),
+            [roms.join("game.iso")]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn linked_inputs_are_counted_by_their_target() {
+        use std::os::unix::fs::symlink;
+
+        let dir = tempfile::tempdir().unwrap();
+        let rom = dir.path().join("game.iso");
+        write(&rom, vec![0; 4096]).unwrap();
+        symlink(&rom, dir.path().join("link.iso")).unwrap();
+
+        let link = dir.path().join("link.iso");
+        assert_eq!(SizeMode::Logical.of(&link).unwrap(), 4096);
+        assert_eq!(
+            SizeMode::OnDisk.of(&link).unwrap(),
+            SizeMode::OnDisk.of(&rom).unwrap()
+        );
+        assert!(SizeMode::OnDisk.of(&link).unwrap() > 0);
+    }
+
+    #[test]
+    fn zero_threads_are_rejected() {
+        assert_eq!(
+            parse_threads("0"),
+            Err("at least 1 thread is needed".to_string())
+        );
+        assert!(parse_threads("0%").is_err());
+        assert!(parse_threads("0.0").is_err());
+        assert!(Cli::try_parse_from(["romcomp", "--threads", "1", ".", "auto"]).is_ok());
+        assert!(Cli::try_parse_from(["romcomp", "--threads", "0", ".", "auto"]).is_err());
+    }
+
+    #[test]
+    fn fractions_of_the_cores_are_at_least_one_thread() {
+        assert_eq!(parse_threads("3"), Ok(3));
+        assert_eq!(parse_threads("0.0001"), Ok(1));
+        assert_eq!(parse_threads("1%"), Ok((available_cores() / 100).max(1)));
+        assert_eq!(parse_threads("100%"), Ok(available_cores()));
+    }
 }
@@ -0,0 +1,144 @@
+use clap::ValueEnum;
+use crossbeam_channel::Receiver;
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+/// the implementation used to convert n64 roms into the big endian z64 format
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum N64Backend {
+    /// convert with rom64
+    Rom64,
+    /// convert with romcomp's own byteswapping, which doesn't need any external tools
+    Internal,
+}
+
+/// the byte order of an n64 rom, detected by the first word of the rom header
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ByteOrder {
+    /// z64, big endian
+    BigEndian,
+    /// v64, every 16 bit word is byteswapped
+    ByteSwapped,
+    /// n64, every 32 bit word is little endian
+    LittleEndian,
+}
+
+impl ByteOrder {
+    pub fn detect(magic: &[u8; 4]) -> Option<Self> {
+        match magic {
+            [0x80, 0x37, 0x12, 0x40] => Some(ByteOrder::BigEndian),
+            [0x37, 0x80, 0x40, 0x12] => Some(ByteOrder::ByteSwapped),
+            [0x40, 0x12, 0x37, 0x80] => Some(ByteOrder::LittleEndian),
+            _ => None,
+        }
+    }
+
+    pub fn to_big_endian(self, chunk: &mut [u8]) {
+        match self {
+            ByteOrder::BigEndian => (),
+            ByteOrder::ByteSwapped => chunk.chunks_exact_mut(2).for_each(|w| w.swap(0, 1)),
+            ByteOrder::LittleEndian => chunk.chunks_exact_mut(4).for_each(|w| w.reverse()),
+        }
+    }
+}
+
+/// the header, the boot code and the first megabyte of the rom are covered by the checks of the CIC,
+/// so a rom is never trimmed below this size
+const N64_MIN_TRIMMED_SIZE: u64 = 0x101000;
+
+/// returns the length of a z64 rom without the trailing padding (0x00 or 0xFF bytes) added to fill up the cartridge,
+/// or None if the rom isn't padded or isn't a valid z64 rom
+pub fn trimmed_length(path: &Path) -> io::Result<Option<u64>> {
+    let mut fh = File::open(path)?;
+    let size = fh.metadata()?.len();
+    let mut magic = [0; 4];
+    fh.read_exact(&mut magic)?;
+
+    if ByteOrder::detect(&magic) != Some(ByteOrder::BigEndian) || size <= N64_MIN_TRIMMED_SIZE {
+        return Ok(None);
+    }
+
+    let mut padding = [0; 1];
+    fh.seek(SeekFrom::End(-1))?;
+    fh.read_exact(&mut padding)?;
+
+    if padding[0] != 0x00 && padding[0] != 0xFF {
+        return Ok(None);
+    }
+
+    // search backwards for the last byte which isn't padding
+    let mut end = size;
+    let mut chunk = vec![0; 1024 * 1024];
+
+    while end > N64_MIN_TRIMMED_SIZE {
+        let start = end
+            .saturating_sub(chunk.len() as u64)
+            .max(N64_MIN_TRIMMED_SIZE);
+        let chunk = &mut chunk[..(end - start) as usize];
+        fh.seek(SeekFrom::Start(start))?;
+        fh.read_exact(chunk)?;
+
+        match chunk.iter().rposition(|b| *b != padding[0]) {
+            Some(p) => {
+                end = start + p as u64 + 1;
+                break;
+            }
+            None => end = start,
+        }
+    }
+
+    // keep whole words
+    let end = end.next_multiple_of(4).max(N64_MIN_TRIMMED_SIZE);
+
+    Ok((end < size).then_some(end))
+}
+
+/// converts an n64 rom in any byte order into a z64 rom.
+/// returns whether the conversion got interrupted
+pub fn convert_to_z64(input: &Path, output: &Path, interrupt: &Receiver<()>) -> io::Result<bool> {
+    let mut reader = BufReader::new(File::open(input)?);
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+
+    let order = ByteOrder::detect(&magic).ok_or_else(|| {
+        io::Error::new(
+            ErrorKind::InvalidData,
+            format!("{} isn't a valid n64 rom", input.display()),
+        )
+    })?;
+
+    let mut writer = BufWriter::new(File::create(output)?);
+    order.to_big_endian(&mut magic);
+    writer.write_all(&magic)?;
+
+    // roms are always a multiple of 4 bytes, so the chunks never split a word
+    let mut chunk = vec![0; 1024 * 1024];
+
+    loop {
+        if !interrupt.is_empty() {
+            return Ok(true);
+        }
+
+        let mut read = 0;
+        while read < chunk.len() {
+            match reader.read(&mut chunk[read..])? {
+                0 => break,
+                n => read += n,
+            }
+        }
+
+        if read == 0 {
+            break;
+        }
+
+        order.to_big_endian(&mut chunk[..read]);
+        writer.write_all(&chunk[..read])?;
+    }
+
+    writer.flush()?;
+
+    Ok(false)
+}
@@ -0,0 +1,83 @@
+use std::{fs::read_to_string, io, path::Path};
+
+/// the owner applied to output files, a missing part is left unchanged
+#[derive(Clone, Copy, Debug)]
+pub struct Owner {
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+}
+
+/// parses an octal file mode like 644 or 0640
+pub fn parse_mode(value: &str) -> Result<u32, String> {
+    u32::from_str_radix(value, 8)
+        .ok()
+        .filter(|m| *m <= 0o7777)
+        .ok_or_else(|| format!("{} isn't a valid octal file mode", value))
+}
+
+/// looks up the id of a user or group in /etc/passwd or /etc/group
+fn lookup_id(database: &str, name: &str) -> Option<u32> {
+    read_to_string(database).ok()?.lines().find_map(|line| {
+        let mut fields = line.split(':');
+        (fields.next() == Some(name))
+            .then(|| fields.nth(1).and_then(|id| id.parse().ok()))
+            .flatten()
+    })
+}
+
+/// parses user:group, user or :group, each part being either a name or a numeric id
+pub fn parse_owner(value: &str) -> Result<Owner, String> {
+    let (user, group) = value.split_once(':').unwrap_or((value, ""));
+
+    let resolve = |name: &str, database: &str| -> Result<Option<u32>, String> {
+        if name.is_empty() {
+            Ok(None)
+        } else {
+            name.parse()
+                .ok()
+                .or_else(|| lookup_id(database, name))
+                .map(Some)
+                .ok_or_else(|| format!("{} doesn't exist", name))
+        }
+    };
+
+    let owner = Owner {
+        uid: resolve(user, "/etc/passwd")?,
+        gid: resolve(group, "/etc/group")?,
+    };
+
+    if owner.uid.is_none() && owner.gid.is_none() {
+        return Err("either a user or a group is required".to_string());
+    }
+
+    Ok(owner)
+}
+
+/// applies the file mode and owner to an output file
+#[cfg(unix)]
+pub fn apply_permissions(path: &Path, mode: Option<u32>, owner: Option<Owner>) -> io::Result<()> {
+    use std::{
+        fs::{set_permissions, Permissions},
+        os::unix::fs::{chown, PermissionsExt},
+    };
+
+    if let Some(mode) = mode {
+        set_permissions(path, Permissions::from_mode(mode))?;
+    }
+
+    if let Some(owner) = owner {
+        chown(path, owner.uid, owner.gid)?;
+    }
+
+    Ok(())
+}
+
+/// file modes and owners only exist on Unix
+#[cfg(not(unix))]
+pub fn apply_permissions(
+    _path: &Path,
+    _mode: Option<u32>,
+    _owner: Option<Owner>,
+) -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,149 @@
+use crate::{convert::ConversionRecord, rom_format::RomFormat};
+use clap::ValueEnum;
+use serde_json::json;
+use std::{
+    fs::{read_to_string, write},
+    io,
+    path::{Path, PathBuf},
+};
+
+/// the kinds of playlists which can be generated after a run
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum PlaylistFormat {
+    /// RetroArch .lpl playlist
+    RetroArch,
+}
+
+impl PlaylistFormat {
+    pub fn write(&self, path: &Path, records: &[ConversionRecord]) -> io::Result<()> {
+        match self {
+            PlaylistFormat::RetroArch => write_retroarch_playlist(path, records),
+        }
+    }
+}
+
+/// the name of RetroArch's database for a rom format
+fn retroarch_system(format: RomFormat) -> Option<&'static str> {
+    if format.contains(RomFormat::PlayStationX) {
+        Some("Sony - PlayStation")
+    } else if format.contains(RomFormat::PlayStation2) {
+        Some("Sony - PlayStation 2")
+    } else if format.contains(RomFormat::PlayStation3) {
+        Some("Sony - PlayStation 3")
+    } else if format.contains(RomFormat::PlayStationPortable) {
+        Some("Sony - PlayStation Portable")
+    } else if format.contains(RomFormat::NDD) {
+        Some("Nintendo - Nintendo 64DD")
+    } else if format.contains(RomFormat::Nintendo64) {
+        Some("Nintendo - Nintendo 64")
+    } else if format.contains(RomFormat::NintendoDS) {
+        Some("Nintendo - Nintendo DS")
+    } else if format.contains(RomFormat::NintendoWii) {
+        Some("Nintendo - Wii")
+    } else if format.contains(RomFormat::NintendoGameCube) {
+        Some("Nintendo - GameCube")
+    } else if format.contains(RomFormat::Dreamcast) {
+        Some("Sega - Dreamcast")
+    } else {
+        None
+    }
+}
+
+fn write_retroarch_playlist(path: &Path, records: &[ConversionRecord]) -> io::Result<()> {
+    let items = records
+        .iter()
+        .filter(|r| r.output.is_file())
+        .map(|r| {
+            json!({
+                "path": r.output.to_str().unwrap(),
+                "label": r.output.file_stem().unwrap().to_str().unwrap(),
+                "core_path": "DETECT",
+                "core_name": "DETECT",
+                "crc32": "DETECT",
+                "db_name": retroarch_system(r.format)
+                    .map(|s| format!("{}.lpl", s))
+                    .unwrap_or_default(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let playlist = json!({
+        "version": "1.5",
+        "default_core_path": "",
+        "default_core_name": "",
+        "label_display_mode": 0,
+        "right_thumbnail_mode": 0,
+        "left_thumbnail_mode": 0,
+        "sort_mode": 0,
+        "items": items,
+    });
+
+    write(path, serde_json::to_string_pretty(&playlist)?)
+}
+
+/// reads all entries of a m3u playlist.
+/// comments and empty lines are ignored, relative entries are resolved against the playlist location.
+pub fn read_m3u(path: &Path) -> Option<Vec<PathBuf>> {
+    let content = read_to_string(path).ok()?;
+    let parent = path.parent()?;
+
+    Some(
+        m3u_entries(&content)
+            .map(|e| {
+                let entry = parent.join(e);
+                entry.canonicalize().unwrap_or(entry)
+            })
+            .collect(),
+    )
+}
+
+/// replaces all entries of a m3u playlist which got compressed with their output files.
+/// outputs contains the (absolute) entries of the playlist together with their output files,
+/// entries whose output file doesn't exist are left untouched.
+pub fn rewrite_m3u(path: &Path, outputs: &[(PathBuf, PathBuf)]) -> io::Result<bool> {
+    let content = read_to_string(path)?;
+    let parent = path.parent().unwrap();
+    let mut changed = false;
+
+    let lines = content
+        .lines()
+        .map(|line| {
+            let entry = line.trim();
+
+            if entry.is_empty() || entry.starts_with('#') {
+                return line.to_string();
+            }
+
+            let absolute = parent.join(entry);
+            let absolute = absolute.canonicalize().unwrap_or(absolute);
+
+            match outputs
+                .iter()
+                .find(|(input, output)| *input == absolute && output.is_file())
+            {
+                Some((_, output)) => {
+                    changed = true;
+                    Path::new(entry)
+                        .with_file_name(output.file_name().unwrap())
+                        .to_str()
+                        .unwrap()
+                        .to_string()
+                }
+                None => line.to_string(),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if changed {
+        write(path, lines.join("\n") + "\n")?;
+    }
+
+    Ok(changed)
+}
+
+fn m3u_entries(content: &str) -> impl Iterator<Item = &str> {
+    content
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+}
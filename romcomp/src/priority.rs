@@ -0,0 +1,55 @@
+use clap::ValueEnum;
+use std::io;
+
+/// the I/O scheduling class of romcomp and the tools it runs, see --ionice
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum IoClass {
+    /// the lowest priority of the default class, still gets its share of a busy disk
+    BestEffort,
+    /// only gets disk time once nothing else needs it
+    Idle,
+}
+
+/// lowers the CPU priority of romcomp, which every thread and tool started afterwards inherits.
+/// values from -20 (highest) to 19 (lowest), raising the priority requires root
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn set_nice(nice: i32) -> io::Result<()> {
+    // SAFETY: setpriority only changes the scheduling of the calling process.
+    // the type of the first parameter differs between the platforms
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS as _, 0, nice) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn set_nice(_nice: i32) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// changes the I/O scheduling class of romcomp, which every thread and tool started afterwards inherits
+#[cfg(target_os = "linux")]
+pub fn set_io_class(class: IoClass) -> io::Result<()> {
+    // see linux/ioprio.h, the class is stored above the level within the class
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+
+    let priority = match class {
+        IoClass::BestEffort => (2 << IOPRIO_CLASS_SHIFT) | 7,
+        IoClass::Idle => 3 << IOPRIO_CLASS_SHIFT,
+    };
+
+    // SAFETY: ioprio_set only changes the scheduling of the calling thread
+    if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, priority) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+/// I/O scheduling classes only exist on Linux
+#[cfg(not(target_os = "linux"))]
+pub fn set_io_class(_class: IoClass) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
@@ -0,0 +1,496 @@
+use crate::{
+    n64::ByteOrder,
+    playlist::read_m3u,
+    search::{
+        guess_file, iso_looks_truncated, read_at, sector_mode, wbfs_parts, GAMECUBE_MAGIC,
+        ISO_PVD_OFFSET, WII_MAGIC,
+    },
+};
+use cue::{cd::CD, track::TrackMode};
+use humansize::{format_size, DECIMAL};
+use serde::{Deserialize, Serialize};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// everything romcomp can tell about a single file, see the probe command
+#[derive(Serialize)]
+pub struct Probe {
+    file: PathBuf,
+    size: u64,
+    /// the file format and console flags romcomp detects, empty if the file isn't recognized
+    detected: Vec<String>,
+    /// the first 16 bytes of the file
+    magic: String,
+    tracks: Option<Vec<ProbeTrack>>,
+    iso: Option<ProbeIso>,
+    wii: Option<ProbeWii>,
+    n64: Option<ProbeN64>,
+    nds: Option<ProbeNds>,
+    chd: Option<ProbeChd>,
+    rvz: Option<ProbeRvz>,
+    wbfs_parts: Option<Vec<PathBuf>>,
+    playlist: Option<Vec<PathBuf>>,
+}
+
+/// a track of a cue sheet
+#[derive(Serialize)]
+struct ProbeTrack {
+    number: usize,
+    file: String,
+    mode: &'static str,
+    /// the start within the file as mm:ss:ff
+    start: String,
+    /// the size of the file, None if it doesn't exist
+    file_size: Option<u64>,
+    /// the mode of the first sector of the file, if it starts with a raw data sector
+    sector_mode: Option<u8>,
+}
+
+/// the primary volume descriptor of an ISO 9660 image
+#[derive(Serialize)]
+struct ProbeIso {
+    system_id: String,
+    volume_id: String,
+    volume_blocks: u32,
+    block_size: u16,
+    looks_truncated: bool,
+}
+
+/// the disc header of Wii and GameCube images
+#[derive(Serialize)]
+struct ProbeWii {
+    console: &'static str,
+    game_id: String,
+    title: String,
+}
+
+/// the header of an n64 rom
+#[derive(Serialize)]
+struct ProbeN64 {
+    byte_order: String,
+    title: String,
+    game_code: String,
+}
+
+/// the header of an nds rom
+#[derive(Serialize)]
+struct ProbeNds {
+    title: String,
+    game_code: String,
+    header_size: u32,
+    /// the size of the rom without the padding up to the cartridge size
+    used_rom_size: u32,
+}
+
+/// the header of a chd file, only version 5 headers are read completely
+#[derive(Deserialize, Serialize)]
+pub struct ProbeChd {
+    version: u32,
+    /// the size of the uncompressed data
+    logical_size: Option<u64>,
+    hunk_size: Option<u32>,
+    /// the SHA-1 of the uncompressed data
+    data_sha1: Option<String>,
+    tracks: Vec<ProbeChdTrack>,
+}
+
+/// a track of a CD or GD-ROM chd, read from its metadata
+#[derive(Deserialize, Serialize)]
+struct ProbeChdTrack {
+    number: u32,
+    track_type: String,
+    frames: u64,
+}
+
+/// the header of an rvz file, including the header of the disc inside
+#[derive(Deserialize, Serialize)]
+pub struct ProbeRvz {
+    game_id: String,
+    title: String,
+    /// the size of the uncompressed disc image
+    iso_size: u64,
+    compression: String,
+    chunk_size: u32,
+}
+
+const CHD_MAGIC: &[u8; 8] = b"MComprHD";
+const CHD_V5_HEADER_SIZE: usize = 124;
+/// the most metadata entries read from a chd file, in case the chain is damaged and loops
+pub const CHD_MAX_METADATA_ENTRIES: usize = 256;
+/// the metadata tags of CD tracks (old and current format) and GD-ROM tracks
+const CHD_TRACK_TAGS: [&[u8; 4]; 3] = [b"CHTR", b"CHT2", b"CHGD"];
+const RVZ_MAGIC: &[u8; 4] = b"RVZ\x01";
+/// the disc header is stored after the first header (0x48 bytes) and the first 16 bytes of the second header
+const RVZ_DISC_HEADER_OFFSET: usize = 0x58;
+
+/// the printable part of a fixed size text field in a header
+fn text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes)
+        .trim_end_matches(['\0', ' '])
+        .to_string()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// reads the header and the track layout of a chd file
+pub fn probe_chd(path: &Path) -> Option<ProbeChd> {
+    let header = read_at(path, 0, 16).filter(|h| h[..8] == *CHD_MAGIC)?;
+    let version = u32::from_be_bytes(header[12..16].try_into().unwrap());
+
+    let Some(header) = read_at(path, 0, CHD_V5_HEADER_SIZE).filter(|_| version == 5) else {
+        return Some(ProbeChd {
+            version,
+            logical_size: None,
+            hunk_size: None,
+            data_sha1: None,
+            tracks: vec![],
+        });
+    };
+
+    let u64_at = |b: &[u8], o: usize| u64::from_be_bytes(b[o..o + 8].try_into().unwrap());
+    let mut tracks = vec![];
+    let mut offset = u64_at(&header, 48);
+
+    // the metadata is a linked list of entries, each starting with a 16 byte header
+    for _ in 0..CHD_MAX_METADATA_ENTRIES {
+        if offset == 0 {
+            break;
+        }
+
+        let Some(entry) = read_at(path, offset, 16) else {
+            break;
+        };
+        let length = u32::from_be_bytes([0, entry[5], entry[6], entry[7]]) as usize;
+
+        if CHD_TRACK_TAGS.iter().any(|t| entry[..4] == **t) {
+            let data = read_at(path, offset + 16, length).unwrap_or_default();
+            let text = String::from_utf8_lossy(&data);
+            let field = |name: &str| {
+                text.split_whitespace()
+                    .find_map(|f| f.strip_prefix(name)?.strip_prefix(':'))
+                    .map(|v| v.trim_end_matches('\0').to_string())
+            };
+
+            tracks.push(ProbeChdTrack {
+                number: field("TRACK").and_then(|v| v.parse().ok()).unwrap_or(0),
+                track_type: field("TYPE").unwrap_or_default(),
+                frames: field("FRAMES").and_then(|v| v.parse().ok()).unwrap_or(0),
+            });
+        }
+
+        offset = u64_at(&entry, 8);
+    }
+
+    Some(ProbeChd {
+        version,
+        logical_size: Some(u64_at(&header, 32)),
+        hunk_size: Some(u32::from_be_bytes(header[56..60].try_into().unwrap())),
+        data_sha1: Some(hex(&header[64..84])),
+        tracks,
+    })
+}
+
+/// reads the header of an rvz file and the disc header stored in it
+pub fn probe_rvz(path: &Path) -> Option<ProbeRvz> {
+    let header =
+        read_at(path, 0, RVZ_DISC_HEADER_OFFSET + 0x60).filter(|h| h[..4] == *RVZ_MAGIC)?;
+    let disc = &header[RVZ_DISC_HEADER_OFFSET..];
+
+    Some(ProbeRvz {
+        game_id: text(&disc[..6]),
+        title: text(&disc[0x20..0x60]),
+        iso_size: u64::from_be_bytes(header[36..44].try_into().unwrap()),
+        compression: match u32::from_be_bytes(header[0x4C..0x50].try_into().unwrap()) {
+            0 => "none",
+            1 => "purge",
+            2 => "bzip2",
+            3 => "lzma",
+            4 => "lzma2",
+            5 => "zstd",
+            _ => "unknown",
+        }
+        .to_string(),
+        chunk_size: u32::from_be_bytes(header[0x54..0x58].try_into().unwrap()),
+    })
+}
+
+fn track_mode(mode: TrackMode) -> &'static str {
+    match mode {
+        TrackMode::Audio => "AUDIO",
+        TrackMode::Mode1 => "MODE1/2048",
+        TrackMode::Mode1Raw => "MODE1/2352",
+        TrackMode::Mode2 => "MODE2/2336",
+        TrackMode::Mode2Form1 => "MODE2/2048",
+        TrackMode::Mode2Form2 => "MODE2/2324",
+        TrackMode::Mode2FormMix => "MODE2/2336",
+        TrackMode::Mode2Raw => "MODE2/2352",
+    }
+}
+
+impl Probe {
+    pub fn new(file: PathBuf) -> io::Result<Probe> {
+        let size = file.metadata()?.len();
+        let name = file
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+        let head = read_at(&file, 0, 16.min(size as usize)).unwrap_or_default();
+
+        let tracks = (name.ends_with(".cue") || name.ends_with(".cue.txt"))
+            .then(|| CD::parse_file(file.clone()).ok())
+            .flatten()
+            .map(|cd| {
+                cd.tracks()
+                    .iter()
+                    .enumerate()
+                    .map(|(i, track)| {
+                        let bin = file.parent().unwrap().join(track.get_filename());
+                        let start = track.get_start();
+
+                        ProbeTrack {
+                            number: i + 1,
+                            file: track.get_filename(),
+                            mode: track_mode(track.get_mode()),
+                            start: format!(
+                                "{:02}:{:02}:{:02}",
+                                start / 75 / 60,
+                                start / 75 % 60,
+                                start % 75
+                            ),
+                            file_size: bin.metadata().ok().map(|m| m.len()),
+                            sector_mode: sector_mode(&bin),
+                        }
+                    })
+                    .collect()
+            });
+
+        let iso = read_at(&file, ISO_PVD_OFFSET, 136)
+            .filter(|pvd| pvd[0] == 1 && pvd[1..6] == *b"CD001")
+            .map(|pvd| ProbeIso {
+                system_id: text(&pvd[8..40]),
+                volume_id: text(&pvd[40..72]),
+                volume_blocks: u32::from_le_bytes(pvd[80..84].try_into().unwrap()),
+                block_size: u16::from_le_bytes(pvd[128..130].try_into().unwrap()),
+                looks_truncated: iso_looks_truncated(&file),
+            });
+
+        let wii = read_at(&file, 0, 0x60).and_then(|header| {
+            let console = if header[0x18..0x1C] == WII_MAGIC {
+                "Wii"
+            } else if header[0x1C..0x20] == GAMECUBE_MAGIC {
+                "GameCube"
+            } else {
+                return None;
+            };
+
+            Some(ProbeWii {
+                console,
+                game_id: text(&header[..6]),
+                title: text(&header[0x20..0x60]),
+            })
+        });
+
+        let n64 = read_at(&file, 0, 0x40).and_then(|mut header| {
+            let byte_order = ByteOrder::detect(&header[..4].try_into().unwrap())?;
+            byte_order.to_big_endian(&mut header);
+
+            Some(ProbeN64 {
+                byte_order: format!("{:?}", byte_order),
+                title: text(&header[0x20..0x34]),
+                game_code: text(&header[0x3B..0x3F]),
+            })
+        });
+
+        let nds = name
+            .ends_with(".nds")
+            .then(|| read_at(&file, 0, 0x88))
+            .flatten()
+            .map(|header| ProbeNds {
+                title: text(&header[..12]),
+                game_code: text(&header[12..16]),
+                header_size: u32::from_le_bytes(header[0x84..0x88].try_into().unwrap()),
+                used_rom_size: u32::from_le_bytes(header[0x80..0x84].try_into().unwrap()),
+            });
+
+        Ok(Probe {
+            detected: guess_file(&file)
+                .map(|f| f.iter_names().map(|(n, _)| n.to_string()).collect())
+                .unwrap_or_default(),
+            magic: head
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .join(" "),
+            tracks,
+            iso,
+            wii,
+            n64,
+            nds,
+            chd: probe_chd(&file),
+            rvz: probe_rvz(&file),
+            wbfs_parts: name.ends_with(".wbfs").then(|| wbfs_parts(&file)),
+            playlist: name.ends_with(".m3u").then(|| read_m3u(&file)).flatten(),
+            file,
+            size,
+        })
+    }
+
+    pub fn print(&self) {
+        println!("File: {}", self.file.display());
+        println!(
+            "Size: {} ({} bytes)",
+            format_size(self.size, DECIMAL),
+            self.size
+        );
+        println!(
+            "Detected: {}",
+            if self.detected.is_empty() {
+                "not recognized".to_string()
+            } else {
+                self.detected.join(", ")
+            }
+        );
+        println!("Magic bytes: {}", self.magic);
+
+        if let Some(tracks) = self.tracks.as_ref() {
+            println!("Cue sheet tracks:");
+
+            for track in tracks.iter() {
+                println!(
+                    "\t{:02} {} {} at {}, {}{}",
+                    track.number,
+                    track.mode,
+                    track.file,
+                    track.start,
+                    track
+                        .file_size
+                        .map(|s| format_size(s, DECIMAL))
+                        .unwrap_or_else(|| "missing".to_string()),
+                    track
+                        .sector_mode
+                        .map(|m| format!(", first sector is MODE{}", m))
+                        .unwrap_or_default()
+                );
+            }
+        }
+
+        if let Some(iso) = self.iso.as_ref() {
+            println!("ISO 9660 primary volume descriptor:");
+            println!("\tSystem identifier: {}", iso.system_id);
+            println!("\tVolume identifier: {}", iso.volume_id);
+            println!(
+                "\tVolume size: {} blocks of {} bytes",
+                iso.volume_blocks, iso.block_size
+            );
+            println!("\tLooks truncated: {}", iso.looks_truncated);
+        }
+
+        if let Some(wii) = self.wii.as_ref() {
+            println!("{} disc header:", wii.console);
+            println!("\tGame ID: {}", wii.game_id);
+            println!("\tTitle: {}", wii.title);
+        }
+
+        if let Some(n64) = self.n64.as_ref() {
+            println!("N64 header:");
+            println!("\tByte order: {}", n64.byte_order);
+            println!("\tTitle: {}", n64.title);
+            println!("\tGame code: {}", n64.game_code);
+        }
+
+        if let Some(nds) = self.nds.as_ref() {
+            println!("NDS header:");
+            println!("\tTitle: {}", nds.title);
+            println!("\tGame code: {}", nds.game_code);
+            println!("\tHeader size: {} bytes", nds.header_size);
+            println!("\tUsed ROM size: {} bytes", nds.used_rom_size);
+        }
+
+        if let Some(chd) = self.chd.as_ref() {
+            println!("CHD header:");
+            println!("\tVersion: {}", chd.version);
+
+            if let Some(size) = chd.logical_size {
+                println!("\tUncompressed size: {} bytes", size);
+            }
+
+            if let Some(hunk_size) = chd.hunk_size {
+                println!("\tHunk size: {} bytes", hunk_size);
+            }
+
+            if let Some(sha1) = chd.data_sha1.as_ref() {
+                println!("\tData SHA-1: {}", sha1);
+            }
+
+            for track in chd.tracks.iter() {
+                println!(
+                    "\tTrack {:02}: {}, {} frames",
+                    track.number, track.track_type, track.frames
+                );
+            }
+        }
+
+        if let Some(rvz) = self.rvz.as_ref() {
+            println!("RVZ header:");
+            println!("\tGame ID: {}", rvz.game_id);
+            println!("\tTitle: {}", rvz.title);
+            println!("\tUncompressed size: {} bytes", rvz.iso_size);
+            println!(
+                "\tCompression: {} in chunks of {} bytes",
+                rvz.compression, rvz.chunk_size
+            );
+        }
+
+        if let Some(parts) = self.wbfs_parts.as_ref() {
+            println!("Additional WBFS parts: {}", parts.len());
+
+            for part in parts.iter() {
+                println!("\t{}", part.display());
+            }
+        }
+
+        if let Some(entries) = self.playlist.as_ref() {
+            println!("Playlist entries:");
+
+            for entry in entries.iter() {
+                println!("\t{}", entry.display());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::write;
+
+    #[test]
+    fn looping_chd_metadata_ends() {
+        let mut chd = vec![0; CHD_V5_HEADER_SIZE];
+        chd[..8].copy_from_slice(CHD_MAGIC);
+        chd[12..16].copy_from_slice(&5_u32.to_be_bytes());
+        chd[48..56].copy_from_slice(&(CHD_V5_HEADER_SIZE as u64).to_be_bytes());
+
+        // a track entry which names itself as the next entry
+        let data = b"TRACK:1 TYPE:MODE1_RAW FRAMES:100\0";
+        let mut entry = b"CHT2".to_vec();
+        entry.extend([1, 0, 0, data.len() as u8]);
+        entry.extend((CHD_V5_HEADER_SIZE as u64).to_be_bytes());
+        entry.extend(data);
+        chd.extend(entry);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("game.chd");
+        write(&path, chd).unwrap();
+
+        let probe = probe_chd(&path).unwrap();
+
+        assert_eq!(probe.tracks.len(), CHD_MAX_METADATA_ENTRIES);
+        assert_eq!(probe.tracks[0].frames, 100);
+    }
+}
@@ -0,0 +1,129 @@
+use crate::convert::{savings, ConversionRecord, SizeMode, SkipReason};
+use gag::Gag;
+use humansize::{format_size, DECIMAL};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::{
+    collections::HashMap,
+    io::{self, stdout, IsTerminal},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// progress bars on stderr, an overall one and a spinner per running conversion
+pub struct Progress {
+    pub total_files: usize,
+    pub start: Instant,
+    pub processed_files: Arc<AtomicUsize>,
+    pub failed_files: Arc<Mutex<Vec<(PathBuf, String)>>>,
+    pub skipped_files: Arc<Mutex<Vec<(PathBuf, SkipReason)>>>,
+    pub input_file_size: Arc<AtomicUsize>,
+    pub output_file_size: Arc<AtomicUsize>,
+    pub active_files: Arc<Mutex<Vec<PathBuf>>>,
+    pub records: Arc<Mutex<Vec<ConversionRecord>>>,
+    pub size_mode: SizeMode,
+}
+
+impl Progress {
+    /// draws the bars until the run stops. if stdout is the same terminal, everything printed to it
+    /// in the meantime gets discarded and only finished and failed files are printed above the bars
+    pub fn run(&self, running: &AtomicBool) -> io::Result<()> {
+        let gag = stdout().is_terminal().then(Gag::stdout).transpose()?;
+        let multi = MultiProgress::with_draw_target(ProgressDrawTarget::stderr());
+        let overall = multi.add(ProgressBar::new(self.total_files as u64));
+        let spinner_style = ProgressStyle::with_template("{spinner} {elapsed:>4} {msg}").unwrap();
+        let mut spinners: HashMap<PathBuf, ProgressBar> = HashMap::new();
+        let (mut finished, mut failed) = (0, 0);
+
+        overall.set_style(
+            ProgressStyle::with_template("[{bar:40}] {pos}/{len} files, {msg}")
+                .unwrap()
+                .progress_chars("=> "),
+        );
+
+        loop {
+            // one last pass once the run stopped prints the files which finished since the previous one
+            let stopped = !running.load(Ordering::Relaxed);
+            let active_files = self.active_files.lock().unwrap().clone();
+
+            spinners.retain(|file, spinner| {
+                let keep = active_files.contains(file);
+                if !keep {
+                    spinner.finish_and_clear();
+                    multi.remove(spinner);
+                }
+                keep
+            });
+
+            for file in active_files.iter() {
+                spinners.entry(file.clone()).or_insert_with(|| {
+                    let spinner = multi.add(ProgressBar::new_spinner());
+                    spinner.set_style(spinner_style.clone());
+                    spinner.set_message(format!(
+                        "{} ({})",
+                        file.display(),
+                        format_size(self.size_mode.of(file).unwrap_or(0), DECIMAL)
+                    ));
+                    spinner
+                });
+            }
+
+            for spinner in spinners.values() {
+                spinner.tick();
+            }
+
+            if gag.is_some() {
+                for record in self.records.lock().unwrap().iter().skip(finished) {
+                    multi.println(format!(
+                        "Finished compression of {}: {} to {}. {}",
+                        record.output.display(),
+                        format_size(record.input_size, DECIMAL),
+                        format_size(record.output_size, DECIMAL),
+                        savings(record.input_size, record.output_size)
+                    ))?;
+                    finished += 1;
+                }
+
+                for (file, e) in self.failed_files.lock().unwrap().iter().skip(failed) {
+                    multi.println(format!("Failed compression of {}: {}", file.display(), e))?;
+                    failed += 1;
+                }
+            }
+
+            self.update_overall(&overall);
+
+            if stopped {
+                break;
+            }
+
+            std::thread::sleep(Duration::from_millis(100));
+        }
+
+        for spinner in spinners.values() {
+            spinner.finish_and_clear();
+        }
+        overall.finish_and_clear();
+
+        Ok(())
+    }
+
+    fn update_overall(&self, overall: &ProgressBar) {
+        let processed = self.processed_files.load(Ordering::Relaxed);
+        let failed = self.failed_files.lock().unwrap().len();
+        let skipped = self.skipped_files.lock().unwrap().len();
+        let is = self.input_file_size.load(Ordering::Relaxed);
+        let os = self.output_file_size.load(Ordering::Relaxed);
+        let elapsed = self.start.elapsed().as_secs_f64();
+
+        overall.set_position((processed + failed + skipped) as u64);
+        overall.set_message(format!(
+            "{} running, saved {}, {}/s",
+            self.active_files.lock().unwrap().len(),
+            format_size(is.saturating_sub(os), DECIMAL),
+            format_size((is as f64 / elapsed.max(1.0)) as u64, DECIMAL)
+        ));
+    }
+}
@@ -0,0 +1,55 @@
+use std::{fs::copy, io, path::Path};
+
+/// duplicates a file by cloning its data, which is instant and takes no additional space on copy-on-write
+/// filesystems like btrfs, XFS or APFS. falls back to a regular copy if cloning isn't supported,
+/// e.g. by the filesystem or because both files are on different filesystems.
+/// returns whether the file was cloned
+pub fn reflink_or_copy(from: &Path, to: &Path) -> io::Result<bool> {
+    match reflink(from, to) {
+        Ok(()) => Ok(true),
+        Err(_) => copy(from, to).map(|_| false),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn reflink(from: &Path, to: &Path) -> io::Result<()> {
+    use std::{
+        fs::{remove_file, File},
+        os::fd::AsRawFd,
+    };
+
+    let source = File::open(from)?;
+    let target = File::create(to)?;
+
+    // SAFETY: both file descriptors stay open until the call returns
+    if unsafe { libc::ioctl(target.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) } != 0 {
+        let error = io::Error::last_os_error();
+        drop(target);
+        let _ = remove_file(to);
+        return Err(error);
+    }
+
+    // a regular copy keeps the permissions as well
+    target.set_permissions(source.metadata()?.permissions())
+}
+
+#[cfg(target_os = "macos")]
+fn reflink(from: &Path, to: &Path) -> io::Result<()> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let from = CString::new(from.as_os_str().as_bytes())?;
+    let to = CString::new(to.as_os_str().as_bytes())?;
+
+    // SAFETY: both paths are nul-terminated and outlive the call.
+    // clonefile fails if the target exists, which the regular copy then overwrites
+    if unsafe { libc::clonefile(from.as_ptr(), to.as_ptr(), 0) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn reflink(_from: &Path, _to: &Path) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
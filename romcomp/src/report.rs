@@ -0,0 +1,148 @@
+use crate::{convert::ConversionRecord, hash::InputHash};
+use humansize::{format_size, DECIMAL};
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    fs::{rename, write},
+    io,
+    path::{Path, PathBuf},
+};
+
+/// the outcome of a file in the JSON report
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+    Processed,
+    Skipped,
+    Failed,
+    Aborted,
+}
+
+/// a file of the JSON report written with --report
+#[derive(Clone, Debug, Serialize)]
+pub struct ReportEntry {
+    pub input: PathBuf,
+    /// the output file, or the one it would have been written to if the file wasn't processed
+    pub output: Option<PathBuf>,
+    #[serde(rename = "input_bytes")]
+    pub input_size: u64,
+    /// the size of the existing output for skipped files, 0 if no output was written
+    #[serde(rename = "output_bytes")]
+    pub output_size: u64,
+    /// the output size divided by the input size, if both are known
+    pub ratio: Option<f64>,
+    pub status: FileStatus,
+    /// why the file was skipped, failed or got aborted
+    pub reason: Option<String>,
+    /// the hashes of the input roms, see --hash
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub hashes: Vec<InputHash>,
+}
+
+impl ReportEntry {
+    pub fn new(
+        input: PathBuf,
+        output: Option<PathBuf>,
+        input_size: u64,
+        output_size: u64,
+        status: FileStatus,
+        reason: Option<String>,
+    ) -> Self {
+        Self {
+            input,
+            output,
+            input_size,
+            output_size,
+            ratio: (input_size > 0 && output_size > 0)
+                .then(|| output_size as f64 / input_size as f64),
+            status,
+            reason,
+            hashes: vec![],
+        }
+    }
+
+    pub fn hashes(mut self, hashes: Vec<InputHash>) -> Self {
+        self.hashes = hashes;
+        self
+    }
+}
+
+/// writes every file of the run and the totals as JSON. the totals only include processed files,
+/// just like the summary printed after the run
+pub fn write_json_report(path: &Path, entries: &[ReportEntry]) -> io::Result<()> {
+    let count = |status: FileStatus| entries.iter().filter(|e| e.status == status).count();
+    let (is, os) = entries
+        .iter()
+        .filter(|e| e.status == FileStatus::Processed)
+        .fold((0u64, 0u64), |(is, os), e| {
+            (is + e.input_size, os + e.output_size)
+        });
+
+    let mut files = entries.to_vec();
+    files.sort_by(|a, b| a.input.cmp(&b.input));
+
+    let report = json!({
+        "files": files,
+        "totals": {
+            "files_processed": count(FileStatus::Processed),
+            "files_skipped": count(FileStatus::Skipped),
+            "files_failed": count(FileStatus::Failed),
+            "files_aborted": count(FileStatus::Aborted),
+            "input_bytes": is,
+            "output_bytes": os,
+            "saved_bytes": is as i128 - os as i128,
+            "ratio": (is > 0).then(|| os as f64 / is as f64),
+        },
+    });
+
+    // written next to the report and renamed, so an existing report is never left half written
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+
+    write(&temp, serde_json::to_string_pretty(&report)?)?;
+    rename(&temp, path)
+}
+
+/// prints the input and output sizes of all conversions, grouped by the directory of the input files.
+/// the directories which saved the most space come first
+pub fn print_report_by_dir(records: &[ConversionRecord]) {
+    let mut dirs: HashMap<&Path, (u64, u64)> = HashMap::new();
+
+    for record in records.iter() {
+        let sizes = dirs.entry(record.input.parent().unwrap()).or_insert((0, 0));
+        sizes.0 += record.input_size;
+        sizes.1 += record.output_size;
+    }
+
+    let mut dirs = dirs.into_iter().collect::<Vec<_>>();
+    dirs.sort_by_key(|(_, (is, os))| std::cmp::Reverse(*is as i128 - *os as i128));
+
+    println!(
+        "{:>12} {:>12} {:>12} {:>8}  Directory",
+        "Input", "Output", "Saved", "Saved %"
+    );
+
+    for (dir, (is, os)) in dirs.iter() {
+        let saved = if is >= os {
+            format_size(is - os, DECIMAL)
+        } else {
+            format!("-{}", format_size(os - is, DECIMAL))
+        };
+
+        let percentage = if *is > 0 {
+            format!("{:.2}%", 100f64 - (*os as f64 * 100f64 / *is as f64))
+        } else {
+            "-".to_string()
+        };
+
+        println!(
+            "{:>12} {:>12} {:>12} {:>8}  {}",
+            format_size(*is, DECIMAL),
+            format_size(*os, DECIMAL),
+            saved,
+            percentage,
+            dir.display()
+        );
+    }
+}
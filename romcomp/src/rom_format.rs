@@ -1,48 +1,680 @@
+use crate::{
+    cso::{compress_cso, CsoFormat},
+    n64::convert_to_z64,
+};
 use bitflags::bitflags;
-use duct::{cmd, Expression};
-use std::path::PathBuf;
+use clap::ValueEnum;
+use crossbeam_channel::Receiver;
+use duct::cmd;
+use lazy_regex::regex_find;
+use log::debug;
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, io,
+    io::ErrorKind,
+    iter::once,
+    ops::RangeInclusive,
+    path::Path,
+    process::{Command, Stdio},
+    time::Duration,
+};
+use zip::{
+    write::{FileOptions, SimpleFileOptions},
+    CompressionMethod,
+};
 
-#[derive(Copy, Clone, Eq, PartialEq)]
+/// settings which influence the compression tools and are shared by all conversions
+#[derive(Clone, Default)]
+pub struct CompressionOptions {
+    /// hunk size in bytes passed to chdman, uses chdman's default if not set
+    pub chd_hunk_size: Option<u32>,
+    /// the resolved binary of each tool, tools missing here use their default binary
+    pub binaries: HashMap<CompressionTool, String>,
+    /// tools which get replaced by romcomp's own implementation
+    pub internal: HashSet<CompressionTool>,
+    /// compression level passed to dolphin-tool, uses the level of the preset if not set
+    pub rvz_level: Option<u8>,
+    /// compression codec passed to dolphin-tool, uses the codec of the preset if not set
+    pub rvz_codec: Option<RvzCodec>,
+    /// block size in bytes passed to dolphin-tool, uses 128 KiB if not set
+    pub rvz_block_size: Option<u32>,
+    /// let dolphin-tool remove the unused parts of GameCube and Wii discs, which makes the output differ from the dump
+    pub rvz_scrub: bool,
+    /// the format maxcso creates
+    pub cso_format: CsoFormat,
+    /// block size in bytes passed to maxcso, uses maxcso's default, which depends on the size of the image, if not set
+    pub cso_block_size: Option<u32>,
+    /// threads passed to maxcso, uses maxcso's default if not set
+    pub cso_threads: Option<usize>,
+    /// create a DVD chd instead of a CD chd, depends on the image converted
+    pub chd_dvd: bool,
+    /// uncompressed size of each frame of seekable zstd files, uses 1 MiB if not set
+    pub zstd_frame_size: Option<u32>,
+    /// the compression method of the zip files created for n64 and nds roms
+    pub zip_method: ZipMethod,
+    /// password the zip outputs get encrypted with using AES-256, they aren't encrypted if not set
+    #[cfg_attr(not(feature = "encrypt"), allow(dead_code))]
+    pub encrypt_password: Option<String>,
+    /// the defaults of the settings which aren't given explicitly
+    pub preset: Preset,
+}
+
+impl CompressionOptions {
+    /// the options of a zip entry, encrypted if a password was given
+    pub fn zip_entry(&self, method: CompressionMethod) -> FileOptions<'_, ()> {
+        let level = match method {
+            CompressionMethod::Deflated => Some(self.preset.zip_level()),
+            #[cfg(feature = "zip-zstd")]
+            CompressionMethod::Zstd => Some(self.preset.zstd_level().into()),
+            _ => None,
+        };
+        let options = SimpleFileOptions::default()
+            .compression_method(method)
+            .compression_level(level);
+
+        #[cfg(feature = "encrypt")]
+        if let Some(password) = self.encrypt_password.as_deref() {
+            return options.with_aes_encryption(zip::AesMode::Aes256, password);
+        }
+
+        options
+    }
+
+    /// the options of the zip entry of an n64 or nds rom, see --zip-method.
+    /// a method romcomp wasn't built for is rejected before any conversion starts
+    pub fn rom_zip_entry(&self) -> FileOptions<'_, ()> {
+        self.zip_entry(
+            self.zip_method
+                .compression_method()
+                .unwrap_or(CompressionMethod::Deflated),
+        )
+    }
+
+    /// the extension of the files created by a tool, which depends on the format for maxcso
+    pub fn extension(&self, tool: CompressionTool) -> &'static str {
+        match tool {
+            CompressionTool::MaxCSO => self.cso_format.extension(),
+            _ => tool.extension(),
+        }
+    }
+
+    /// why the cso settings can't be used with the internal cso encoder, which only creates CSO v1 files
+    /// with blocks of 2048 bytes
+    pub fn internal_cso_settings(&self) -> Result<(), String> {
+        if self.cso_format != CsoFormat::Cso1 {
+            return Err(format!(
+                "the internal cso encoder can't create {} files",
+                self.cso_format.maxcso_name()
+            ));
+        }
+
+        if self.cso_block_size.is_some_and(|s| s != 2048) {
+            return Err("the internal cso encoder only uses blocks of 2048 bytes".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// the codec and level passed to dolphin-tool, or why they can't be combined.
+    /// the level of the preset is limited to the range of the codec, an explicit level has to be within it
+    pub fn rvz_settings(&self) -> Result<(RvzCodec, Option<u8>), String> {
+        let (preset_codec, preset_level) = self.preset.rvz();
+        let codec = self.rvz_codec.unwrap_or(preset_codec);
+
+        match (codec.levels(), self.rvz_level) {
+            (None, None) => Ok((codec, None)),
+            (None, Some(level)) => Err(format!(
+                "the rvz codec {} doesn't take a compression level, but {} was given",
+                codec, level
+            )),
+            (Some(levels), None) => Ok((
+                codec,
+                Some(preset_level.clamp(*levels.start(), *levels.end())),
+            )),
+            (Some(levels), Some(level)) if levels.contains(&level) => Ok((codec, Some(level))),
+            (Some(levels), Some(level)) => Err(format!(
+                "the rvz compression level {} is out of range for {}, which supports {} to {}",
+                level,
+                codec,
+                levels.start(),
+                levels.end()
+            )),
+        }
+    }
+}
+
+/// defaults for the compression settings of every format, trading conversion speed for smaller outputs.
+/// settings given explicitly, e.g. by a sidecar file, take precedence
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum Preset {
+    /// the fastest settings of every tool
+    Fast,
+    /// the defaults of every tool
+    #[default]
+    Balanced,
+    /// the smallest outputs, at the cost of much longer conversions
+    Max,
+}
+
+impl Preset {
+    /// the codec and level passed to dolphin-tool
+    pub fn rvz(self) -> (RvzCodec, u8) {
+        match self {
+            Preset::Fast => (RvzCodec::Zstd, 1),
+            Preset::Balanced => (RvzCodec::Zstd, 5),
+            Preset::Max => (RvzCodec::Zstd, 22),
+        }
+    }
+
+    /// the codecs passed to chdman, which picks the smallest of cdlz, cdzl and cdfl for every hunk by default,
+    /// or of lzma, zlib, huff and flac for DVDs. zstd is never used, older emulators can't read it
+    pub fn chd_codecs(self, dvd: bool) -> Option<&'static str> {
+        match self {
+            Preset::Fast if dvd => Some("zlib,huff"),
+            Preset::Fast => Some("cdzl,cdfl"),
+            Preset::Balanced | Preset::Max => None,
+        }
+    }
+
+    /// the additional arguments passed to maxcso
+    pub fn maxcso_arguments(self) -> &'static [&'static str] {
+        match self {
+            Preset::Fast => &["--fast"],
+            Preset::Balanced => &[],
+            Preset::Max => &["--use-zopfli"],
+        }
+    }
+
+    /// the deflate level of the internal cso encoder
+    pub fn cso_level(self) -> u32 {
+        match self {
+            Preset::Fast => 1,
+            Preset::Balanced | Preset::Max => 9,
+        }
+    }
+
+    /// the deflate level of zip files, levels above 9 use zopfli with (level - 9) iterations
+    pub fn zip_level(self) -> i64 {
+        match self {
+            Preset::Fast => 1,
+            Preset::Balanced => 6,
+            Preset::Max => 24,
+        }
+    }
+
+    /// the level of the files created by --zstd-seekable
+    pub fn zstd_level(self) -> i32 {
+        match self {
+            Preset::Fast => 1,
+            Preset::Balanced => zstd::DEFAULT_COMPRESSION_LEVEL,
+            Preset::Max => 19,
+        }
+    }
+
+    /// the compression level passed to flac
+    pub fn flac_level(self) -> &'static str {
+        match self {
+            Preset::Fast => "--fast",
+            Preset::Balanced | Preset::Max => "--best",
+        }
+    }
+}
+
+/// the codecs dolphin-tool supports for rvz files
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum RvzCodec {
+    None,
+    Zstd,
+    #[value(alias = "bzip")]
+    Bzip2,
+    Lzma,
+    Lzma2,
+}
+
+impl RvzCodec {
+    /// the compression levels dolphin-tool accepts for the codec, None if it doesn't take a level
+    pub fn levels(self) -> Option<RangeInclusive<u8>> {
+        match self {
+            RvzCodec::None => None,
+            RvzCodec::Zstd => Some(1..=22),
+            RvzCodec::Bzip2 | RvzCodec::Lzma | RvzCodec::Lzma2 => Some(1..=9),
+        }
+    }
+}
+
+/// the compression method of the zip files created for n64 and nds roms
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum ZipMethod {
+    /// deflate, which every emulator reads
+    #[default]
+    Deflated,
+    /// no compression at all, e.g. for trimmed nds roms which hardly compress anyway
+    Stored,
+    /// zstd, requires the zip-zstd feature. only few emulators read it
+    Zstd,
+    /// bzip2, requires the zip-bzip2 feature. only few emulators read it
+    Bzip2,
+}
+
+impl ZipMethod {
+    /// the method of the zip crate, or the feature romcomp has to be built with to support it
+    pub fn compression_method(self) -> Result<CompressionMethod, &'static str> {
+        match self {
+            ZipMethod::Deflated => Ok(CompressionMethod::Deflated),
+            ZipMethod::Stored => Ok(CompressionMethod::Stored),
+            #[cfg(feature = "zip-zstd")]
+            ZipMethod::Zstd => Ok(CompressionMethod::Zstd),
+            #[cfg(not(feature = "zip-zstd"))]
+            ZipMethod::Zstd => Err("zip-zstd"),
+            #[cfg(feature = "zip-bzip2")]
+            ZipMethod::Bzip2 => Ok(CompressionMethod::Bzip2),
+            #[cfg(not(feature = "zip-bzip2"))]
+            ZipMethod::Bzip2 => Err("zip-bzip2"),
+        }
+    }
+}
+
+impl fmt::Display for RvzCodec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RvzCodec::None => write!(f, "none"),
+            RvzCodec::Zstd => write!(f, "zstd"),
+            RvzCodec::Bzip2 => write!(f, "bzip2"),
+            RvzCodec::Lzma => write!(f, "lzma"),
+            RvzCodec::Lzma2 => write!(f, "lzma2"),
+        }
+    }
+}
+
+impl CompressionOptions {
+    pub fn binary(&self, tool: CompressionTool) -> String {
+        self.binaries
+            .get(&tool)
+            .cloned()
+            .unwrap_or_else(|| tool.to_string())
+    }
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, Eq, Hash, PartialEq)]
+#[serde(rename_all = "lowercase")]
 pub enum CompressionTool {
     BitButcher,
     Chdman,
+    #[serde(rename = "dolphin-tool")]
     DolphinTool,
+    Flac,
     MaxCSO,
     Rom64,
 }
 
+impl fmt::Display for CompressionTool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompressionTool::BitButcher => write!(f, "BitButcher"),
+            CompressionTool::Chdman => write!(f, "chdman"),
+            CompressionTool::DolphinTool => write!(f, "dolphin-tool"),
+            CompressionTool::Flac => write!(f, "flac"),
+            CompressionTool::MaxCSO => write!(f, "maxcso"),
+            CompressionTool::Rom64 => write!(f, "rom64"),
+        }
+    }
+}
+
+/// how many of the last lines a failing tool printed explain its failure
+const FAILURE_LINES: usize = 5;
+
+/// the numbers of a version like 0.262 or 1.13.0, trailing zeros don't count, so 1.4 equals 1.4.0
+pub fn parse_version(text: &str) -> Option<Vec<u32>> {
+    let mut version = regex_find!(r"\d+(\.\d+)+", text)?
+        .split('.')
+        .map(|n| n.parse().ok())
+        .collect::<Option<Vec<u32>>>()?;
+
+    while version.len() > 1 && version.last() == Some(&0) {
+        version.pop();
+    }
+
+    Some(version)
+}
+
 impl CompressionTool {
-    pub fn build(&self, input: &PathBuf, output: &PathBuf) -> Expression {
+    /// the version of the tool's binary, read from the first version number it prints.
+    /// None if the binary doesn't print one, e.g. dolphin-tool
+    pub fn version(&self, binary: &str) -> Option<Vec<u32>> {
+        // chdman prints its version in the banner of its usage
+        let args: &[&str] = match self {
+            CompressionTool::Chdman => &[],
+            CompressionTool::Flac | CompressionTool::MaxCSO => &["--version"],
+            _ => return None,
+        };
+        let output = cmd(binary, args)
+            .stdout_capture()
+            .stderr_capture()
+            .unchecked()
+            .run()
+            .ok()?;
+
+        parse_version(&String::from_utf8_lossy(&output.stdout))
+            .or_else(|| parse_version(&String::from_utf8_lossy(&output.stderr)))
+    }
+
+    /// returns the first of the given binaries which can be executed
+    pub fn resolve(candidates: &[String]) -> Option<String> {
+        candidates
+            .iter()
+            .find(|c| {
+                !matches!(
+                    Command::new(c)
+                        .stdout(Stdio::null())
+                        .stderr(Stdio::null())
+                        .spawn(),
+                    Err(e) if e.kind() == ErrorKind::NotFound
+                )
+            })
+            .cloned()
+    }
+
+    /// runs romcomp's own implementation of the tool instead of the tool itself,
+    /// returns whether the conversion got interrupted
+    pub fn run_internal(
+        &self,
+        input: &Path,
+        output: &Path,
+        options: &CompressionOptions,
+        interrupt: &Receiver<()>,
+    ) -> io::Result<bool> {
         match self {
-            CompressionTool::BitButcher => cmd!("BitButcher", "-e", input.to_str().unwrap(),),
-            CompressionTool::Chdman => cmd!(
-                "chdman",
-                "createcd",
-                "-i",
-                input.to_str().unwrap(),
-                "-o",
-                output.to_str().unwrap(),
-            ),
-            CompressionTool::DolphinTool => cmd!(
-                "dolphin-tool",
+            CompressionTool::MaxCSO => {
+                compress_cso(input, output, options.preset.cso_level(), interrupt)
+            }
+            CompressionTool::Rom64 => convert_to_z64(input, output, interrupt),
+            _ => Err(io::Error::new(
+                ErrorKind::Unsupported,
+                format!("{} has no internal implementation", self),
+            )),
+        }
+    }
+
+    /// the extension of the files created by the tool with its default settings
+    pub fn extension(&self) -> &'static str {
+        match self {
+            CompressionTool::BitButcher => "nds",
+            CompressionTool::Chdman => "chd",
+            CompressionTool::DolphinTool => "rvz",
+            CompressionTool::Flac => "flac",
+            CompressionTool::MaxCSO => "cso",
+            CompressionTool::Rom64 => "z64",
+        }
+    }
+
+    /// the arguments passed to the tool's binary
+    pub fn arguments(
+        &self,
+        input: &Path,
+        output: &Path,
+        options: &CompressionOptions,
+    ) -> Vec<String> {
+        let input = input.to_str().unwrap();
+        let output = output.to_str().unwrap();
+        // the rvz settings are checked before any conversion starts
+        let (rvz_codec, rvz_level) = options.rvz_settings().unwrap();
+        let rvz_codec = rvz_codec.to_string();
+        let rvz_block_size = options.rvz_block_size.unwrap_or(128 * 1024).to_string();
+        let cso_format = format!("--format={}", options.cso_format.maxcso_name());
+
+        let mut args: Vec<String> = match self {
+            CompressionTool::BitButcher => vec!["-e", input],
+            CompressionTool::Chdman if options.chd_dvd => {
+                vec!["createdvd", "-i", input, "-o", output]
+            }
+            CompressionTool::Chdman => vec!["createcd", "-i", input, "-o", output],
+            CompressionTool::DolphinTool => vec![
                 "convert",
                 "-b",
-                "131072",
+                &rvz_block_size,
                 "-c",
-                "zstd",
+                &rvz_codec,
                 "-f",
                 "rvz",
                 "-i",
-                input.to_str().unwrap(),
-                "-l",
-                "5",
+                input,
                 "-o",
-                output.to_str().unwrap(),
-            ),
-            CompressionTool::MaxCSO => cmd!("maxcso", input.to_str().unwrap(),),
-            CompressionTool::Rom64 => cmd!("rom64", "convert", input.to_str().unwrap(),),
+                output,
+            ],
+            // raw CD audio is 16 bit little endian stereo pcm at 44.1 kHz
+            CompressionTool::Flac => vec![
+                "--silent",
+                options.preset.flac_level(),
+                "--force-raw-format",
+                "--endian=little",
+                "--sign=signed",
+                "--channels=2",
+                "--bps=16",
+                "--sample-rate=44100",
+                "-o",
+                output,
+                input,
+            ],
+            CompressionTool::MaxCSO => vec![input, "-o", output, &cso_format],
+            CompressionTool::Rom64 => vec!["convert", input],
         }
+        .into_iter()
+        .map(String::from)
+        .collect();
+
+        if *self == CompressionTool::Chdman {
+            // the hunk size is given in whole CD frames, DVDs keep the default of chdman
+            if let Some(hunk_size) = options.chd_hunk_size.filter(|_| !options.chd_dvd) {
+                args.push("-hs".to_string());
+                args.push(hunk_size.to_string());
+            }
+
+            if let Some(codecs) = options.preset.chd_codecs(options.chd_dvd) {
+                args.push("-c".to_string());
+                args.push(codecs.to_string());
+            }
+        }
+
+        if let Some(level) = rvz_level.filter(|_| *self == CompressionTool::DolphinTool) {
+            args.push("-l".to_string());
+            args.push(level.to_string());
+        }
+
+        if *self == CompressionTool::DolphinTool && options.rvz_scrub {
+            args.push("-s".to_string());
+        }
+
+        if *self == CompressionTool::MaxCSO {
+            if let Some(block_size) = options.cso_block_size {
+                args.push(format!("--block={}", block_size));
+            }
+
+            if let Some(threads) = options.cso_threads {
+                args.push(format!("--threads={}", threads));
+            }
+
+            args.extend(
+                options
+                    .preset
+                    .maxcso_arguments()
+                    .iter()
+                    .map(|a| a.to_string()),
+            );
+        }
+
+        args
+    }
+
+    /// runs the tool's binary with the given arguments until it exits, the last lines of its error output
+    /// explain a failure, everything it wrote is logged at the debug level. returns whether it got interrupted
+    pub fn run(
+        &self,
+        binary: &str,
+        args: &[String],
+        interrupt: &Receiver<()>,
+    ) -> Result<bool, String> {
+        // the tool's own errors are in the captured output, which is checked once it exited
+        let expression = cmd(binary, args)
+            .stderr_capture()
+            .stdout_capture()
+            .unchecked();
+
+        // a ctrl+c in the terminal reaches the whole process group. in its own group, the tool keeps running
+        // until romcomp kills it, so the first ctrl+c can let it finish
+        #[cfg(unix)]
+        let expression = expression.before_spawn(|command| {
+            std::os::unix::process::CommandExt::process_group(command, 0);
+            Ok(())
+        });
+        #[cfg(windows)]
+        let expression = expression.before_spawn(|command| {
+            const CREATE_NEW_PROCESS_GROUP: u32 = 0x200;
+            std::os::windows::process::CommandExt::creation_flags(
+                command,
+                CREATE_NEW_PROCESS_GROUP,
+            );
+            Ok(())
+        });
+
+        let proc = expression
+            .start()
+            .map_err(|e| format!("Couldn't start {}: {}", self, e))?;
+
+        loop {
+            match proc.try_wait() {
+                Ok(None) => {
+                    std::thread::sleep(Duration::from_millis(50));
+                    if !interrupt.is_empty() {
+                        let _ = proc.kill();
+                        return Ok(true);
+                    }
+                    std::thread::sleep(Duration::from_millis(50));
+                }
+                Ok(Some(result)) if result.status.success() => return Ok(false),
+                Ok(Some(result)) => {
+                    let stdout = String::from_utf8_lossy(&result.stdout);
+                    let stderr = String::from_utf8_lossy(&result.stderr);
+
+                    debug!(
+                        "Output of {} {}:\n{}",
+                        self,
+                        args.join(" "),
+                        format!("{}{}", stdout, stderr).trim_end()
+                    );
+
+                    // some tools print their errors to stdout
+                    let output = if stderr.trim().is_empty() {
+                        stdout
+                    } else {
+                        stderr
+                    };
+                    let mut lines = output
+                        .lines()
+                        .map(|l| l.trim())
+                        .filter(|l| !l.is_empty())
+                        .rev()
+                        .take(FAILURE_LINES)
+                        .collect::<Vec<_>>();
+                    lines.reverse();
+
+                    return Err(match lines.as_slice() {
+                        [] => format!("{} failed with {}", self, result.status),
+                        [line] => format!("{} failed with {}: {}", self, result.status, line),
+                        lines => format!(
+                            "{} failed with {}:\n    {}",
+                            self,
+                            result.status,
+                            lines.join("\n    ")
+                        ),
+                    });
+                }
+                Err(e) => return Err(format!("Couldn't wait for {}: {}", self, e)),
+            }
+        }
+    }
+
+    /// the command running the tool, quoted for POSIX shells
+    pub fn command_line(
+        &self,
+        input: &Path,
+        output: &Path,
+        options: &CompressionOptions,
+    ) -> String {
+        once(options.binary(*self))
+            .chain(self.arguments(input, output, options))
+            .map(|arg| {
+                if !arg.is_empty()
+                    && arg
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c))
+                {
+                    arg
+                } else {
+                    format!("'{}'", arg.replace('\'', "'\\''"))
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// the formats which can be chosen on the command line and in sidecar files
+#[derive(ValueEnum, Clone, Deserialize, Eq, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceRomFormat {
+    /// detect the format of each file, see --format-by-dir
+    Auto,
+    Nds,
+    N64,
+    Psx,
+    Ps2,
+    /// decrypted PS3 disc images, converted into chd files with chdman createdvd
+    Ps3,
+    Psp,
+    Wii,
+    Gcn,
+    Dc,
+    /// MAME romset zips, recompressed in place with zopfli, which is slow but compresses better.
+    /// requires --remove and is never detected by the auto format
+    Arcade,
+}
+
+impl SourceRomFormat {
+    pub fn rom_format(&self) -> Option<RomFormat> {
+        match self {
+            SourceRomFormat::Auto => None,
+            SourceRomFormat::N64 => Some(RomFormat::Nintendo64),
+            SourceRomFormat::Psx => Some(RomFormat::PlayStationX),
+            SourceRomFormat::Ps2 => Some(RomFormat::PlayStation2),
+            SourceRomFormat::Ps3 => Some(RomFormat::PlayStation3),
+            SourceRomFormat::Psp => Some(RomFormat::PlayStationPortable),
+            SourceRomFormat::Nds => Some(RomFormat::NintendoDS),
+            SourceRomFormat::Wii => Some(RomFormat::NintendoWii),
+            SourceRomFormat::Gcn => Some(RomFormat::NintendoGameCube),
+            SourceRomFormat::Dc => Some(RomFormat::Dreamcast),
+            SourceRomFormat::Arcade => Some(RomFormat::Arcade),
+        }
+    }
+}
+
+/// checks a --chd-hunk-size, which is also used for the hunk size of sidecar files
+pub fn parse_chd_hunk_size(value: &str) -> Result<u32, String> {
+    // chdman requires hunks to contain whole CD frames (2352 bytes of data + 96 bytes of subcode)
+    const CD_FRAME_SIZE: u32 = 2448;
+    const MAX_HUNK_SIZE: u32 = 1024 * 1024;
+
+    let size = value.parse::<u32>().map_err(|e| e.to_string())?;
+
+    if size == 0 || size % CD_FRAME_SIZE != 0 || size > MAX_HUNK_SIZE {
+        return Err(format!(
+            "{} must be a multiple of {} and no larger than {}",
+            size, CD_FRAME_SIZE, MAX_HUNK_SIZE
+        ));
     }
+
+    Ok(size)
 }
 
 // these are the possible rom formats
@@ -51,8 +683,8 @@ impl CompressionTool {
 // iso files can contain psx, ps2 and psp, and possibly more
 
 bitflags! {
-    #[derive(Clone, Copy, Eq, PartialEq)]
-    pub struct RomFormat: u16 {
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub struct RomFormat: u32 {
         /// bin file, in combination with a cue or cue.txt file
         const BIN = 0b1;
         /// iso file
@@ -65,40 +697,99 @@ bitflags! {
         const Z64 = 0b10000;
         /// Nintendo DS ROM
         const NDS = 0b100000;
+        /// m3u playlist, referencing multiple disc images
+        const M3U = 0b1000000;
+        /// Wii Backup File System image, possibly split into multiple parts
+        const WBFS = 0b10000000;
+        /// bin / cue combination containing audio tracks,
+        /// which gets archived with flac instead of being converted
+        const FLAC = 0b100000000;
+        /// single file rom which gets wrapped into the zstd seekable format instead of being converted
+        const ZST = 0b1000000000;
+        /// gdi file, referencing the track files of a GD-ROM
+        const GDI = 0b10000000000;
+        /// zip file of an arcade romset, which gets recompressed in place
+        const ZIP = 0b100000000000;
+        /// cso or dax image, which is compressed already and only gets recompressed with --recompress
+        const CSO = 0b1000000000000;
+        /// 64DD disk image, which gets zipped as it is
+        const NDD = 0b10000000000000;
 
         /// the file format flags
-        const FILE_FORMATS = 0b11111111;
+        const FILE_FORMATS = 0b1111111111111111;
 
         /// either a bin / cue combination, or an iso
-        const PlayStationX = 0b100000000;
+        const PlayStationX = 0b10000000000000000;
         /// either a bin / cue combination, or an iso
-        const PlayStation2 = 0b1000000000;
+        const PlayStation2 = 0b100000000000000000;
         /// an iso
-        const PlayStationPortable = 0b10000000000;
-        /// any of the 3 n64 formats (n64, v64 or z64)
-        const Nintendo64 = 0b100000000000;
+        const PlayStationPortable = 0b1000000000000000000;
+        /// any of the 3 n64 formats (n64, v64 or z64), or a 64DD disk
+        const Nintendo64 = 0b10000000000000000000;
         /// Nintendo DS
-        const NintendoDS = 0b1000000000000;
+        const NintendoDS = 0b100000000000000000000;
         /// Nintendo Wii
-        const NintendoWii = 0b10000000000000;
+        const NintendoWii = 0b1000000000000000000000;
+        /// a gdi file with its tracks
+        const Dreamcast = 0b10000000000000000000000;
+        /// a MAME romset zip
+        const Arcade = 0b100000000000000000000000;
+        /// Nintendo GameCube
+        const NintendoGameCube = 0b1000000000000000000000000;
+        /// a decrypted iso of a PlayStation 3 Blu-ray disc
+        const PlayStation3 = 0b10000000000000000000000000;
+
+        /// the console flags
+        const CONSOLES = 0b11111111111111110000000000000000;
     }
 }
 
 impl RomFormat {
+    /// the file formats consisting of a single file, which can be wrapped with --zstd-seekable
+    pub const SINGLE_FILE: RomFormat = RomFormat::ISO
+        .union(RomFormat::N64)
+        .union(RomFormat::V64)
+        .union(RomFormat::Z64)
+        .union(RomFormat::NDD)
+        .union(RomFormat::NDS);
+
     pub fn zip(&self) -> bool {
-        self.contains(RomFormat::Nintendo64) || self.contains(RomFormat::NintendoDS)
+        !self.contains(RomFormat::ZST)
+            && (self.contains(RomFormat::Nintendo64) || self.contains(RomFormat::NintendoDS))
+    }
+
+    /// tools producing a different kind of output which the format can be converted with as well, see --best-of
+    pub fn alternative_tools(&self) -> Vec<CompressionTool> {
+        if self.contains(RomFormat::PlayStation2) && self.contains(RomFormat::ISO) {
+            vec![CompressionTool::MaxCSO]
+        } else {
+            vec![]
+        }
     }
 
     pub fn compression_tool(&self) -> Option<CompressionTool> {
-        if self.contains(RomFormat::PlayStationX) || self.contains(RomFormat::PlayStation2) {
+        if self.contains(RomFormat::FLAC) {
+            // every audio track gets compressed on its own
+            None
+        } else if self.contains(RomFormat::ZST) {
+            None
+        } else if self.contains(RomFormat::PlayStationX)
+            || self.contains(RomFormat::PlayStation2)
+            || self.contains(RomFormat::PlayStation3)
+            || self.contains(RomFormat::Dreamcast)
+        {
             Some(CompressionTool::Chdman)
         } else if self.contains(RomFormat::PlayStationPortable) {
             Some(CompressionTool::MaxCSO)
-        } else if self.contains(RomFormat::Nintendo64) && !self.contains(RomFormat::Z64) {
+        } else if self.contains(RomFormat::Nintendo64)
+            && !self.intersects(RomFormat::Z64 | RomFormat::NDD)
+        {
             Some(CompressionTool::Rom64)
         } else if self.contains(RomFormat::NintendoDS) {
             Some(CompressionTool::BitButcher)
-        } else if self.contains(RomFormat::NintendoWii) {
+        } else if self.contains(RomFormat::NintendoWii)
+            || self.contains(RomFormat::NintendoGameCube)
+        {
             Some(CompressionTool::DolphinTool)
         } else {
             None
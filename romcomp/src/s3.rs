@@ -0,0 +1,223 @@
+use std::{env, path::Path, str::FromStr};
+
+/// where outputs get uploaded to, given as bucket/prefix
+#[derive(Clone, Debug)]
+#[cfg_attr(not(feature = "s3"), allow(dead_code))]
+pub struct S3Target {
+    pub bucket: String,
+    pub prefix: String,
+}
+
+impl FromStr for S3Target {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (bucket, prefix) = value.split_once('/').unwrap_or((value, ""));
+
+        if bucket.is_empty() {
+            return Err("a bucket is required".to_string());
+        }
+
+        Ok(S3Target {
+            bucket: bucket.to_string(),
+            prefix: prefix.trim_matches('/').to_string(),
+        })
+    }
+}
+
+/// uploads files to a bucket of an S3-compatible storage.
+/// the endpoint and the credentials are read from the usual AWS environment variables
+#[derive(Clone)]
+#[cfg_attr(not(feature = "s3"), allow(dead_code))]
+pub struct S3Client {
+    target: S3Target,
+    endpoint: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+}
+
+impl S3Client {
+    pub fn from_env(target: S3Target) -> Result<S3Client, String> {
+        let var = |name: &str| env::var(name).ok().filter(|v| !v.is_empty());
+
+        let region = var("AWS_REGION")
+            .or_else(|| var("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|| "us-east-1".to_string());
+
+        Ok(S3Client {
+            endpoint: var("AWS_ENDPOINT_URL")
+                .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", region))
+                .trim_end_matches('/')
+                .to_string(),
+            region,
+            access_key: var("AWS_ACCESS_KEY_ID").ok_or("AWS_ACCESS_KEY_ID isn't set")?,
+            secret_key: var("AWS_SECRET_ACCESS_KEY").ok_or("AWS_SECRET_ACCESS_KEY isn't set")?,
+            session_token: var("AWS_SESSION_TOKEN"),
+            target,
+        })
+    }
+
+    /// the object key of a file, keeping its path relative to the root directory.
+    /// keys are UTF-8, so paths which aren't can't be uploaded
+    pub fn key(&self, file: &Path, root: &Path) -> Result<String, String> {
+        let relative = match file.strip_prefix(root) {
+            Ok(relative) if !relative.as_os_str().is_empty() => relative,
+            _ => file
+                .file_name()
+                .map(Path::new)
+                .ok_or_else(|| "The file has no name".to_string())?,
+        };
+        let relative = relative
+            .components()
+            .map(|c| c.as_os_str().to_str())
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| "The path isn't valid UTF-8, which object keys have to be".to_string())?
+            .join("/");
+
+        if self.target.prefix.is_empty() {
+            Ok(relative)
+        } else {
+            Ok(format!("{}/{}", self.target.prefix, relative))
+        }
+    }
+
+    /// uploads a file with a single signed PUT request, which S3 limits to 5 GB
+    #[cfg(feature = "s3")]
+    pub fn upload(&self, file: &Path, key: &str) -> Result<(), String> {
+        use hmac::{Hmac, Mac};
+        use sha2::{Digest, Sha256};
+        use std::{fs::File, time::SystemTime};
+
+        fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+            let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+            mac.update(data.as_bytes());
+            mac.finalize().into_bytes().to_vec()
+        }
+
+        fn hex(bytes: &[u8]) -> String {
+            bytes.iter().map(|b| format!("{:02x}", b)).collect()
+        }
+
+        let fh = File::open(file).map_err(|e| e.to_string())?;
+        let length = fh.metadata().map_err(|e| e.to_string())?.len();
+
+        let path = format!("/{}/{}", self.target.bucket, key)
+            .split('/')
+            .map(uri_encode)
+            .collect::<Vec<_>>()
+            .join("/");
+        let host = self
+            .endpoint
+            .split_once("://")
+            .map_or(self.endpoint.as_str(), |(_, h)| h);
+        let (date, timestamp) = utc_timestamp(SystemTime::now());
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+
+        let mut headers = vec![
+            ("host", host.to_string()),
+            ("x-amz-content-sha256", "UNSIGNED-PAYLOAD".to_string()),
+            ("x-amz-date", timestamp.clone()),
+        ];
+
+        if let Some(token) = self.session_token.as_ref() {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_request = format!(
+            "PUT\n{}\n\n{}\n{}\nUNSIGNED-PAYLOAD",
+            path,
+            headers
+                .iter()
+                .map(|(name, value)| format!("{}:{}\n", name, value))
+                .collect::<String>(),
+            signed_headers
+        );
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            timestamp,
+            scope,
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let key = [date.as_str(), self.region.as_str(), "s3", "aws4_request"]
+            .iter()
+            .fold(format!("AWS4{}", self.secret_key).into_bytes(), |k, d| {
+                hmac(&k, d)
+            });
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key,
+            scope,
+            signed_headers,
+            hex(&hmac(&key, &string_to_sign))
+        );
+
+        let mut request = ureq::put(&format!("{}{}", self.endpoint, path))
+            .set("Authorization", &authorization)
+            .set("Content-Length", &length.to_string());
+
+        for (name, value) in headers.iter().filter(|(name, _)| *name != "host") {
+            request = request.set(name, value);
+        }
+
+        request.send(fh).map(|_| ()).map_err(|e| e.to_string())
+    }
+
+    #[cfg(not(feature = "s3"))]
+    pub fn upload(&self, _file: &Path, _key: &str) -> Result<(), String> {
+        Err("romcomp was built without S3 support".to_string())
+    }
+}
+
+/// percent-encodes a path segment as required by the AWS signature
+#[cfg(feature = "s3")]
+fn uri_encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// formats a point in time as the date (YYYYMMDD) and timestamp (YYYYMMDDTHHMMSSZ) used by the AWS signature
+#[cfg(feature = "s3")]
+fn utc_timestamp(time: std::time::SystemTime) -> (String, String) {
+    let seconds = time
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let (days, seconds) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+
+    // converts days since the unix epoch into a civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    let date = format!("{:04}{:02}{:02}", year, month, day);
+    let timestamp = format!(
+        "{}T{:02}{:02}{:02}Z",
+        date,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    );
+
+    (date, timestamp)
+}
@@ -0,0 +1,73 @@
+use humansize::{format_size, DECIMAL};
+use romcomp::RomFormat;
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// a recognized file found by the scan command
+#[derive(Serialize)]
+pub struct ScanEntry {
+    pub file: PathBuf,
+    /// the file format and console flags romcomp detects
+    pub detected: Vec<String>,
+    /// the console the file would be converted as, None if the auto format can't determine it
+    pub format: Option<String>,
+    /// the output the file would be converted into, next to the input
+    pub output: Option<PathBuf>,
+    /// the size of all files read by the conversion, e.g. the cue sheet and its tracks
+    pub size: u64,
+    pub output_exists: bool,
+}
+
+/// the names of the set flags, e.g. ISO and PlayStation2
+pub fn flag_names(format: RomFormat) -> Vec<String> {
+    format.iter_names().map(|(n, _)| n.to_string()).collect()
+}
+
+/// prints the scanned files as a table, one file per line
+pub fn print_scan(entries: &[ScanEntry]) {
+    let rows = entries
+        .iter()
+        .map(|e| {
+            [
+                e.file.display().to_string(),
+                e.detected.join(" | "),
+                e.format.clone().unwrap_or_else(|| "-".to_string()),
+                e.output
+                    .as_ref()
+                    .map(|o| o.display().to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+                format_size(e.size, DECIMAL),
+                if e.output_exists { "yes" } else { "no" }.to_string(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let header = ["File", "Detected", "Format", "Output", "Size", "Exists"].map(String::from);
+
+    let mut widths = header.clone().map(|h| h.chars().count());
+
+    for row in rows.iter() {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    for row in [header].iter().chain(rows.iter()) {
+        let line = row
+            .iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+
+        println!("{}", line.trim_end());
+    }
+
+    let converted = entries.iter().filter(|e| e.format.is_some()).count();
+
+    println!(
+        "{} files recognized, {} of them would be converted, {} outputs exist already",
+        entries.len(),
+        converted,
+        entries.iter().filter(|e| e.output_exists).count()
+    );
+}
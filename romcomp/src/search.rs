@@ -1,6 +1,457 @@
-use crate::rom_format::RomFormat;
-use cue::cd::CD;
-use std::path::PathBuf;
+use crate::{
+    cso::{CSO_MAGIC, DAX_MAGIC},
+    gdi::{gdi_files, parse_gdi},
+    n64::ByteOrder,
+    playlist::read_m3u,
+    rom_format::RomFormat,
+};
+use cue::{cd::CD, track::TrackMode};
+use globset::{Error, GlobBuilder, GlobSet, GlobSetBuilder};
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+/// names of files and directories which never contain roms,
+/// like hidden files and metadata created by operating systems
+const JUNK_PATTERNS: &[&str] = &[".*", "Thumbs.db", "desktop.ini"];
+
+/// builds a matcher for file and directory names which should be ignored,
+/// consisting of the built-in junk patterns and the given additional patterns
+pub fn junk_matcher(additional: &[String]) -> Result<GlobSet, Error> {
+    let mut builder = GlobSetBuilder::new();
+
+    for pattern in JUNK_PATTERNS
+        .iter()
+        .copied()
+        .chain(additional.iter().map(|p| p.as_str()))
+    {
+        builder.add(GlobBuilder::new(pattern).case_insensitive(true).build()?);
+    }
+
+    builder.build()
+}
+
+/// builds a matcher for the paths given to --include or --exclude, which are relative to their location
+pub fn path_matcher(patterns: &[String]) -> Result<GlobSet, Error> {
+    let mut builder = GlobSetBuilder::new();
+
+    for pattern in patterns.iter() {
+        builder.add(GlobBuilder::new(pattern).case_insensitive(true).build()?);
+    }
+
+    builder.build()
+}
+
+/// sector size of ISO 9660 images
+const ISO_SECTOR_SIZE: u64 = 2048;
+/// the primary volume descriptor is located in sector 16,
+/// so any complete image must at least contain 17 sectors
+const ISO_MIN_SIZE: u64 = ISO_SECTOR_SIZE * 17;
+
+/// reads the given amount of bytes at the given offset of a file, or None if the file is too short
+pub fn read_at(path: &Path, offset: u64, length: usize) -> Option<Vec<u8>> {
+    let mut buf = vec![0_u8; length];
+
+    File::open(path)
+        .and_then(|mut fh| {
+            fh.seek(SeekFrom::Start(offset))?;
+            fh.read_exact(&mut buf)
+        })
+        .ok()
+        .map(|_| buf)
+}
+
+/// checks whether a file contains the given bytes at the given offset
+pub fn has_magic(path: &Path, offset: u64, magic: &[u8]) -> bool {
+    read_at(path, offset, magic.len()).is_some_and(|buf| buf == magic)
+}
+
+/// returns the additional parts of a split wbfs image (.wbf1, .wbf2, ...) which exist next to it
+pub fn wbfs_parts(path: &Path) -> Vec<PathBuf> {
+    (1..10)
+        .map(|i| path.with_extension(format!("wbf{}", i)))
+        .take_while(|p| p.is_file())
+        .collect()
+}
+
+/// returns the other cue sheet of the same disc, game.cue.txt for game.cue and vice versa, if it exists
+pub fn cue_twin(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_str()?;
+    let lower = name.to_lowercase();
+
+    let twin = if lower.ends_with(".cue.txt") {
+        path.with_file_name(&name[..name.len() - 4])
+    } else if lower.ends_with(".cue") {
+        path.with_file_name(format!("{}.txt", name))
+    } else {
+        return None;
+    };
+
+    twin.is_file().then_some(twin)
+}
+
+/// the game.cue which gets converted instead of a game.cue.txt, if it exists and is a valid cue sheet.
+/// both describe the same disc, and converting game.cue.txt would overwrite game.cue with a temporary copy
+pub fn converted_twin(path: &Path) -> Option<PathBuf> {
+    let is_txt = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("txt"));
+
+    cue_twin(path).filter(|twin| is_txt && guess_file(twin).is_some())
+}
+
+/// the most specific of the roots the file is located in
+pub fn root_of<'a>(file: &Path, roots: &'a [PathBuf]) -> Option<&'a PathBuf> {
+    roots
+        .iter()
+        .filter(|r| file.starts_with(r))
+        .max_by_key(|r| r.components().count())
+}
+
+/// checks whether an iso file is obviously incomplete,
+/// e.g. due to an interrupted download or a failed dump
+pub fn iso_looks_truncated(path: &Path) -> bool {
+    path.metadata()
+        .map(|m| m.len() < ISO_MIN_SIZE || m.len() % ISO_SECTOR_SIZE != 0)
+        .unwrap_or(true)
+}
+
+/// the largest CD-ROM, 99 minutes of 2048 byte sectors. larger images can only be DVDs
+const CD_MAX_SIZE: u64 = 99 * 60 * 75 * ISO_SECTOR_SIZE;
+/// the volume recognition sequence follows the primary volume descriptor
+const VOLUME_DESCRIPTOR_SECTORS: u64 = 16;
+
+/// checks whether an iso file contains a DVD instead of a CD, which chdman has to convert differently.
+/// small DVDs are recognized by the UDF file system every PS2 DVD contains in addition to ISO 9660
+pub fn is_dvd_image(path: &Path) -> bool {
+    path.metadata().is_ok_and(|m| m.len() > CD_MAX_SIZE)
+        || (0..VOLUME_DESCRIPTOR_SECTORS).any(|i| {
+            read_at(path, ISO_PVD_OFFSET + i * ISO_SECTOR_SIZE + 1, 5)
+                .is_some_and(|id| id == b"NSR02" || id == b"NSR03")
+        })
+}
+
+/// every raw CD sector starts with this sync pattern, followed by the address and the mode byte
+const CD_SYNC_PATTERN: [u8; 12] = [
+    0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00,
+];
+
+/// the mode of the first sector of a raw CD image, or None if it doesn't start with a raw data sector
+pub fn sector_mode(bin: &Path) -> Option<u8> {
+    read_at(bin, 0, 16)
+        .filter(|header| header[..12] == CD_SYNC_PATTERN)
+        .map(|header| header[15])
+}
+
+/// compares the mode of the raw data tracks declared in a cue sheet with the mode of their first sector.
+/// returns the file, the declared mode and the actual mode of every mismatching track
+pub fn sector_mode_mismatches(cue: &Path) -> Vec<(String, u8, u8)> {
+    let Ok(cd) = CD::parse_file(cue.to_path_buf()) else {
+        return vec![];
+    };
+    let mut checked: Vec<String> = vec![];
+    let mut mismatches = vec![];
+
+    for track in cd.tracks() {
+        let file = track.get_filename();
+
+        // only the first track of a file is known to start at the beginning of it
+        if checked.contains(&file) {
+            continue;
+        }
+        checked.push(file.clone());
+
+        let declared = match track.get_mode() {
+            TrackMode::Mode1Raw => 1,
+            TrackMode::Mode2Raw => 2,
+            _ => continue,
+        };
+
+        let Some(actual) = sector_mode(&cue.parent().unwrap().join(&file)) else {
+            continue;
+        };
+
+        if actual != declared && (actual == 1 || actual == 2) {
+            mismatches.push((file, declared, actual));
+        }
+    }
+
+    mismatches
+}
+
+/// the primary volume descriptor of an ISO 9660 image is located in sector 16
+pub const ISO_PVD_OFFSET: u64 = 16 * ISO_SECTOR_SIZE;
+/// the magic words of the disc header, at offset 0x18 for Wii and 0x1C for GameCube discs
+pub const WII_MAGIC: [u8; 4] = [0x5D, 0x1C, 0x9E, 0xA3];
+pub const GAMECUBE_MAGIC: [u8; 4] = [0xC2, 0x33, 0x9F, 0x3D];
+/// the CRC-16 of the Nintendo logo, which every nds rom header contains at 0x15C
+const NDS_LOGO_CRC: [u8; 2] = [0x56, 0xCF];
+const NDS_LOGO_CRC_OFFSET: u64 = 0x15C;
+/// the CRC-16 of the header up to 0x15E follows the logo CRC
+const NDS_HEADER_CRC_OFFSET: usize = 0x15E;
+
+/// the CRC-16/MODBUS used by the nds rom header
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0xFFFF, |crc, byte| {
+        (0..8).fold(crc ^ u16::from(*byte), |crc, _| {
+            if crc & 1 == 1 {
+                (crc >> 1) ^ 0xA001
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+/// whether the file starts with an nds rom header. two bytes alone match too many other files,
+/// so the CRC of the whole header has to match as well
+fn is_nds_rom(path: &Path) -> bool {
+    read_at(path, 0, NDS_HEADER_CRC_OFFSET + 2).is_some_and(|header| {
+        header[NDS_LOGO_CRC_OFFSET as usize..NDS_HEADER_CRC_OFFSET] == NDS_LOGO_CRC
+            && crc16(&header[..NDS_HEADER_CRC_OFFSET]).to_le_bytes()
+                == header[NDS_HEADER_CRC_OFFSET..]
+    })
+}
+
+/// where the user data of a sector is located, within 2048 byte iso sectors or raw 2352 byte CD sectors
+#[derive(Clone, Copy)]
+struct SectorLayout {
+    size: u64,
+    data_offset: u64,
+}
+
+impl SectorLayout {
+    const ISO: SectorLayout = SectorLayout {
+        size: ISO_SECTOR_SIZE,
+        data_offset: 0,
+    };
+
+    /// the layout of an image by the header of its first sector, cooked images have no sync pattern
+    fn detect(path: &Path) -> SectorLayout {
+        match sector_mode(path) {
+            Some(1) => SectorLayout {
+                size: 2352,
+                data_offset: 16,
+            },
+            Some(2) => SectorLayout {
+                size: 2352,
+                data_offset: 24,
+            },
+            _ => SectorLayout::ISO,
+        }
+    }
+
+    fn read(&self, path: &Path, lba: u64, length: usize) -> Option<Vec<u8>> {
+        read_at(path, lba * self.size + self.data_offset, length)
+    }
+}
+
+/// the entries of the root directory of an ISO 9660 file system, their name, first sector, size and whether
+/// they are a directory. only the first sectors of large directories are read
+fn iso_root_entries(path: &Path, layout: SectorLayout) -> Vec<(String, u64, u64, bool)> {
+    let Some(pvd) = layout
+        .read(path, VOLUME_DESCRIPTOR_SECTORS, ISO_SECTOR_SIZE as usize)
+        .filter(|pvd| pvd[1..6] == *b"CD001")
+    else {
+        return vec![];
+    };
+    let root = &pvd[156..190];
+    let extent = u32::from_le_bytes(root[2..6].try_into().unwrap()) as u64;
+    let size = u32::from_le_bytes(root[10..14].try_into().unwrap()) as u64;
+    let mut entries = vec![];
+
+    for lba in extent..extent + size.div_ceil(ISO_SECTOR_SIZE).min(16) {
+        let Some(sector) = layout.read(path, lba, ISO_SECTOR_SIZE as usize) else {
+            break;
+        };
+        let mut offset = 0;
+
+        // records never cross sectors, the rest of a sector is zeroed
+        while offset + 33 < sector.len() && sector[offset] != 0 {
+            let record = &sector[offset..offset + sector[offset] as usize];
+            let name_length = record[32] as usize;
+
+            if let Some(name) = record.get(33..33 + name_length) {
+                entries.push((
+                    String::from_utf8_lossy(name)
+                        .trim_end_matches(";1")
+                        .to_uppercase(),
+                    u32::from_le_bytes(record[2..6].try_into().unwrap()) as u64,
+                    u32::from_le_bytes(record[10..14].try_into().unwrap()) as u64,
+                    record[25] & 0b10 != 0,
+                ));
+            }
+
+            offset += record.len();
+        }
+    }
+
+    entries
+}
+
+/// the consoles the content of a disc image points to, for images whose extension fits several consoles.
+/// checks the Wii disc header, the system identifier and the root directory of the ISO 9660 file system,
+/// where PSP and PS3 discs have a PSP_GAME or PS3_GAME directory and the SYSTEM.CNF of PlayStation discs boots either
+/// a PS2 (BOOT2) or a PS1 (BOOT) executable. None if nothing points to a console
+pub fn content_formats(path: &Path) -> Option<RomFormat> {
+    let name = path.file_name()?.to_str()?.to_lowercase();
+
+    // a cue sheet boots from the file of its first track
+    let (image, layout) = if name.ends_with(".cue") || name.ends_with(".cue.txt") {
+        let cd = CD::parse_file(path.to_path_buf()).ok()?;
+        let image = path.parent()?.join(cd.tracks().first()?.get_filename());
+        let layout = SectorLayout::detect(&image);
+
+        (image, layout)
+    } else {
+        if has_magic(path, 0x18, &WII_MAGIC) {
+            return Some(RomFormat::NintendoWii);
+        }
+
+        (path.to_path_buf(), SectorLayout::ISO)
+    };
+
+    let system_id = layout
+        .read(&image, VOLUME_DESCRIPTOR_SECTORS, 40)
+        .filter(|pvd| pvd[1..6] == *b"CD001")?;
+    let entries = iso_root_entries(&image, layout);
+
+    if system_id[8..16] == *b"PSP GAME" || entries.iter().any(|(n, _, _, d)| *d && n == "PSP_GAME")
+    {
+        return Some(RomFormat::PlayStationPortable);
+    }
+
+    // every PS3 disc identifies itself with PS3_DISC.SFB next to the PS3_GAME directory holding PARAM.SFO
+    if entries
+        .iter()
+        .any(|(n, _, _, d)| (!*d && n == "PS3_DISC.SFB") || (*d && n == "PS3_GAME"))
+    {
+        return Some(RomFormat::PlayStation3);
+    }
+
+    let boot = entries
+        .iter()
+        .find(|(n, _, _, d)| !*d && n == "SYSTEM.CNF")
+        .and_then(|(_, lba, size, _)| {
+            layout.read(&image, *lba, (*size).min(ISO_SECTOR_SIZE) as usize)
+        })
+        .map(|cnf| String::from_utf8_lossy(&cnf).to_uppercase());
+
+    // Wii discs don't contain an ISO 9660 file system, and PS1 discs are never DVDs
+    Some(match boot {
+        Some(cnf) if cnf.contains("BOOT2") => RomFormat::PlayStation2,
+        Some(cnf) if cnf.contains("BOOT") => RomFormat::PlayStationX,
+        _ if layout.size == ISO_SECTOR_SIZE && is_dvd_image(&image) => {
+            RomFormat::PlayStation2 | RomFormat::PlayStationPortable | RomFormat::PlayStation3
+        }
+        _ => {
+            RomFormat::PlayStationX
+                | RomFormat::PlayStation2
+                | RomFormat::PlayStationPortable
+                | RomFormat::PlayStation3
+        }
+    })
+}
+
+/// guesses the format of a file by the signatures in its header, for roms with an unrecognized extension
+fn guess_by_magic(path: &Path) -> Option<RomFormat> {
+    if let Some(order) = read_at(path, 0, 4).and_then(|m| ByteOrder::detect(&m.try_into().unwrap()))
+    {
+        return Some(
+            match order {
+                ByteOrder::BigEndian => RomFormat::Z64,
+                ByteOrder::ByteSwapped => RomFormat::V64,
+                ByteOrder::LittleEndian => RomFormat::N64,
+            } | RomFormat::Nintendo64,
+        );
+    }
+
+    if let Some(header) = read_at(path, 0, 0x20) {
+        if header[0x18..0x1C] == WII_MAGIC {
+            return Some(RomFormat::ISO | RomFormat::NintendoWii);
+        }
+
+        if header[0x1C..0x20] == GAMECUBE_MAGIC {
+            return Some(RomFormat::ISO | RomFormat::NintendoGameCube);
+        }
+    }
+
+    if let Some(pvd) = read_at(path, ISO_PVD_OFFSET, 40).filter(|pvd| pvd[1..6] == *b"CD001") {
+        return Some(if pvd[8..16] == *b"PSP GAME" {
+            RomFormat::ISO | RomFormat::PlayStationPortable
+        } else {
+            RomFormat::ISO
+                | RomFormat::PlayStationX
+                | RomFormat::PlayStation2
+                | RomFormat::PlayStation3
+        });
+    }
+
+    if is_nds_rom(path) {
+        return Some(RomFormat::NDS | RomFormat::NintendoDS);
+    }
+
+    None
+}
+
+/// whether a cue sheet or gdi file next to the file lists it as one of its tracks.
+/// data tracks look like iso images, but only get converted together with their disc
+fn is_track_file(path: &Path) -> bool {
+    let Ok(entries) = path.parent().unwrap().read_dir() else {
+        return false;
+    };
+
+    entries.flatten().map(|e| e.path()).any(|sheet| {
+        let name = sheet
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default()
+            .to_lowercase();
+
+        if name.ends_with(".cue") || name.ends_with(".cue.txt") {
+            CD::parse_file(sheet.clone()).is_ok_and(|cd| {
+                cd.tracks()
+                    .iter()
+                    .any(|t| sheet.parent().unwrap().join(t.get_filename()) == path)
+            })
+        } else if name.ends_with(".gdi") {
+            gdi_files(&sheet).iter().any(|f| f == path)
+        } else {
+            false
+        }
+    })
+}
+
+/// the track files referenced by a cue sheet which don't exist, which keep guess_file from recognizing it.
+/// empty for anything but a readable cue sheet
+pub fn missing_tracks(path: &Path) -> Vec<PathBuf> {
+    let name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+
+    if !name.ends_with(".cue") && !name.ends_with(".cue.txt") {
+        return vec![];
+    }
+
+    let Ok(cd) = CD::parse_file(path.to_path_buf()) else {
+        return vec![];
+    };
+    let mut missing = vec![];
+
+    for track in cd.tracks() {
+        let file = path.parent().unwrap().join(track.get_filename());
+
+        if !file.is_file() && !missing.contains(&file) {
+            missing.push(file);
+        }
+    }
+
+    missing
+}
 
 pub fn guess_file(path: &PathBuf) -> Option<RomFormat> {
     path.file_name().and_then(|e| {
@@ -18,11 +469,26 @@ pub fn guess_file(path: &PathBuf) -> Option<RomFormat> {
                         None
                     }
                 })
+            } else if path.is_file()
+                && (e.to_lowercase().ends_with(".iso") || e.to_lowercase().ends_with(".gcm"))
+                && has_magic(path, 0x1C, &GAMECUBE_MAGIC)
+            {
+                // unlike the other consoles, GameCube discs can be told apart by their header
+                Some(RomFormat::ISO | RomFormat::NintendoGameCube)
+            } else if path.is_file()
+                && [".cso", ".dax", ".iso"]
+                    .iter()
+                    .any(|x| e.to_lowercase().ends_with(x))
+                && (has_magic(path, 0, CSO_MAGIC) || has_magic(path, 0, DAX_MAGIC))
+            {
+                // PSP images which are compressed already, sometimes with the extension of the uncompressed image
+                Some(RomFormat::CSO | RomFormat::PlayStationPortable)
             } else if path.is_file() && e.to_lowercase().ends_with(".iso") {
                 Some(
                     RomFormat::PlayStationX
                         | RomFormat::PlayStation2
                         | RomFormat::PlayStationPortable
+                        | RomFormat::PlayStation3
                         | RomFormat::NintendoWii
                         | RomFormat::ISO,
                 )
@@ -32,8 +498,48 @@ pub fn guess_file(path: &PathBuf) -> Option<RomFormat> {
                 Some(RomFormat::V64 | RomFormat::Nintendo64)
             } else if path.is_file() && e.to_lowercase().ends_with(".z64") {
                 Some(RomFormat::Z64 | RomFormat::Nintendo64)
+            } else if path.is_file() && e.to_lowercase().ends_with(".ndd") {
+                Some(RomFormat::NDD | RomFormat::Nintendo64)
             } else if path.is_file() && e.to_lowercase().ends_with(".nds") {
                 Some(RomFormat::NDS | RomFormat::NintendoDS)
+            } else if path.is_file()
+                && e.to_lowercase().ends_with(".wbfs")
+                && has_magic(path, 0, b"WBFS")
+            {
+                Some(RomFormat::WBFS | RomFormat::NintendoWii)
+            } else if path.is_file() && e.to_lowercase().ends_with(".gdi") {
+                parse_gdi(path)
+                    .ok()
+                    .map(|_| RomFormat::GDI | RomFormat::Dreamcast)
+            } else if path.is_file()
+                && e.to_lowercase().ends_with(".zip")
+                && has_magic(path, 0, b"PK\x03\x04")
+            {
+                Some(RomFormat::ZIP | RomFormat::Arcade)
+            } else if path.is_file() && e.to_lowercase().ends_with(".m3u") {
+                // a playlist can only be converted into rom formats all of its entries support.
+                // playlists within playlists aren't supported, a playlist listing itself would never end
+                read_m3u(path).and_then(|entries| {
+                    entries
+                        .iter()
+                        .map(|entry| {
+                            if entry
+                                .extension()
+                                .is_some_and(|e| e.eq_ignore_ascii_case("m3u"))
+                            {
+                                return None;
+                            }
+
+                            guess_file(entry).map(|f| f & !RomFormat::FILE_FORMATS)
+                        })
+                        .reduce(|a, b| a.zip(b).map(|(a, b)| a & b))
+                        .flatten()
+                        .filter(|f| !f.is_empty())
+                        .map(|f| f | RomFormat::M3U)
+                })
+            } else if path.is_file() {
+                // the signatures are only checked once the extension isn't recognized, since that is much cheaper
+                guess_by_magic(path).filter(|_| !is_track_file(path))
             } else {
                 None
             }
@@ -42,3 +548,85 @@ pub fn guess_file(path: &PathBuf) -> Option<RomFormat> {
         }
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::write;
+
+    /// a header with the logo CRC and, if wanted, a matching header CRC
+    fn nds_header(header_crc: bool) -> Vec<u8> {
+        let mut header = vec![0; 0x200];
+        header[..12].copy_from_slice(b"HOMEBREW    ");
+        header[NDS_LOGO_CRC_OFFSET as usize..NDS_HEADER_CRC_OFFSET].copy_from_slice(&NDS_LOGO_CRC);
+
+        if header_crc {
+            let crc = crc16(&header[..NDS_HEADER_CRC_OFFSET]).to_le_bytes();
+            header[NDS_HEADER_CRC_OFFSET..NDS_HEADER_CRC_OFFSET + 2].copy_from_slice(&crc);
+        }
+
+        header
+    }
+
+    #[test]
+    fn crc16_matches_the_modbus_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x4B37);
+    }
+
+    #[test]
+    fn nds_roms_are_detected_by_their_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let rom = dir.path().join("game.bin");
+        write(&rom, nds_header(true)).unwrap();
+
+        assert_eq!(
+            guess_by_magic(&rom),
+            Some(RomFormat::NDS | RomFormat::NintendoDS)
+        );
+    }
+
+    #[test]
+    fn logo_crc_alone_isnt_an_nds_rom() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("data.bin");
+        write(&file, nds_header(false)).unwrap();
+
+        assert_eq!(guess_by_magic(&file), None);
+    }
+
+    /// writes a cue sheet of a single track and its bin file
+    fn cue(path: &Path) {
+        write(
+            path,
+            "FILE \"game.bin\" BINARY\n  TRACK 01 MODE2/2352\n    INDEX 01 00:00:00\n",
+        )
+        .unwrap();
+        write(path.with_file_name("game.bin"), vec![0; 2352 * 16]).unwrap();
+    }
+
+    #[test]
+    fn cue_is_converted_instead_of_its_txt_twin() {
+        let dir = tempfile::tempdir().unwrap();
+        let cue_file = dir.path().join("game.cue");
+        let txt = dir.path().join("game.cue.txt");
+        cue(&cue_file);
+        cue(&txt);
+
+        assert_eq!(converted_twin(&txt), Some(cue_file.clone()));
+        assert_eq!(converted_twin(&cue_file), None);
+    }
+
+    #[test]
+    fn txt_twin_is_converted_without_a_valid_cue() {
+        let dir = tempfile::tempdir().unwrap();
+        let txt = dir.path().join("Game.CUE.TXT");
+        cue(&txt);
+
+        assert_eq!(converted_twin(&txt), None);
+
+        // a game.cue which isn't a cue sheet doesn't replace the game.cue.txt
+        write(dir.path().join("Game.CUE"), b"not a cue sheet").unwrap();
+
+        assert_eq!(converted_twin(&txt), None);
+    }
+}
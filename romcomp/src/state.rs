@@ -0,0 +1,119 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{read_to_string, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+/// an input which was converted successfully, one JSON object per line of the state file
+#[derive(Deserialize, Serialize)]
+struct StateEntry {
+    #[serde(flatten)]
+    stamp: InputStamp,
+    /// where the output ended up, e.g. after --flatten moved it
+    output: PathBuf,
+}
+
+/// identifies the contents of an input without reading it
+#[derive(Clone, Deserialize, Eq, PartialEq, Serialize)]
+pub struct InputStamp {
+    /// the absolute path, so the locations may be given differently when resuming
+    input: PathBuf,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+impl InputStamp {
+    /// has to be taken before the input could have been removed
+    pub fn of(file: &Path) -> io::Result<Self> {
+        let metadata = file.metadata()?;
+
+        Ok(Self {
+            input: file.canonicalize()?,
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+/// the inputs converted by earlier runs with the same state file, see --state.
+/// entries are appended as soon as a conversion finishes, so an interrupted run loses none of them
+pub struct StateFile {
+    path: PathBuf,
+    completed: HashMap<PathBuf, InputStamp>,
+    file: Mutex<File>,
+}
+
+impl StateFile {
+    /// loads the entries of the state file, it is created if it doesn't exist yet
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut completed = HashMap::new();
+        let contents = if path.is_file() {
+            read_to_string(path)?
+        } else {
+            String::new()
+        };
+
+        for (i, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            // the last line is cut off if romcomp was killed while writing it
+            match serde_json::from_str::<StateEntry>(line) {
+                Ok(entry) => {
+                    completed.insert(entry.stamp.input.clone(), entry.stamp);
+                }
+                Err(e) => warn!(
+                    "Ignoring line {} of the state file {}: {}",
+                    i + 1,
+                    path.display(),
+                    e
+                ),
+            }
+        }
+
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        // the first new entry mustn't end up on the line of a cut off one
+        if !contents.is_empty() && !contents.ends_with('\n') {
+            file.write_all(b"\n")?;
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            completed,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// whether the input was converted by an earlier run and wasn't changed since
+    pub fn is_completed(&self, stamp: &InputStamp) -> bool {
+        self.completed.get(&stamp.input) == Some(stamp)
+    }
+
+    /// records a finished conversion
+    pub fn complete(&self, stamp: InputStamp, output: &Path) {
+        let entry = StateEntry {
+            stamp,
+            output: output.to_path_buf(),
+        };
+
+        let result = serde_json::to_string(&entry)
+            .map_err(io::Error::from)
+            .and_then(|line| {
+                self.file
+                    .lock()
+                    .unwrap()
+                    .write_all(format!("{}\n", line).as_bytes())
+            });
+
+        if let Err(e) = result {
+            warn!("Error writing state file {}: {}", self.path.display(), e);
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use crate::convert::SkipReason;
+use serde_json::json;
+use std::{
+    fs::{rename, write},
+    io,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+/// the progress of a run, written as JSON for external monitoring
+pub struct StatusFile {
+    pub path: PathBuf,
+    pub total_files: usize,
+    pub start: Instant,
+    pub processed_files: Arc<AtomicUsize>,
+    pub failed_files: Arc<Mutex<Vec<(PathBuf, String)>>>,
+    pub skipped_files: Arc<Mutex<Vec<(PathBuf, SkipReason)>>>,
+    pub input_file_size: Arc<AtomicUsize>,
+    pub output_file_size: Arc<AtomicUsize>,
+    pub active_files: Arc<Mutex<Vec<PathBuf>>>,
+}
+
+impl StatusFile {
+    /// replaces the status file, readers will never see a partially written file
+    pub fn write(&self, finished: bool) -> io::Result<()> {
+        let processed = self.processed_files.load(Ordering::Relaxed);
+        let failed = self.failed_files.lock().unwrap().len();
+        let skipped = self.skipped_files.lock().unwrap().len();
+        let done = processed + failed + skipped;
+        let elapsed = self.start.elapsed().as_secs_f64();
+
+        let eta = (done > 0 && !finished)
+            .then(|| elapsed / done as f64 * self.total_files.saturating_sub(done) as f64);
+
+        let status = json!({
+            "finished": finished,
+            "files_total": self.total_files,
+            "files_done": done,
+            "files_processed": processed,
+            "files_skipped": skipped,
+            "files_failed": failed,
+            "input_bytes": self.input_file_size.load(Ordering::Relaxed),
+            "output_bytes": self.output_file_size.load(Ordering::Relaxed),
+            "current_files": self
+                .active_files
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|f| f.to_str().unwrap().to_string())
+                .collect::<Vec<_>>(),
+            "elapsed_seconds": elapsed.round() as u64,
+            "eta_seconds": eta.map(|e| e.round() as u64),
+        });
+
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+
+        write(&temp, serde_json::to_string_pretty(&status)?)?;
+        rename(&temp, &self.path)
+    }
+}
@@ -0,0 +1,151 @@
+use crate::convert::{ConversionRecord, SkipReason};
+use gag::Gag;
+use humansize::{format_size, DECIMAL};
+use ratatui::{
+    backend::CrosstermBackend,
+    crossterm::{
+        cursor::{Hide, Show},
+        execute,
+        terminal::{EnterAlternateScreen, LeaveAlternateScreen},
+    },
+    layout::{Constraint, Layout},
+    widgets::{Block, Gauge, List, Paragraph},
+    Frame, Terminal,
+};
+use std::{
+    io::{self, stderr, Stderr},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// the amount of finished and failed files shown
+const RECENT_FILES: usize = 10;
+
+/// a full-screen view of a run, replacing the line-based output while it is running
+pub struct Tui {
+    pub total_files: usize,
+    pub start: Instant,
+    pub processed_files: Arc<AtomicUsize>,
+    pub failed_files: Arc<Mutex<Vec<(PathBuf, String)>>>,
+    pub skipped_files: Arc<Mutex<Vec<(PathBuf, SkipReason)>>>,
+    pub input_file_size: Arc<AtomicUsize>,
+    pub output_file_size: Arc<AtomicUsize>,
+    pub active_files: Arc<Mutex<Vec<PathBuf>>>,
+    pub records: Arc<Mutex<Vec<ConversionRecord>>>,
+}
+
+impl Tui {
+    /// draws the view until the run stops, ctrl+c keeps interrupting the run as usual.
+    /// everything printed to stdout in the meantime gets discarded
+    pub fn run(&self, running: &AtomicBool) -> io::Result<()> {
+        let _gag = Gag::stdout()?;
+        let mut terminal = Terminal::new(CrosstermBackend::new(stderr()))?;
+
+        execute!(terminal.backend_mut(), EnterAlternateScreen, Hide)?;
+
+        let result = self.draw_until_stopped(&mut terminal, running);
+
+        execute!(terminal.backend_mut(), LeaveAlternateScreen, Show)?;
+
+        result
+    }
+
+    fn draw_until_stopped(
+        &self,
+        terminal: &mut Terminal<CrosstermBackend<Stderr>>,
+        running: &AtomicBool,
+    ) -> io::Result<()> {
+        while running.load(Ordering::Relaxed) {
+            terminal.draw(|frame| self.draw(frame))?;
+            std::thread::sleep(Duration::from_millis(250));
+        }
+
+        Ok(())
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let processed = self.processed_files.load(Ordering::Relaxed);
+        let failed_files = self.failed_files.lock().unwrap();
+        let skipped = self.skipped_files.lock().unwrap().len();
+        let done = processed + failed_files.len() + skipped;
+        let is = self.input_file_size.load(Ordering::Relaxed);
+        let os = self.output_file_size.load(Ordering::Relaxed);
+        let active_files = self.active_files.lock().unwrap();
+        let records = self.records.lock().unwrap();
+
+        let [progress, stats, active, recent] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Length(4),
+            Constraint::Min(3),
+            Constraint::Length(RECENT_FILES as u16 + 2),
+        ])
+        .areas(frame.area());
+        let [finished, failed] =
+            Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+                .areas(recent);
+
+        frame.render_widget(
+            Gauge::default()
+                .block(Block::bordered().title("romcomp"))
+                .ratio((done as f64 / self.total_files.max(1) as f64).min(1.0))
+                .label(format!("{} / {} files", done, self.total_files)),
+            progress,
+        );
+
+        frame.render_widget(
+            Paragraph::new(vec![
+                format!(
+                    "Processed: {}, Skipped: {}, Failed: {}, Elapsed: {}s",
+                    processed,
+                    skipped,
+                    failed_files.len(),
+                    self.start.elapsed().as_secs()
+                )
+                .into(),
+                format!(
+                    "Input: {}, Output: {}, Saved: {}",
+                    format_size(is, DECIMAL),
+                    format_size(os, DECIMAL),
+                    format_size(is.saturating_sub(os), DECIMAL)
+                )
+                .into(),
+            ])
+            .block(Block::bordered().title("Statistics")),
+            stats,
+        );
+
+        frame.render_widget(
+            List::new(active_files.iter().map(|f| f.display().to_string()))
+                .block(Block::bordered().title(format!("Active ({})", active_files.len()))),
+            active,
+        );
+
+        frame.render_widget(
+            List::new(
+                records
+                    .iter()
+                    .rev()
+                    .take(RECENT_FILES)
+                    .map(|r| r.output.display().to_string()),
+            )
+            .block(Block::bordered().title("Finished")),
+            finished,
+        );
+
+        frame.render_widget(
+            List::new(
+                failed_files
+                    .iter()
+                    .rev()
+                    .take(RECENT_FILES)
+                    .map(|(f, _)| f.display().to_string()),
+            )
+            .block(Block::bordered().title("Failed")),
+            failed,
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use crate::{
+    cso::{verify_cso, verify_cso_header, CsoFormat},
+    rom_format::{CompressionOptions, CompressionTool},
+};
+use crossbeam_channel::Receiver;
+use std::{fs::File, io::Read, path::Path};
+use zip::ZipArchive;
+
+/// reads every entry of a zip file, the zip crate checks the CRC of each one once it was read completely.
+/// returns whether the check got interrupted
+#[cfg_attr(not(feature = "encrypt"), allow(unused_variables))]
+fn verify_zip(
+    path: &Path,
+    options: &CompressionOptions,
+    interrupt: &Receiver<()>,
+) -> Result<bool, String> {
+    let mut zip =
+        ZipArchive::new(File::open(path).map_err(|e| e.to_string())?).map_err(|e| e.to_string())?;
+    let mut buffer = vec![0; 1024 * 1024];
+
+    for i in 0..zip.len() {
+        #[cfg(feature = "encrypt")]
+        let entry = match options.encrypt_password.as_deref() {
+            Some(password) => zip.by_index_decrypt(i, password.as_bytes()),
+            None => zip.by_index(i),
+        };
+        #[cfg(not(feature = "encrypt"))]
+        let entry = zip.by_index(i);
+        let mut entry = entry.map_err(|e| e.to_string())?;
+
+        loop {
+            if !interrupt.is_empty() {
+                return Ok(true);
+            }
+
+            match entry.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(_) => (),
+                Err(e) => return Err(format!("{}: {}", entry.name(), e)),
+            }
+        }
+    }
+
+    Ok(false)
+}
+
+/// checks that an output can be read back, so a corrupt output never replaces its inputs.
+/// chd and rvz files are checked by their tools, zip and CSO v1 files by decompressing them,
+/// of CSO v2, zso and dax files only the header is checked. other outputs aren't checked.
+/// returns whether the check got interrupted
+pub fn verify_output(
+    path: &Path,
+    options: &CompressionOptions,
+    interrupt: &Receiver<()>,
+) -> Result<bool, String> {
+    verify_replacement(path, path, options, interrupt)
+}
+
+/// checks an output which is written under a temporary name, like a recompressed set or image,
+/// as the kind of file it replaces once it passed the check
+pub fn verify_replacement(
+    path: &Path,
+    target: &Path,
+    options: &CompressionOptions,
+    interrupt: &Receiver<()>,
+) -> Result<bool, String> {
+    let extension = target
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    // the tools get the path as UTF-8 argument
+    let tool_args = || {
+        path.to_str()
+            .map(|p| vec!["verify".to_string(), "-i".to_string(), p.to_string()])
+            .ok_or_else(|| format!("{} isn't valid UTF-8", path.display()))
+    };
+
+    match extension.as_str() {
+        "chd" => CompressionTool::Chdman.run(
+            &options.binary(CompressionTool::Chdman),
+            &tool_args()?,
+            interrupt,
+        ),
+        "rvz" => CompressionTool::DolphinTool.run(
+            &options.binary(CompressionTool::DolphinTool),
+            &tool_args()?,
+            interrupt,
+        ),
+        "zip" => verify_zip(path, options, interrupt),
+        "cso" if options.cso_format == CsoFormat::Cso1 => {
+            verify_cso(path, interrupt).map_err(|e| e.to_string())
+        }
+        "cso" => verify_cso_header(path, CsoFormat::Cso2)
+            .map(|_| false)
+            .map_err(|e| e.to_string()),
+        "zso" => verify_cso_header(path, CsoFormat::Zso)
+            .map(|_| false)
+            .map_err(|e| e.to_string()),
+        "dax" => verify_cso_header(path, CsoFormat::Dax)
+            .map(|_| false)
+            .map_err(|e| e.to_string()),
+        _ => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cso::{CSO_MAGIC, DAX_MAGIC, ZSO_MAGIC};
+    use crossbeam_channel::unbounded;
+    use std::fs::write;
+
+    /// a header of 0x18 bytes with the given magic and the image size at the given offset
+    fn header(magic: &[u8; 4], size_offset: usize, size: u32) -> Vec<u8> {
+        let mut header = vec![0; 0x18];
+        header[..4].copy_from_slice(magic);
+        header[size_offset..size_offset + 4].copy_from_slice(&size.to_le_bytes());
+        header
+    }
+
+    fn verify(name: &str, contents: &[u8]) -> Result<bool, String> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(name);
+        write(&path, contents).unwrap();
+        let (_sender, interrupt) = unbounded();
+
+        verify_output(&path, &CompressionOptions::default(), &interrupt)
+    }
+
+    #[test]
+    fn zso_and_dax_headers_are_accepted() {
+        assert_eq!(verify("game.zso", &header(ZSO_MAGIC, 8, 2048)), Ok(false));
+        assert_eq!(verify("game.dax", &header(DAX_MAGIC, 4, 2048)), Ok(false));
+    }
+
+    #[test]
+    fn cso2_header_is_checked() {
+        let options = CompressionOptions {
+            cso_format: CsoFormat::Cso2,
+            ..Default::default()
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("game.cso");
+        let (_sender, interrupt) = unbounded();
+
+        let mut cso2 = header(CSO_MAGIC, 8, 2048);
+        cso2[20] = 2;
+        write(&path, &cso2).unwrap();
+        assert_eq!(verify_output(&path, &options, &interrupt), Ok(false));
+
+        // a CSO v1 header where a CSO v2 file was expected
+        write(&path, header(CSO_MAGIC, 8, 2048)).unwrap();
+        assert!(verify_output(&path, &options, &interrupt).is_err());
+
+        cso2[8..12].copy_from_slice(&0_u32.to_le_bytes());
+        write(&path, &cso2).unwrap();
+        assert!(verify_output(&path, &options, &interrupt).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_paths_fail_the_tool_verification() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let dir = tempfile::tempdir().unwrap();
+        let (_sender, interrupt) = unbounded();
+
+        for extension in ["chd", "rvz"] {
+            let path = dir
+                .path()
+                .join(OsStr::from_bytes(b"Pok\xe9mon"))
+                .with_extension(extension);
+            write(&path, b"output").unwrap();
+
+            assert!(
+                verify_output(&path, &CompressionOptions::default(), &interrupt)
+                    .unwrap_err()
+                    .ends_with("isn't valid UTF-8")
+            );
+        }
+    }
+
+    #[test]
+    fn wrong_magic_is_rejected() {
+        assert!(verify("game.zso", &header(DAX_MAGIC, 8, 2048)).is_err());
+        assert!(verify("game.dax", &header(ZSO_MAGIC, 4, 2048)).is_err());
+    }
+
+    #[test]
+    fn empty_or_truncated_images_are_rejected() {
+        assert!(verify("game.zso", &header(ZSO_MAGIC, 8, 0)).is_err());
+        assert!(verify("game.dax", &header(DAX_MAGIC, 4, 0)).is_err());
+        assert!(verify("game.zso", b"").is_err());
+        assert!(verify("game.dax", &DAX_MAGIC[..]).is_err());
+    }
+}
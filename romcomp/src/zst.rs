@@ -0,0 +1,170 @@
+use crossbeam_channel::Receiver;
+use std::{
+    fs::File,
+    io::{self, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+use zstd::bulk::{Compressor, Decompressor};
+
+/// the amount of uncompressed data in each frame if no frame size is given
+pub const DEFAULT_FRAME_SIZE: u32 = 1024 * 1024;
+
+/// the seek table is stored in a skippable frame, which regular zstd decoders ignore
+const SKIPPABLE_MAGIC: u32 = 0x184D2A5E;
+const SEEKABLE_MAGIC: u32 = 0x8F92EAB1;
+/// frame count (4 bytes), descriptor (1 byte) and magic (4 bytes) at the very end of the file
+const SEEK_TABLE_FOOTER_SIZE: u64 = 9;
+/// the descriptor bit signaling that every seek table entry contains a checksum
+const CHECKSUM_FLAG: u8 = 0x80;
+
+/// compresses a file into the zstd seekable format: independent zstd frames of the given uncompressed size,
+/// followed by a seek table which allows decompressing any part of the file without reading everything before it.
+/// returns whether the compression got interrupted
+pub fn compress_zstd_seekable(
+    input: &Path,
+    output: &Path,
+    frame_size: u32,
+    level: i32,
+    interrupt: &Receiver<()>,
+) -> io::Result<bool> {
+    let mut reader = File::open(input)?;
+    let mut writer = BufWriter::new(File::create(output)?);
+    let mut compressor = Compressor::new(level)?;
+    // every frame carries the checksum of its content, which zstd checks when decompressing it
+    compressor.include_checksum(true)?;
+    // compressed and decompressed size of every frame
+    let mut seek_table: Vec<(u32, u32)> = vec![];
+    let mut frame = Vec::with_capacity(frame_size as usize);
+
+    loop {
+        if !interrupt.is_empty() {
+            return Ok(true);
+        }
+
+        frame.clear();
+        (&mut reader)
+            .take(frame_size as u64)
+            .read_to_end(&mut frame)?;
+
+        if frame.is_empty() {
+            break;
+        }
+
+        let compressed = compressor.compress(&frame)?;
+        writer.write_all(&compressed)?;
+        seek_table.push((compressed.len() as u32, frame.len() as u32));
+    }
+
+    let table_size = seek_table.len() as u32 * 8 + SEEK_TABLE_FOOTER_SIZE as u32;
+
+    writer.write_all(&SKIPPABLE_MAGIC.to_le_bytes())?;
+    writer.write_all(&table_size.to_le_bytes())?;
+    for (compressed, decompressed) in seek_table.iter() {
+        writer.write_all(&compressed.to_le_bytes())?;
+        writer.write_all(&decompressed.to_le_bytes())?;
+    }
+    writer.write_all(&(seek_table.len() as u32).to_le_bytes())?;
+    writer.write_all(&[0])?;
+    writer.write_all(&SEEKABLE_MAGIC.to_le_bytes())?;
+    writer.flush()?;
+
+    Ok(false)
+}
+
+/// decompresses a file created by compress_zstd_seekable, or any other file in the zstd seekable format.
+/// the checksums of the seek table aren't verified, zstd already checks the integrity of every frame.
+/// returns whether the decompression got interrupted
+pub fn decompress_zstd_seekable(
+    input: &Path,
+    output: &Path,
+    interrupt: &Receiver<()>,
+) -> io::Result<bool> {
+    let invalid = |message: &str| {
+        io::Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "{} isn't a seekable zstd file: {}",
+                input.display(),
+                message
+            ),
+        )
+    };
+    let read_u32 = |reader: &mut File| -> io::Result<u32> {
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf)?;
+        Ok(u32::from_le_bytes(buf))
+    };
+
+    let mut reader = File::open(input)?;
+    let length = reader.metadata()?.len();
+
+    if length < SEEK_TABLE_FOOTER_SIZE + 8 {
+        return Err(invalid("too short"));
+    }
+
+    reader.seek(SeekFrom::Start(length - SEEK_TABLE_FOOTER_SIZE))?;
+    let frames = read_u32(&mut reader)? as u64;
+    let mut descriptor = [0; 1];
+    reader.read_exact(&mut descriptor)?;
+
+    if read_u32(&mut reader)? != SEEKABLE_MAGIC {
+        return Err(invalid("the seek table is missing"));
+    }
+
+    let entry_size = if descriptor[0] & CHECKSUM_FLAG != 0 {
+        12
+    } else {
+        8
+    };
+    let table_size = frames * entry_size + SEEK_TABLE_FOOTER_SIZE;
+
+    if length < table_size + 8 {
+        return Err(invalid("the seek table is truncated"));
+    }
+
+    reader.seek(SeekFrom::Start(length - table_size - 8))?;
+
+    if read_u32(&mut reader)? != SKIPPABLE_MAGIC || read_u32(&mut reader)? as u64 != table_size {
+        return Err(invalid("the seek table is damaged"));
+    }
+
+    let mut seek_table: Vec<(u32, u32)> = Vec::with_capacity(frames as usize);
+
+    for _ in 0..frames {
+        let compressed = read_u32(&mut reader)?;
+        let decompressed = read_u32(&mut reader)?;
+
+        if entry_size == 12 {
+            read_u32(&mut reader)?;
+        }
+
+        seek_table.push((compressed, decompressed));
+    }
+
+    reader.seek(SeekFrom::Start(0))?;
+
+    let mut writer = BufWriter::new(File::create(output)?);
+    let mut decompressor = Decompressor::new()?;
+    let mut frame = vec![];
+
+    for (compressed, decompressed) in seek_table {
+        if !interrupt.is_empty() {
+            return Ok(true);
+        }
+
+        frame.resize(compressed as usize, 0);
+        reader.read_exact(&mut frame)?;
+
+        let data = decompressor.decompress(&frame, decompressed as usize)?;
+
+        if data.len() != decompressed as usize {
+            return Err(invalid("a frame doesn't match the seek table"));
+        }
+
+        writer.write_all(&data)?;
+    }
+
+    writer.flush()?;
+
+    Ok(false)
+}